@@ -0,0 +1,67 @@
+/*
+ * A minimal program that drives tuitty from C.
+ *
+ * Build the library and then compile against the generated header:
+ *   cargo build --release
+ *   cc examples/c/demo.c -Iinclude -Ltarget/release -ltuitty_rs -o demo
+ *   LD_LIBRARY_PATH=target/release ./demo
+ *
+ * Press any key to see it echoed back. Press Ctrl+G to quit.
+ */
+#include <stdio.h>
+#include <unistd.h>
+#include "tuitty.h"
+
+int main(void) {
+    TuittyDispatcher *dispatch = tuitty_dispatcher_init();
+    TuittyEventHandle *input = tuitty_dispatcher_listen(dispatch);
+
+    tuitty_new_screen(input);
+    tuitty_raw(input);
+    tuitty_hide_cursor(input);
+    tuitty_enable_mouse(input);
+
+    TuittyColor cyan = { TUITTY_COLOR_CYAN, 0, 0, 0, 0 };
+    TuittyColor reset = { TUITTY_COLOR_RESET, 0, 0, 0, 0 };
+    tuitty_set_styles(input, cyan, reset, tuitty_fx(TUITTY_FX_BOLD));
+    tuitty_set_content(input, "Hello from C! (Ctrl+G to quit)", 0, 0);
+    tuitty_reset_styles(input);
+    tuitty_render(input);
+
+    int16_t w = 0, h = 0;
+    tuitty_request_size(input, &w, &h);
+
+    char line[64];
+    TuittyEvent evt;
+    for (;;) {
+        if (tuitty_poll_async(input, &evt) != 1) {
+            usleep(16 * 1000);
+            continue;
+        }
+        if (evt.kind == TUITTY_EVENT_KEYBOARD) {
+            if (evt.key == TUITTY_KEY_CTRL && evt.ch == 'g') break;
+            snprintf(line, sizeof(line), "Key: (%u, %u) in %dx%d",
+                     evt.key, evt.ch, w, h);
+        } else if (evt.kind == TUITTY_EVENT_MOUSE) {
+            snprintf(line, sizeof(line), "Mouse: (%u, %u) at (%d, %d)",
+                     evt.mouse, evt.button, evt.col, evt.row);
+        } else {
+            continue;
+        }
+        tuitty_set_content(input, line, 0, 2);
+        tuitty_clear(input, TUITTY_CLEAR_NEW_LN);
+        tuitty_render(input);
+    }
+
+    char *contents = tuitty_request_dump(input, 0, TUITTY_FORMAT_PLAIN);
+    tuitty_string_free(contents);
+
+    tuitty_disable_mouse(input);
+    tuitty_show_cursor(input);
+    tuitty_cook(input);
+    tuitty_switch_to(input, 0);
+
+    tuitty_handle_free(input);
+    tuitty_dispatcher_free(dispatch);
+    return 0;
+}
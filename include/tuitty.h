@@ -0,0 +1,475 @@
+/* tuitty: C bindings for the Dispatcher and EventHandle. */
+
+#ifndef TUITTY_H
+#define TUITTY_H
+
+/* Generated with cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define TUITTY_EVENT_NONE 0
+
+#define TUITTY_EVENT_KEYBOARD 1
+
+#define TUITTY_EVENT_MOUSE 2
+
+#define TUITTY_EVENT_CURSOR_POS 3
+
+#define TUITTY_EVENT_RESPONSE 4
+
+#define TUITTY_EVENT_LOCKED 5
+
+#define TUITTY_EVENT_LOCK_TIMEOUT 6
+
+#define TUITTY_EVENT_ERROR 7
+
+#define TUITTY_EVENT_RESUMED 8
+
+#define TUITTY_EVENT_SIGNAL 9
+
+#define TUITTY_EVENT_REGION 10
+
+#define TUITTY_EVENT_ENTER 11
+
+#define TUITTY_EVENT_LEAVE 12
+
+#define TUITTY_EVENT_GESTURE 13
+
+#define TUITTY_EVENT_UNSUPPORTED 255
+
+#define TUITTY_KEY_NONE 0
+
+#define TUITTY_KEY_CHAR 1
+
+#define TUITTY_KEY_ALT 2
+
+#define TUITTY_KEY_CTRL 3
+
+#define TUITTY_KEY_F 4
+
+#define TUITTY_KEY_BACKSPACE 5
+
+#define TUITTY_KEY_ENTER 6
+
+#define TUITTY_KEY_LEFT 7
+
+#define TUITTY_KEY_RIGHT 8
+
+#define TUITTY_KEY_UP 9
+
+#define TUITTY_KEY_DOWN 10
+
+#define TUITTY_KEY_HOME 11
+
+#define TUITTY_KEY_END 12
+
+#define TUITTY_KEY_PAGE_UP 13
+
+#define TUITTY_KEY_PAGE_DOWN 14
+
+#define TUITTY_KEY_TAB 15
+
+#define TUITTY_KEY_BACK_TAB 16
+
+#define TUITTY_KEY_DELETE 17
+
+#define TUITTY_KEY_INSERT 18
+
+#define TUITTY_KEY_NULL 19
+
+#define TUITTY_KEY_ESC 20
+
+#define TUITTY_KEY_CTRL_UP 21
+
+#define TUITTY_KEY_CTRL_DOWN 22
+
+#define TUITTY_KEY_CTRL_RIGHT 23
+
+#define TUITTY_KEY_CTRL_LEFT 24
+
+#define TUITTY_KEY_SHIFT_UP 25
+
+#define TUITTY_KEY_SHIFT_DOWN 26
+
+#define TUITTY_KEY_SHIFT_RIGHT 27
+
+#define TUITTY_KEY_SHIFT_LEFT 28
+
+#define TUITTY_KEY_OTHER 29
+
+#define TUITTY_KEY_PRESS 0
+
+#define TUITTY_KEY_REPEAT 1
+
+#define TUITTY_KEY_RELEASE 2
+
+#define TUITTY_MOD_SHIFT 1
+
+#define TUITTY_MOD_ALT 2
+
+#define TUITTY_MOD_CTRL 4
+
+#define TUITTY_MOD_SUPER 8
+
+#define TUITTY_MOD_HYPER 16
+
+#define TUITTY_MOD_META 32
+
+#define TUITTY_MOD_CAPS_LOCK 64
+
+#define TUITTY_MOD_NUM_LOCK 128
+
+#define TUITTY_MOUSE_NONE 0
+
+#define TUITTY_MOUSE_PRESS 1
+
+#define TUITTY_MOUSE_RELEASE 2
+
+#define TUITTY_MOUSE_HOLD 3
+
+#define TUITTY_MOUSE_MOVE 4
+
+#define TUITTY_BUTTON_NONE 0
+
+#define TUITTY_BUTTON_LEFT 1
+
+#define TUITTY_BUTTON_RIGHT 2
+
+#define TUITTY_BUTTON_MIDDLE 3
+
+#define TUITTY_BUTTON_WHEEL_UP 4
+
+#define TUITTY_BUTTON_WHEEL_DOWN 5
+
+#define TUITTY_BUTTON_WHEEL_LEFT 6
+
+#define TUITTY_BUTTON_WHEEL_RIGHT 7
+
+#define TUITTY_BUTTON_EXTRA 8
+
+#define TUITTY_GESTURE_NONE 0
+
+#define TUITTY_GESTURE_DOUBLE_CLICK 1
+
+#define TUITTY_GESTURE_TRIPLE_CLICK 2
+
+#define TUITTY_GESTURE_DRAG_START 3
+
+#define TUITTY_GESTURE_DRAG_MOVE 4
+
+#define TUITTY_GESTURE_DRAG_END 5
+
+#define TUITTY_GESTURE_LONG_PRESS 6
+
+#define TUITTY_NAME_LEN 64
+
+#define TUITTY_DETAILS_LEN 256
+
+#define TUITTY_COLOR_RESET 0
+
+#define TUITTY_COLOR_BLACK 1
+
+#define TUITTY_COLOR_DARK_GREY 2
+
+#define TUITTY_COLOR_RED 3
+
+#define TUITTY_COLOR_DARK_RED 4
+
+#define TUITTY_COLOR_GREEN 5
+
+#define TUITTY_COLOR_DARK_GREEN 6
+
+#define TUITTY_COLOR_YELLOW 7
+
+#define TUITTY_COLOR_DARK_YELLOW 8
+
+#define TUITTY_COLOR_BLUE 9
+
+#define TUITTY_COLOR_DARK_BLUE 10
+
+#define TUITTY_COLOR_MAGENTA 11
+
+#define TUITTY_COLOR_DARK_MAGENTA 12
+
+#define TUITTY_COLOR_CYAN 13
+
+#define TUITTY_COLOR_DARK_CYAN 14
+
+#define TUITTY_COLOR_WHITE 15
+
+#define TUITTY_COLOR_GREY 16
+
+#define TUITTY_COLOR_ANSI 17
+
+#define TUITTY_COLOR_RGB 18
+
+#define TUITTY_CLEAR_ALL 0
+
+#define TUITTY_CLEAR_CURSOR_DN 1
+
+#define TUITTY_CLEAR_CURSOR_UP 2
+
+#define TUITTY_CLEAR_CURRENT_LN 3
+
+#define TUITTY_CLEAR_NEW_LN 4
+
+#define TUITTY_FX_RESET 0
+
+#define TUITTY_FX_BOLD 1
+
+#define TUITTY_FX_DIM 2
+
+#define TUITTY_FX_UNDERLINE 3
+
+#define TUITTY_FX_REVERSE 4
+
+#define TUITTY_FX_HIDE 5
+
+#define TUITTY_MOUSE_LEVEL_OFF 0
+
+#define TUITTY_MOUSE_LEVEL_BUTTON 1
+
+#define TUITTY_MOUSE_LEVEL_DRAG 2
+
+#define TUITTY_MOUSE_LEVEL_MOTION 3
+
+#define TUITTY_MOUSE_ENCODING_LEGACY 0
+
+#define TUITTY_MOUSE_ENCODING_SGR 1
+
+#define TUITTY_MOUSE_ENCODING_SGR_PIXELS 2
+
+#define TUITTY_KITTY_DISAMBIGUATE 1
+
+#define TUITTY_KITTY_EVENT_TYPES 2
+
+#define TUITTY_KITTY_ALTERNATE_KEYS 4
+
+#define TUITTY_KITTY_ALL_KEYS 8
+
+#define TUITTY_KITTY_TEXT 16
+
+#define TUITTY_ERROR_DISCONNECTED 0
+
+#define TUITTY_ERROR_INTERRUPTED 1
+
+#define TUITTY_ERROR_OTHER 2
+
+#define TUITTY_POLICY_IGNORE 0
+
+#define TUITTY_POLICY_RETRY 1
+
+#define TUITTY_POLICY_SHUTDOWN 2
+
+#define TUITTY_SIGNAL_INTERRUPT 0
+
+#define TUITTY_SIGNAL_TERMINATE 1
+
+#define TUITTY_SIGNAL_HANGUP 2
+
+#define TUITTY_FORMAT_PLAIN 0
+
+#define TUITTY_FORMAT_ANSI 1
+
+#define TUITTY_FORMAT_HTML 2
+
+typedef struct TuittyDispatcher TuittyDispatcher;
+
+typedef struct TuittyEventHandle TuittyEventHandle;
+
+typedef struct TuittySnapshot TuittySnapshot;
+
+typedef struct TuittyEvent {
+  uint8_t kind;
+  uint8_t key;
+  uint32_t ch;
+  uint8_t key_kind;
+  uint8_t modifiers;
+  uint8_t mouse;
+  uint8_t button;
+  uint8_t gesture;
+  uint8_t error_class;
+  uint8_t is_fatal;
+  int16_t col;
+  int16_t row;
+  char name[TUITTY_NAME_LEN];
+  char details[TUITTY_DETAILS_LEN];
+} TuittyEvent;
+
+typedef struct TuittyColor {
+  uint8_t kind;
+  uint8_t value;
+  uint8_t r;
+  uint8_t g;
+  uint8_t b;
+} TuittyColor;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+uint32_t tuitty_fx(uint8_t kind);
+
+struct TuittyDispatcher *tuitty_dispatcher_init(void);
+
+void tuitty_dispatcher_free(struct TuittyDispatcher *dispatch);
+
+struct TuittyEventHandle *tuitty_dispatcher_listen(struct TuittyDispatcher *dispatch);
+
+struct TuittyEventHandle *tuitty_dispatcher_spawn(const struct TuittyDispatcher *dispatch);
+
+void tuitty_dispatcher_restore_on_panic(const struct TuittyDispatcher *dispatch);
+
+void tuitty_dispatcher_restore_on_exit(const struct TuittyDispatcher *dispatch);
+
+int tuitty_dispatcher_grace(const struct TuittyDispatcher *dispatch, uint64_t ms);
+
+int tuitty_dispatcher_on_error(const struct TuittyDispatcher *dispatch,
+                               uint8_t class_,
+                               uint8_t policy,
+                               uint8_t retries);
+
+void tuitty_handle_free(struct TuittyEventHandle *handle);
+
+int tuitty_poll_async(const struct TuittyEventHandle *handle, struct TuittyEvent *event);
+
+int tuitty_poll_latest_async(const struct TuittyEventHandle *handle, struct TuittyEvent *event);
+
+int tuitty_poll_sync(const struct TuittyEventHandle *handle, struct TuittyEvent *event);
+
+int tuitty_fd(const struct TuittyEventHandle *handle);
+
+int tuitty_suspend(const struct TuittyEventHandle *handle);
+
+int tuitty_transmit(const struct TuittyEventHandle *handle);
+
+int tuitty_stop(const struct TuittyEventHandle *handle);
+
+int tuitty_lock(const struct TuittyEventHandle *handle);
+
+int tuitty_lock_timeout(const struct TuittyEventHandle *handle, uint64_t ms);
+
+int tuitty_unlock(const struct TuittyEventHandle *handle);
+
+int tuitty_focus(const struct TuittyEventHandle *handle, bool share_mouse);
+
+int tuitty_blur(const struct TuittyEventHandle *handle);
+
+int tuitty_goto(const struct TuittyEventHandle *handle, int16_t col, int16_t row);
+
+int tuitty_up(const struct TuittyEventHandle *handle, int16_t n);
+
+int tuitty_down(const struct TuittyEventHandle *handle, int16_t n);
+
+int tuitty_left(const struct TuittyEventHandle *handle, int16_t n);
+
+int tuitty_right(const struct TuittyEventHandle *handle, int16_t n);
+
+int tuitty_clear(const struct TuittyEventHandle *handle, uint8_t clr);
+
+int tuitty_prints(const struct TuittyEventHandle *handle, const char *s);
+
+int tuitty_set_content(const struct TuittyEventHandle *handle,
+                       const char *s,
+                       int16_t col,
+                       int16_t row);
+
+int tuitty_flush(const struct TuittyEventHandle *handle);
+
+int tuitty_render(const struct TuittyEventHandle *handle);
+
+int tuitty_resize(const struct TuittyEventHandle *handle, int16_t w, int16_t h);
+
+int tuitty_set_fx(const struct TuittyEventHandle *handle, uint32_t fx);
+
+int tuitty_set_fg(const struct TuittyEventHandle *handle, struct TuittyColor c);
+
+int tuitty_set_bg(const struct TuittyEventHandle *handle, struct TuittyColor c);
+
+int tuitty_set_styles(const struct TuittyEventHandle *handle,
+                      struct TuittyColor fg,
+                      struct TuittyColor bg,
+                      uint32_t fx);
+
+int tuitty_reset_styles(const struct TuittyEventHandle *handle);
+
+int tuitty_hide_cursor(const struct TuittyEventHandle *handle);
+
+int tuitty_show_cursor(const struct TuittyEventHandle *handle);
+
+int tuitty_enable_mouse(const struct TuittyEventHandle *handle);
+
+int tuitty_disable_mouse(const struct TuittyEventHandle *handle);
+
+int tuitty_set_mouse(const struct TuittyEventHandle *handle, uint8_t level, uint8_t encoding);
+
+int tuitty_raw(const struct TuittyEventHandle *handle);
+
+int tuitty_cook(const struct TuittyEventHandle *handle);
+
+int tuitty_new_screen(const struct TuittyEventHandle *handle);
+
+int tuitty_switch_to(const struct TuittyEventHandle *handle, size_t id);
+
+int tuitty_close_screen(const struct TuittyEventHandle *handle, size_t id);
+
+int tuitty_push_screen(const struct TuittyEventHandle *handle, size_t id);
+
+int tuitty_pop_screen(const struct TuittyEventHandle *handle);
+
+int tuitty_resized(const struct TuittyEventHandle *handle);
+
+int tuitty_sync_size(const struct TuittyEventHandle *handle, int16_t w, int16_t h);
+
+int tuitty_sync_marker(const struct TuittyEventHandle *handle, int16_t col, int16_t row);
+
+int tuitty_jump(const struct TuittyEventHandle *handle);
+
+int tuitty_sync_tab_size(const struct TuittyEventHandle *handle, size_t n);
+
+int tuitty_sync_bidi(const struct TuittyEventHandle *handle, bool state);
+
+int tuitty_restore(const struct TuittyEventHandle *handle, const struct TuittySnapshot *snapshot);
+
+int tuitty_start_cast(const struct TuittyEventHandle *handle, const char *path);
+
+int tuitty_stop_cast(const struct TuittyEventHandle *handle);
+
+int tuitty_push_keyboard(const struct TuittyEventHandle *handle, uint8_t flags);
+
+int tuitty_pop_keyboard(const struct TuittyEventHandle *handle);
+
+int tuitty_suspend_process(const struct TuittyEventHandle *handle);
+
+int tuitty_request_size(const struct TuittyEventHandle *handle, int16_t *w, int16_t *h);
+
+int tuitty_request_coord(const struct TuittyEventHandle *handle, int16_t *col, int16_t *row);
+
+int tuitty_request_pos(const struct TuittyEventHandle *handle, int16_t *col, int16_t *row);
+
+int tuitty_request_screen(const struct TuittyEventHandle *handle, size_t *id);
+
+int tuitty_request_screens(const struct TuittyEventHandle *handle, size_t *ids, size_t len);
+
+char *tuitty_request_getch(const struct TuittyEventHandle *handle);
+
+char *tuitty_request_getln(const struct TuittyEventHandle *handle);
+
+char *tuitty_request_dump(const struct TuittyEventHandle *handle, size_t screen, uint8_t format);
+
+struct TuittySnapshot *tuitty_request_snapshot(const struct TuittyEventHandle *handle,
+                                               size_t screen);
+
+void tuitty_snapshot_free(struct TuittySnapshot *snapshot);
+
+void tuitty_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* TUITTY_H */
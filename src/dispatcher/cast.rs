@@ -0,0 +1,208 @@
+// This module provides the asciicast v2 recorder that tees the terminal
+// output of the signal thread into a `.cast` file (see `Action::StartCast`).
+//
+// (imdaveho) NOTE: Term writes directly to the terminal, so the output is
+// reconstructed from each Action and the Store once it has been handled. The
+// bytes are the escape sequences that reproduce what is on screen rather than
+// a verbatim copy of what Term wrote (eg. a render is a full repaint). Cursor
+// movements are recorded as the sequences that Term writes for them, and a
+// recording that starts mid-session opens with a repaint of the screen.
+// Output that bypasses the Dispatcher (eg. from a child process) is missed.
+use std::{
+    fs::File, path::Path, time::{ Instant, SystemTime, UNIX_EPOCH },
+    io::{ BufWriter, Result, Write },
+};
+use crate::tuitty_core::common::enums::Clear;
+use crate::store::{ Store, ansi, bidi };
+use super::message::{ Action::{ self, * }, Format };
+
+
+pub struct Cast {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+// The output of an Action that is captured before it is handled, and then
+// written out with the state of the Store after it has been handled.
+pub enum Output {
+    Nothing,
+    Cursor,
+    Print(String),
+    Sequence(String),
+    Repaint,
+    Screen(usize),
+    Resize,
+}
+
+impl Output {
+    pub fn of(action: &Action, store: &Store) -> Output {
+        match action {
+            Goto(c, r) => {
+                Output::Sequence(format!("\x1B[{};{}H", r + 1, c + 1))
+            },
+            Up(n) => Output::Sequence(format!("\x1B[{}A", n)),
+            Down(n) => Output::Sequence(format!("\x1B[{}B", n)),
+            Right(n) => Output::Sequence(format!("\x1B[{}C", n)),
+            Left(n) => Output::Sequence(format!("\x1B[{}D", n)),
+            // The marker is only known to the Store.
+            Jump => Output::Cursor,
+            Clear(c) => Output::Sequence(match c {
+                Clear::All => "\x1B[2J",
+                Clear::CursorDn => "\x1B[J",
+                Clear::CursorUp => "\x1B[1J",
+                Clear::CurrentLn => "\x1B[2K",
+                Clear::NewLn => "\x1B[K",
+            }.to_string()),
+            Prints(s) => Output::Print(s.to_string()),
+            SetFx(fx) => Output::Sequence(ansi::effects(*fx)),
+            SetFg(c) => Output::Sequence(ansi::color(*c, false)),
+            SetBg(c) => Output::Sequence(ansi::color(*c, true)),
+            SetStyles(fg, bg, fx) => {
+                Output::Sequence(ansi::sgr((*fg, *bg, *fx)))
+            },
+            ResetStyles => Output::Sequence("\x1B[0m".to_string()),
+            HideCursor => Output::Sequence("\x1B[?25l".to_string()),
+            ShowCursor => Output::Sequence("\x1B[?25h".to_string()),
+            Render | Restore(_) => Output::Repaint,
+            NewScreen | SwitchTo(_) | CloseScreen(_) | PushScreen(_)
+                | PopScreen => Output::Screen(store.id()),
+            Resize(_, _) | Resized | SyncSize(_, _) => Output::Resize,
+            _ => Output::Nothing,
+        }
+    }
+}
+
+fn json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => escaped
+                .push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn goto(store: &Store) -> String {
+    let (col, row) = store.coord();
+    format!("\x1B[{};{}H", row + 1, col + 1)
+}
+
+fn repaint(store: &Store) -> String {
+    let contents = store.dump(store.id(), Format::Ansi).unwrap_or_default();
+    format!("\x1B[H{}{}", contents.replace('\n', "\r\n"), goto(store))
+}
+
+impl Cast {
+    pub fn create(path: &Path, store: &Store) -> Result<Cast> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (w, h) = store.size();
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs()).unwrap_or(0);
+        writeln!(file,
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \
+             \"timestamp\": {}}}", w, h, timestamp)?;
+        file.flush()?;
+        let mut cast = Cast { file, start: Instant::now() };
+        // Start from what is already on screen.
+        let mut s = String::new();
+        if store.id() != 0 { s.push_str("\x1B[?1049h") }
+        s.push_str("\x1B[2J");
+        s.push_str(&repaint(store));
+        cast.event("o", &s);
+        Ok(cast)
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        if data.is_empty() { return }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let _ = writeln!(self.file, "[{:.6}, \"{}\", {}]",
+            elapsed, code, json(data)).and_then(|_| self.file.flush());
+    }
+
+    pub fn write(&mut self, output: Output, store: &Store) {
+        match output {
+            Output::Nothing => (),
+            Output::Cursor => self.event("o", &goto(store)),
+            Output::Print(s) => {
+                // In bidi mode, the terminal receives the visual order.
+                if store.is_bidi() { self.event("o", &bidi::reorder(&s)) }
+                else { self.event("o", &s) }
+            },
+            Output::Sequence(s) => self.event("o", &s),
+            Output::Repaint => self.event("o", &repaint(store)),
+            Output::Screen(previous) => {
+                let current = store.id();
+                if current == previous { return }
+                if current == 0 {
+                    self.event("o", "\x1B[?1049l");
+                    return
+                }
+                let mut s = String::new();
+                if previous == 0 { s.push_str("\x1B[?1049h") }
+                s.push_str("\x1B[2J");
+                s.push_str(&repaint(store));
+                self.event("o", &s);
+            },
+            Output::Resize => {
+                let (w, h) = store.size();
+                self.event("r", &format!("{}x{}", w, h));
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cast_json() {
+        assert_eq!(json("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json("\x1B[0m\r\n"), "\"\\u001b[0m\\r\\n\"");
+        assert_eq!(json("你好"), "\"你好\"");
+    }
+
+    #[test]
+    fn test_cast_events() {
+        let path = std::env::temp_dir()
+            .join(format!("tuitty-cast-{}.cast", std::process::id()));
+        let mut store = Store::new(3, 2);
+        store.sync_content("a");
+        let mut cast = Cast::create(&path, &store).unwrap();
+        let output = Output::of(&Prints("b".into()), &store);
+        store.sync_content("b");
+        cast.write(output, &store);
+        let output = Output::of(&Goto(0, 1), &store);
+        store.sync_goto(0, 1);
+        cast.write(output, &store);
+        cast.write(Output::of(&Render, &store), &store);
+        cast.write(Output::of(&Resized, &store), &store);
+        cast.write(Output::of(&Flush, &store), &store);
+        drop(cast);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with(
+            "{\"version\": 2, \"width\": 3, \"height\": 2,"));
+        // Started mid-session:
+        assert!(lines[1].ends_with(
+            ", \"o\", \"\\u001b[2J\\u001b[Ha  \\r\\n   \\u001b[1;2H\"]"));
+        assert!(lines[2].ends_with(", \"o\", \"b\"]"));
+        assert!(lines[3].ends_with(", \"o\", \"\\u001b[2;1H\"]"));
+        assert!(lines[4].ends_with(
+            ", \"o\", \"\\u001b[Hab \\r\\n   \\u001b[2;1H\"]"));
+        assert!(lines[5].ends_with(", \"r\", \"3x2\"]"));
+    }
+}
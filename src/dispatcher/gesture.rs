@@ -0,0 +1,270 @@
+// This module synthesizes gestures from the mouse events that the terminal
+// reports (see `Dispatcher::gestures`).
+//
+// Each mouse event is timestamped as it is fed to the Tracker, and the
+// gestures it completes are returned to be emitted right after it:
+//
+//   Press, Release, Press            DoubleClick (with the second Press)
+//   Press, Release, Press, ..        TripleClick (with the third Press)
+//   Press, Drag, Drag, .., Release   DragStart (with the Press), DragMove
+//                                    (with each Drag) and DragEnd
+//   Press (held in place)            LongPress (with the Press)
+//
+// (imdaveho) NOTE: A button held in place does not send anything, so the
+// signal thread also ticks the Tracker to send long presses on time. A
+// replay feeds the Tracker on the clock of the recording instead, so the
+// ticks are skipped and long presses are sent as the next event is fed.
+use std::{
+    sync::{ Mutex, atomic::{ AtomicBool, Ordering } },
+    time::Instant,
+};
+use super::message::{ Msg, Mouse, MouseKind, Button, Gesture, Thresholds };
+use crate::tuitty_core::common::enums::InputEvent;
+
+
+struct Held {
+    press: Mouse,
+    at: Instant,
+    is_drag: bool,
+    is_long: bool,
+}
+
+struct Click {
+    press: Mouse,
+    at: Instant,
+    count: u8,
+}
+
+#[derive(Default)]
+struct State {
+    thresholds: Option<Thresholds>,
+    held: Option<Held>,
+    click: Option<Click>,
+}
+
+pub struct Tracker {
+    state: Mutex<State>,
+    // Whether a replay feeds the Tracker (see `Dispatcher::replay`).
+    is_replay: AtomicBool,
+}
+
+fn distance(a: &Mouse, b: &Mouse) -> i16 {
+    (a.col - b.col).abs().max((a.row - b.row).abs())
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker {
+            state: Mutex::new(State::default()),
+            is_replay: AtomicBool::new(false),
+        }
+    }
+
+    pub fn sync_replay(&self, state: bool) {
+        self.is_replay.store(state, Ordering::SeqCst)
+    }
+
+    pub fn set(&self, thresholds: Option<Thresholds>) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = State { thresholds, ..State::default() }
+        }
+    }
+
+    pub fn feed(&self, msg: &Msg, at: Instant) -> Vec<Msg> {
+        let mut gestures = Vec::new();
+        let mut mouse = match msg {
+            Msg::Received(InputEvent::Mouse(mv)) =>
+                match Mouse::from_legacy(mv) {
+                    Some(m) => m,
+                    None => return gestures,
+                },
+            Msg::Mouse(mouse) => mouse.clone(),
+            _ => return gestures,
+        };
+        let mut guard = match self.state.lock() {
+            Ok(s) => s,
+            Err(_) => return gestures,
+        };
+        let state = &mut *guard;
+        let thresholds = match state.thresholds {
+            Some(t) => t,
+            None => return gestures,
+        };
+        // A long press that was due before this event.
+        long_press(state, &thresholds, at, &mut gestures);
+
+        match mouse.kind {
+            MouseKind::Press => {
+                // Wheel events are neither clicks nor drags.
+                if let Some(Button::WheelUp) | Some(Button::WheelDown)
+                    | Some(Button::WheelLeft) | Some(Button::WheelRight)
+                    | None = mouse.button { return gestures }
+                let is_repeat = |c: &Click| c.press.button == mouse.button
+                    && at.saturating_duration_since(c.at)
+                        <= thresholds.click_interval
+                    && distance(&c.press, &mouse) <= thresholds.click_distance;
+                // (imdaveho) NOTE: A fourth click starts over as a single one.
+                let count = match state.click.as_ref() {
+                    Some(c) if is_repeat(c) => c.count % 3 + 1,
+                    _ => 1,
+                };
+                match count {
+                    2 => gestures.push(Msg::Gesture(
+                        Gesture::DoubleClick, mouse.clone())),
+                    3 => gestures.push(Msg::Gesture(
+                        Gesture::TripleClick, mouse.clone())),
+                    _ => (),
+                }
+                state.click = Some(Click { press: mouse.clone(), at, count });
+                state.held = Some(Held {
+                    press: mouse, at, is_drag: false, is_long: false,
+                });
+            },
+            MouseKind::Drag => {
+                let held = match state.held.as_mut() {
+                    Some(h) => h,
+                    None => return gestures,
+                };
+                // Legacy Drags do not report the button.
+                if mouse.button.is_none() { mouse.button = held.press.button }
+                if !held.is_drag {
+                    if distance(&held.press, &mouse)
+                        < thresholds.drag_distance { return gestures }
+                    held.is_drag = true;
+                    gestures.push(Msg::Gesture(
+                        Gesture::DragStart, held.press.clone()));
+                    // A drag is not a click.
+                    state.click = None;
+                }
+                gestures.push(Msg::Gesture(Gesture::DragMove, mouse));
+            },
+            MouseKind::Release => {
+                if let Some(held) = state.held.take() {
+                    if held.is_drag {
+                        if mouse.button.is_none() {
+                            mouse.button = held.press.button
+                        }
+                        gestures.push(Msg::Gesture(Gesture::DragEnd, mouse));
+                    }
+                }
+            },
+            MouseKind::Move => (),
+        }
+        gestures
+    }
+
+    pub fn tick(&self, now: Instant) -> Vec<Msg> {
+        let mut gestures = Vec::new();
+        // `now` is not on the clock of a replay.
+        if self.is_replay.load(Ordering::SeqCst) { return gestures }
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(thresholds) = state.thresholds {
+                long_press(&mut state, &thresholds, now, &mut gestures);
+            }
+        }
+        gestures
+    }
+}
+
+fn long_press(
+    state: &mut State, thresholds: &Thresholds, now: Instant,
+    gestures: &mut Vec<Msg>,
+) {
+    let is_due = match state.held.as_mut() {
+        Some(held) if !held.is_drag && !held.is_long
+            && now.saturating_duration_since(held.at)
+                >= thresholds.long_press => {
+            held.is_long = true;
+            gestures.push(Msg::Gesture(
+                Gesture::LongPress, held.press.clone()));
+            true
+        },
+        _ => false,
+    };
+    // A long press is not a click.
+    if is_due { state.click = None }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn kinds(msgs: Vec<Msg>) -> Vec<Gesture> {
+        msgs.into_iter().filter_map(|msg| match msg {
+            Msg::Gesture(gesture, _) => Some(gesture),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_gestures() {
+        let mouse = |kind, col, row| Msg::Mouse(Mouse {
+            kind, button: Some(Button::Left), col, row, modifiers: 0,
+        });
+        let (press, release) = (mouse(MouseKind::Press, 2, 2),
+            mouse(MouseKind::Release, 2, 2));
+        let ms = |n| Duration::from_millis(n);
+        let start = Instant::now();
+        let tracker = Tracker::new();
+        // Nothing is synthesized until thresholds are set:
+        assert!(tracker.feed(&press, start).is_empty());
+
+        tracker.set(Some(Thresholds::default()));
+        assert!(kinds(tracker.feed(&press, start)).is_empty());
+        assert!(kinds(tracker.feed(&release, start + ms(50))).is_empty());
+        assert_eq!(kinds(tracker.feed(&press, start + ms(200))),
+            [Gesture::DoubleClick]);
+        tracker.feed(&release, start + ms(250));
+        assert_eq!(kinds(tracker.feed(&press, start + ms(400))),
+            [Gesture::TripleClick]);
+        tracker.feed(&release, start + ms(450));
+        // Too slow (or too far) for another click:
+        assert!(kinds(tracker.feed(&press, start + ms(2000))).is_empty());
+        tracker.feed(&release, start + ms(2050));
+        assert!(kinds(tracker.feed(
+            &mouse(MouseKind::Press, 9, 9), start + ms(2100))).is_empty());
+        tracker.feed(&release, start + ms(2150));
+
+        // Drags:
+        let start = start + ms(5000);
+        tracker.feed(&press, start);
+        let drag = Msg::Received(InputEvent::Mouse(
+            crate::tuitty_core::common::enums::MouseEvent::Hold(4, 2)));
+        match tracker.feed(&drag, start + ms(10)).as_slice() {
+            [Msg::Gesture(Gesture::DragStart, from),
+             Msg::Gesture(Gesture::DragMove, to)] => {
+                assert_eq!((from.col, to.col), (2, 4));
+                assert_eq!(to.button, Some(Button::Left));
+            },
+            _ => panic!("Expected the drag to start"),
+        }
+        assert_eq!(kinds(tracker.feed(&drag, start + ms(20))),
+            [Gesture::DragMove]);
+        assert_eq!(kinds(tracker.feed(&release, start + ms(30))),
+            [Gesture::DragEnd]);
+        // A drag is not a click:
+        assert!(kinds(tracker.feed(&press, start + ms(40))).is_empty());
+        tracker.feed(&release, start + ms(50));
+
+        // Long presses:
+        let start = start + ms(5000);
+        tracker.feed(&press, start);
+        assert!(tracker.tick(start + ms(100)).is_empty());
+        assert_eq!(kinds(tracker.tick(start + ms(800))), [Gesture::LongPress]);
+        assert!(tracker.tick(start + ms(900)).is_empty());
+        tracker.feed(&release, start + ms(1000));
+        // Or when the next event is fed (eg. in replays):
+        tracker.sync_replay(true);
+        tracker.feed(&press, start + ms(2000));
+        assert!(tracker.tick(start + ms(2800)).is_empty());
+        assert_eq!(kinds(tracker.feed(&release, start + ms(3000))),
+            [Gesture::LongPress]);
+        tracker.sync_replay(false);
+
+        tracker.set(None);
+        tracker.feed(&press, start + ms(4000));
+        assert!(tracker.feed(&press, start + ms(4050)).is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+// This module parses the input sequences that the `tuitty_core` parser does
+// not know about.
+//
+// The key events of the kitty keyboard protocol are sent once enhancement
+// flags have been pushed (see `Action::PushKeyboard`):
+//
+//   CSI code[:shifted[:base]] ; modifiers[:event] ; text[:text...] u
+//   CSI number ; modifiers:event ~       eg. `CSI 3;1:3~` (Delete released)
+//   CSI 1 ; modifiers:event <A-D|H|F|P|Q|S>
+//
+// (imdaveho) NOTE: The `~` and letter forms are only parsed here if they
+// carry an event type. Otherwise they are the same as the legacy sequences
+// and are left to the `tuitty_core` parser, so legacy terminals (that never
+// send CSI u) fall back to the usual InputEvents.
+//
+// The mouse events of the extended tracking modes are sent once they have
+// been set (see `Action::SetMouse`), in either encoding:
+//
+//   CSI < button ; col ; row <M|m>       SGR (m is a release)
+//   CSI M <button+32> <col+33> <row+33>  Legacy
+//
+// The button is a bitset: 0-2 are left, middle and right (3 is a release in
+// the legacy encoding or no button), +4 shift, +8 alt, +16 ctrl, +32 motion,
+// +64 wheel (up, down, left, right) and +128 extra buttons.
+use super::message::{ Key, KeyCode, KeyKind, Mouse, MouseKind, Button };
+
+
+pub fn csi_len(bytes: &[u8]) -> Option<usize> {
+    // The length of the CSI sequence at the start of `bytes` (if any), so
+    // that a read with several sequences (eg. a press and its release) can
+    // be split up.
+    if bytes.len() < 3 || bytes[0] != 0x1B || bytes[1] != b'[' { return None }
+    // The legacy mouse encoding is followed by 3 raw bytes.
+    if bytes[2] == b'M' { return if bytes.len() >= 6 { Some(6) } else { None } }
+    bytes[2..].iter().position(|b| (0x40..=0x7E).contains(b)).map(|i| i + 3)
+}
+
+fn number(field: Option<&str>, default: u32) -> Option<u32> {
+    match field {
+        None | Some("") => Some(default),
+        Some(s) => s.parse().ok(),
+    }
+}
+
+pub fn parse_key(bytes: &[u8]) -> Option<Key> {
+    if bytes.len() < 3 || bytes[0] != 0x1B || bytes[1] != b'[' { return None }
+    let end = bytes[2..].iter()
+        .position(|b| !(b.is_ascii_digit() || *b == b';' || *b == b':'))? + 2;
+    let params = std::str::from_utf8(&bytes[2..end]).ok()?;
+    let last = bytes[end];
+
+    let mut fields = params.split(';');
+    let mut key = fields.next().unwrap_or("").split(':');
+    let mut mods = fields.next().unwrap_or("").split(':');
+    let text = fields.next();
+    let (n, modifiers) = (number(key.next(), 1)?, number(mods.next(), 1)?);
+    let event = mods.next();
+    if last != b'u' && event.is_none() { return None }
+
+    let code = match last {
+        b'u' => match n {
+            27 => KeyCode::Esc,
+            13 => KeyCode::Enter,
+            9 => KeyCode::Tab,
+            127 => KeyCode::Backspace,
+            57344..=63743 => KeyCode::Other(n),
+            n => KeyCode::Char(std::char::from_u32(n)?),
+        },
+        b'~' => match n {
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            7 => KeyCode::Home,
+            8 => KeyCode::End,
+            11..=15 => KeyCode::F((n - 10) as u8),
+            17..=21 => KeyCode::F((n - 11) as u8),
+            23 | 24 => KeyCode::F((n - 12) as u8),
+            _ => return None,
+        },
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'S' => KeyCode::F(4),
+        _ => return None,
+    };
+    let kind = match number(event, 1)? {
+        1 => KeyKind::Press,
+        2 => KeyKind::Repeat,
+        3 => KeyKind::Release,
+        _ => return None,
+    };
+    let text = match text {
+        Some(t) => t.split(':')
+            .map(|c| c.parse().ok().and_then(std::char::from_u32))
+            .collect::<Option<String>>()?,
+        None => String::new(),
+    };
+    // Modifiers are sent as 1 + the bitset.
+    let modifiers = modifiers.checked_sub(1)? as u8;
+    Some(Key { code, modifiers, kind, text })
+}
+
+fn mouse(cb: u32, col: i16, row: i16, is_release: bool) -> Option<Mouse> {
+    let mut modifiers = 0;
+    if cb & 4 != 0 { modifiers |= Key::SHIFT }
+    if cb & 8 != 0 { modifiers |= Key::ALT }
+    if cb & 16 != 0 { modifiers |= Key::CTRL }
+    let is_motion = cb & 32 != 0;
+    let button = match (cb & 0b1100_0000, cb & 0b11) {
+        (0, 0) => Some(Button::Left),
+        (0, 1) => Some(Button::Middle),
+        (0, 2) => Some(Button::Right),
+        (0, _) => None,
+        (64, 0) => Some(Button::WheelUp),
+        (64, 1) => Some(Button::WheelDown),
+        (64, 2) => Some(Button::WheelLeft),
+        (64, _) => Some(Button::WheelRight),
+        (128, n) => Some(Button::Extra(8 + n as u8)),
+        _ => return None,
+    };
+    let kind = match (is_release, is_motion, button) {
+        (true, _, _) | (false, false, None) => MouseKind::Release,
+        (false, true, None) => MouseKind::Move,
+        (false, true, Some(_)) => MouseKind::Drag,
+        (false, false, Some(_)) => MouseKind::Press,
+    };
+    Some(Mouse { kind, button, col, row, modifiers })
+}
+
+pub fn parse_mouse(bytes: &[u8]) -> Option<Mouse> {
+    if bytes.len() < 6 || bytes[0] != 0x1B || bytes[1] != b'[' { return None }
+    match bytes[2] {
+        b'<' => {
+            let end = bytes[3..].iter()
+                .position(|b| *b == b'M' || *b == b'm')? + 3;
+            let params = std::str::from_utf8(&bytes[3..end]).ok()?;
+            let mut fields = params.split(';').map(str::parse::<u32>);
+            let cb = fields.next()?.ok()?;
+            // Coordinates are 1-based.
+            let col = fields.next()?.ok()?.checked_sub(1)?;
+            let row = fields.next()?.ok()?.checked_sub(1)?;
+            if fields.next().is_some() { return None }
+            let clamp = |n: u32| n.min(i16::MAX as u32) as i16;
+            mouse(cb, clamp(col), clamp(row), bytes[end] == b'm')
+        },
+        b'M' => {
+            let cb = (bytes[3] as u32).checked_sub(32)?;
+            let col = (bytes[4] as i16).checked_sub(33)?;
+            let row = (bytes[5] as i16).checked_sub(33)?;
+            if col < 0 || row < 0 { return None }
+            mouse(cb, col, row, false)
+        },
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuitty_core::common::enums::KeyEvent;
+
+    fn key(code: KeyCode, modifiers: u8, kind: KeyKind, text: &str) -> Key {
+        Key { code, modifiers, kind, text: text.to_string() }
+    }
+
+    #[test]
+    fn test_parse_mouse() {
+        let mouse = |kind, button, col, row, modifiers| Some(Mouse {
+            kind, button, col, row, modifiers,
+        });
+        assert_eq!(parse_mouse(b"\x1B[<0;300;40M"),
+            mouse(MouseKind::Press, Some(Button::Left), 299, 39, 0));
+        assert_eq!(parse_mouse(b"\x1B[<2;1;1m"),
+            mouse(MouseKind::Release, Some(Button::Right), 0, 0, 0));
+        assert_eq!(parse_mouse(b"\x1B[<32;5;6M"),
+            mouse(MouseKind::Drag, Some(Button::Left), 4, 5, 0));
+        assert_eq!(parse_mouse(b"\x1B[<35;5;6M"),
+            mouse(MouseKind::Move, None, 4, 5, 0));
+        // Ctrl+Shift wheel left:
+        assert_eq!(parse_mouse(b"\x1B[<86;2;3M"),
+            mouse(MouseKind::Press, Some(Button::WheelLeft), 1, 2,
+                Key::CTRL | Key::SHIFT));
+        assert_eq!(parse_mouse(b"\x1B[<129;2;3M"),
+            mouse(MouseKind::Press, Some(Button::Extra(9)), 1, 2, 0));
+        // Legacy:
+        assert_eq!(parse_mouse(b"\x1B[M\x28\x21\x22"),
+            mouse(MouseKind::Press, Some(Button::Left), 0, 1, Key::ALT));
+        assert_eq!(parse_mouse(b"\x1B[M\x23\x21\x21"),
+            mouse(MouseKind::Release, None, 0, 0, 0));
+        assert_eq!(parse_mouse(b"\x1B[A"), None);
+        assert_eq!(parse_mouse(b"\x1B[<0;0;1M"), None);
+    }
+
+    #[test]
+    fn test_parse_csi_u() {
+        assert_eq!(parse_key(b"\x1B[97u"),
+            Some(key(KeyCode::Char('a'), 0, KeyKind::Press, "")));
+        // Ctrl+Shift+a, repeated:
+        assert_eq!(parse_key(b"\x1B[97;6:2u"),
+            Some(key(KeyCode::Char('a'), 5, KeyKind::Repeat, "")));
+        // Shift+a with alternate keys and text:
+        assert_eq!(parse_key(b"\x1B[97:65;2;65u\0\0\0"),
+            Some(key(KeyCode::Char('a'), 1, KeyKind::Press, "A")));
+        // Super+Enter released:
+        assert_eq!(parse_key(b"\x1B[13;9:3u"),
+            Some(key(KeyCode::Enter, 8, KeyKind::Release, "")));
+        assert_eq!(parse_key(b"\x1B[57399u"),
+            Some(key(KeyCode::Other(57399), 0, KeyKind::Press, "")));
+        assert_eq!(parse_key(b"\x1B[3;1:3~"),
+            Some(key(KeyCode::Delete, 0, KeyKind::Release, "")));
+        assert_eq!(parse_key(b"\x1B[1;5:1A"),
+            Some(key(KeyCode::Up, 4, KeyKind::Press, "")));
+    }
+
+    #[test]
+    fn test_csi_len() {
+        // A press and release in the same read:
+        let bytes = b"\x1B[97u\x1B[97;1:3u";
+        let len = csi_len(bytes).unwrap();
+        assert_eq!(len, 5);
+        assert_eq!(parse_key(&bytes[..len]),
+            Some(key(KeyCode::Char('a'), 0, KeyKind::Press, "")));
+        assert_eq!(parse_key(&bytes[len..]),
+            Some(key(KeyCode::Char('a'), 0, KeyKind::Release, "")));
+        assert_eq!(csi_len(b"\x1B[<0;3;4Mx"), Some(9));
+        assert_eq!(csi_len(b"\x1B[M\x23\x21\x21\x1B[A"), Some(6));
+        assert_eq!(csi_len(b"\x1B[A"), Some(3));
+        assert_eq!(csi_len(b"\x1B[12;"), None);
+        assert_eq!(csi_len(b"ab"), None);
+    }
+
+    #[test]
+    fn test_parse_legacy_fallback() {
+        // Legacy sequences are left to the tuitty_core parser:
+        assert_eq!(parse_key(b"a"), None);
+        assert_eq!(parse_key(b"\x1B[A"), None);
+        assert_eq!(parse_key(b"\x1B[1;5A"), None);
+        assert_eq!(parse_key(b"\x1B[3~"), None);
+        assert_eq!(parse_key(b"\x1B[<0;3;4M"), None);
+        assert_eq!(parse_key(b"\x1B[?1u"), None);
+
+        let legacy = |bytes: &[u8]| parse_key(bytes).and_then(|k| k.legacy());
+        assert_eq!(legacy(b"\x1B[99;5u"), Some(KeyEvent::Ctrl('c')));
+        assert_eq!(legacy(b"\x1B[97;2u"), Some(KeyEvent::Char('A')));
+        assert_eq!(legacy(b"\x1B[9;2u"), Some(KeyEvent::BackTab));
+        assert_eq!(legacy(b"\x1B[1;2:1C"), Some(KeyEvent::ShiftRight));
+        assert_eq!(legacy(b"\x1B[97;6u"), None);
+        assert_eq!(legacy(b"\x1B[97;1:3u"), None);
+    }
+}
@@ -0,0 +1,418 @@
+// This module provides the Keymap that resolves key sequences read from an
+// EventHandle into named commands.
+//
+// Bindings are written as space separated keys, where each key is either a
+// literal character, a `C-` (Ctrl) or `M-` (Alt) chord, or a named key in
+// angle brackets. Keys without spaces in between are also a sequence:
+//
+//   "C-x C-s"    Ctrl+x followed by Ctrl+s
+//   "g g"        g followed by g (the same as "gg")
+//   "<Esc>:"     Esc followed by :
+//   "<C-Up>"     Ctrl+Up (also <S-Up>, <S-Tab>, <F1>, <Space>, <lt>, ...)
+//
+// Bindings are defined in named layers. The active layers are a stack, and
+// the topmost layer that knows about a sequence resolves it. Pushing and
+// popping layers provides modal keymaps (eg. an "insert" layer on top of a
+// "normal" layer). Within a layer, a binding that is the same as (or the
+// prefix of) another binding is a conflict.
+//
+// Once a sequence is the prefix of a binding, the Keymap waits for the next
+// key. If it doesn't arrive within the timeout, the pending keys are given
+// back as `Keyed::Unbound`, as are keys that don't match any binding.
+use std::{
+    collections::VecDeque, time::{ Duration, Instant },
+    io::{ Error, ErrorKind, Result },
+};
+use crate::tuitty_core::common::enums::{ InputEvent, KeyEvent };
+use super::EventHandle;
+use super::message::{ Msg, KeyKind };
+
+
+pub enum Keyed {
+    Command(String),
+    Unbound(Vec<KeyEvent>),
+    // Any other Msg received by the EventHandle.
+    Msg(Msg),
+}
+
+struct Binding {
+    keys: Vec<KeyEvent>,
+    source: String,
+    command: String,
+}
+
+struct Layer {
+    name: String,
+    bindings: Vec<Binding>,
+}
+
+enum Lookup<'a> {
+    Exact(&'a str),
+    Prefix,
+    Nothing,
+}
+
+impl Layer {
+    fn lookup(&self, keys: &[KeyEvent]) -> Lookup<'_> {
+        let mut lookup = Lookup::Nothing;
+        for binding in self.bindings.iter() {
+            if binding.keys == keys {
+                return Lookup::Exact(&binding.command)
+            }
+            if binding.keys.starts_with(keys) { lookup = Lookup::Prefix }
+        }
+        lookup
+    }
+}
+
+pub struct Keymap {
+    layers: Vec<Layer>,
+    active: Vec<usize>,
+    timeout: Duration,
+    pending: Vec<KeyEvent>,
+    since: Instant,
+    ready: VecDeque<Keyed>,
+}
+
+impl Keymap {
+    pub fn new(timeout: Duration) -> Keymap {
+        // The "global" layer is always at the bottom of the stack.
+        Keymap {
+            layers: vec![Layer { name: "global".into(), bindings: vec![] }],
+            active: vec![0],
+            timeout,
+            pending: Vec::new(),
+            since: Instant::now(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn layer(&mut self, name: &str) -> usize {
+        match self.layers.iter().position(|l| l.name == name) {
+            Some(index) => index,
+            None => {
+                self.layers.push(Layer { name: name.into(), bindings: vec![] });
+                self.layers.len() - 1
+            }
+        }
+    }
+
+    pub fn bind(
+        &mut self, layer: &str, binding: &str, command: &str
+    ) -> Result<()> {
+        let keys = parse(binding)?;
+        let index = self.layer(layer);
+        let layer = &mut self.layers[index];
+        for other in layer.bindings.iter() {
+            if other.keys.starts_with(&keys) || keys.starts_with(&other.keys) {
+                return Err(Error::new(ErrorKind::Other, format!(
+                    "Error: \"{}\" conflicts with \"{}\" in layer {}",
+                    binding, other.source, layer.name)))
+            }
+        }
+        layer.bindings.push(Binding {
+            keys,
+            source: binding.to_string(),
+            command: command.to_string(),
+        });
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, layer: &str, binding: &str) -> Result<()> {
+        let keys = parse(binding)?;
+        if let Some(layer) = self.layers.iter_mut().find(|l| l.name == layer) {
+            layer.bindings.retain(|b| b.keys != keys);
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, layer: &str) {
+        let index = self.layer(layer);
+        self.active.push(index);
+        self.reset();
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        // The "global" layer is never popped.
+        if self.active.len() == 1 { return None }
+        let index = self.active.pop()?;
+        self.reset();
+        Some(self.layers[index].name.to_string())
+    }
+
+    pub fn mode(&self) -> &str {
+        &self.layers[self.active[self.active.len() - 1]].name
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn reset(&mut self) {
+        // Switching layers gives back the keys of a pending sequence.
+        if self.pending.is_empty() { return }
+        let keys = std::mem::take(&mut self.pending);
+        self.ready.push_back(Keyed::Unbound(keys));
+    }
+
+    fn lookup(&self, keys: &[KeyEvent]) -> Lookup<'_> {
+        for index in self.active.iter().rev() {
+            match self.layers[*index].lookup(keys) {
+                Lookup::Nothing => continue,
+                lookup => return lookup,
+            }
+        }
+        Lookup::Nothing
+    }
+
+    pub fn feed(&mut self, kv: KeyEvent) {
+        self.expire();
+        self.pending.push(kv);
+        let command = match self.lookup(&self.pending) {
+            Lookup::Exact(command) => Some(command.to_string()),
+            Lookup::Prefix => {
+                self.since = Instant::now();
+                return
+            },
+            Lookup::Nothing => None,
+        };
+        match command {
+            Some(command) => {
+                self.pending.clear();
+                self.ready.push_back(Keyed::Command(command));
+            },
+            None => {
+                // (imdaveho) NOTE: If a pending sequence was broken by this
+                // key, give back the sequence and try the key on its own.
+                self.pending.pop();
+                if self.pending.is_empty() {
+                    self.ready.push_back(Keyed::Unbound(vec![kv]));
+                } else {
+                    self.reset();
+                    self.feed(kv);
+                }
+            }
+        }
+    }
+
+    pub fn expire(&mut self) {
+        if self.pending.is_empty() { return }
+        if self.since.elapsed() >= self.timeout { self.reset() }
+    }
+
+    pub fn take(&mut self) -> Option<Keyed> {
+        self.expire();
+        self.ready.pop_front()
+    }
+
+    pub fn poll(&mut self, handle: &EventHandle) -> Option<Keyed> {
+        // Non-blocking: resolves the keyboard events of the EventHandle and
+        // passes through any other Msg.
+        if let Some(keyed) = self.take() { return Some(keyed) }
+        while let Some(msg) = handle.poll_async() {
+            match msg {
+                Msg::Received(InputEvent::Keyboard(kv)) => {
+                    self.feed(kv);
+                    if let Some(keyed) = self.take() { return Some(keyed) }
+                },
+                // Kitty key events are resolved by their legacy KeyEvent.
+                Msg::Key(key) => match key.legacy() {
+                    Some(kv) => {
+                        self.feed(kv);
+                        if let Some(keyed) = self.take() {
+                            return Some(keyed)
+                        }
+                    },
+                    None if key.kind == KeyKind::Release => continue,
+                    None => return Some(Keyed::Msg(Msg::Key(key))),
+                },
+                msg => return Some(Keyed::Msg(msg)),
+            }
+        }
+        None
+    }
+}
+
+fn invalid(binding: &str, reason: &str) -> Error {
+    Error::new(ErrorKind::Other,
+        format!("Error: invalid key binding \"{}\": {}", binding, reason))
+}
+
+fn named(name: &str) -> Option<KeyEvent> {
+    let lower = name.to_lowercase();
+    let key = match lower.as_str() {
+        "esc" => KeyEvent::Esc,
+        "enter" | "cr" | "ret" => KeyEvent::Enter,
+        "tab" => KeyEvent::Tab,
+        "s-tab" | "backtab" => KeyEvent::BackTab,
+        "bs" | "backspace" => KeyEvent::Backspace,
+        "del" | "delete" => KeyEvent::Delete,
+        "ins" | "insert" => KeyEvent::Insert,
+        "home" => KeyEvent::Home,
+        "end" => KeyEvent::End,
+        "pageup" => KeyEvent::PageUp,
+        "pagedown" => KeyEvent::PageDown,
+        "up" => KeyEvent::Up,
+        "down" => KeyEvent::Down,
+        "left" => KeyEvent::Left,
+        "right" => KeyEvent::Right,
+        "c-up" => KeyEvent::CtrlUp,
+        "c-down" => KeyEvent::CtrlDown,
+        "c-left" => KeyEvent::CtrlLeft,
+        "c-right" => KeyEvent::CtrlRight,
+        "s-up" => KeyEvent::ShiftUp,
+        "s-down" => KeyEvent::ShiftDown,
+        "s-left" => KeyEvent::ShiftLeft,
+        "s-right" => KeyEvent::ShiftRight,
+        "space" => KeyEvent::Char(' '),
+        "lt" => KeyEvent::Char('<'),
+        "nul" => KeyEvent::Null,
+        _ => match lower.strip_prefix('f').map(str::parse::<u8>) {
+            Some(Ok(n)) if (1..=12).contains(&n) => KeyEvent::F(n),
+            _ => return chord(name),
+        }
+    };
+    Some(key)
+}
+
+fn chord(name: &str) -> Option<KeyEvent> {
+    // `C-x` or `M-x` where x is a single character. Only the uppercase
+    // prefixes are chords, so that eg. `a-b` is typed as is.
+    let mut chars = name.chars();
+    let modifier = chars.next()?;
+    if chars.next()? != '-' { return None }
+    let ch = chars.next()?;
+    if chars.next().is_some() { return None }
+    match modifier {
+        'C' => Some(KeyEvent::Ctrl(ch.to_ascii_lowercase())),
+        'M' => Some(KeyEvent::Alt(ch)),
+        _ => None,
+    }
+}
+
+pub fn parse(binding: &str) -> Result<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    for token in binding.split_whitespace() {
+        if let Some(kv) = chord(token) {
+            keys.push(kv);
+            continue
+        }
+        let mut rest = token;
+        while let Some(ch) = rest.chars().next() {
+            if ch == '<' {
+                let end = match rest.find('>') {
+                    Some(end) => end,
+                    None => return Err(invalid(binding, "missing >")),
+                };
+                match named(&rest[1..end]) {
+                    Some(kv) => keys.push(kv),
+                    None => return Err(invalid(binding,
+                        &format!("unknown key {}", &rest[..=end]))),
+                }
+                rest = &rest[end + 1..];
+            } else {
+                keys.push(KeyEvent::Char(ch));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    if keys.is_empty() { return Err(invalid(binding, "no keys")) }
+    Ok(keys)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(keymap: &mut Keymap) -> Option<String> {
+        match keymap.take() {
+            Some(Keyed::Command(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_keymap_parse() {
+        assert_eq!(parse("C-x C-s").unwrap(),
+            vec![KeyEvent::Ctrl('x'), KeyEvent::Ctrl('s')]);
+        assert_eq!(parse("g g").unwrap(), parse("gg").unwrap());
+        assert_eq!(parse("<Esc>:").unwrap(),
+            vec![KeyEvent::Esc, KeyEvent::Char(':')]);
+        assert_eq!(parse("<C-Up> <f5> <M-x> <lt>").unwrap(), vec![
+            KeyEvent::CtrlUp, KeyEvent::F(5),
+            KeyEvent::Alt('x'), KeyEvent::Char('<')]);
+        assert_eq!(parse("a-b").unwrap(), vec![
+            KeyEvent::Char('a'), KeyEvent::Char('-'), KeyEvent::Char('b')]);
+        assert!(parse("<Escape").is_err());
+        assert!(parse("<Hyper>").is_err());
+        assert!(parse("  ").is_err());
+    }
+
+    #[test]
+    fn test_keymap_sequences() {
+        let mut keymap = Keymap::new(Duration::from_secs(60));
+        keymap.bind("global", "C-x C-s", "save").unwrap();
+        keymap.bind("global", "C-x C-c", "quit").unwrap();
+        assert!(keymap.bind("global", "C-x", "prefix").is_err());
+        assert!(keymap.bind("global", "C-x C-s C-a", "longer").is_err());
+
+        keymap.feed(KeyEvent::Ctrl('x'));
+        assert!(keymap.is_pending());
+        assert!(keymap.take().is_none());
+        keymap.feed(KeyEvent::Ctrl('s'));
+        assert_eq!(command(&mut keymap).as_deref(), Some("save"));
+
+        // A broken sequence is given back and the key is tried again:
+        keymap.feed(KeyEvent::Ctrl('x'));
+        keymap.feed(KeyEvent::Char('a'));
+        match keymap.take() {
+            Some(Keyed::Unbound(keys)) => {
+                assert_eq!(keys, vec![KeyEvent::Ctrl('x')])
+            },
+            _ => panic!("Expected the pending keys"),
+        }
+        match keymap.take() {
+            Some(Keyed::Unbound(keys)) => {
+                assert_eq!(keys, vec![KeyEvent::Char('a')])
+            },
+            _ => panic!("Expected the unbound key"),
+        }
+        assert!(!keymap.is_pending());
+    }
+
+    #[test]
+    fn test_keymap_layers_and_timeout() {
+        let mut keymap = Keymap::new(Duration::from_millis(10));
+        keymap.bind("normal", "g g", "top").unwrap();
+        keymap.bind("normal", "i", "insert").unwrap();
+        keymap.bind("insert", "<Esc>", "normal").unwrap();
+        keymap.bind("global", "C-q", "quit").unwrap();
+        // The same binding in different layers is not a conflict:
+        keymap.bind("insert", "i", "literal").unwrap();
+
+        keymap.push("normal");
+        assert_eq!(keymap.mode(), "normal");
+        keymap.feed(KeyEvent::Char('i'));
+        assert_eq!(command(&mut keymap).as_deref(), Some("insert"));
+        keymap.push("insert");
+        keymap.feed(KeyEvent::Char('i'));
+        assert_eq!(command(&mut keymap).as_deref(), Some("literal"));
+        // Lower layers resolve what the top layer doesn't know about:
+        keymap.feed(KeyEvent::Ctrl('q'));
+        assert_eq!(command(&mut keymap).as_deref(), Some("quit"));
+        assert_eq!(keymap.pop().as_deref(), Some("insert"));
+
+        keymap.feed(KeyEvent::Char('g'));
+        assert!(keymap.take().is_none());
+        std::thread::sleep(Duration::from_millis(20));
+        match keymap.take() {
+            Some(Keyed::Unbound(keys)) => {
+                assert_eq!(keys, vec![KeyEvent::Char('g')])
+            },
+            _ => panic!("Expected the pending keys to time out"),
+        }
+        assert_eq!(keymap.pop().as_deref(), Some("normal"));
+        assert!(keymap.pop().is_none());
+    }
+}
@@ -0,0 +1,209 @@
+// This module queues the handles that wait for the lock (see
+// `EventHandle::lock`).
+//
+// The lock is handed off to the waiting handles in the order that they
+// asked for it, once the owner unlocks (or is stopped or dropped). Each
+// handle is sent `Msg::Locked` when it becomes the owner, or
+// `Msg::LockTimeout` if its timeout passed while it was still waiting.
+//
+// Every `lock()` takes a ticket, and the owner holds the lock until all of
+// its tickets are released (so a stale LockGuard cannot release a lock that
+// was taken again). A `raw_pos` request holds the POS ticket while the
+// terminal answers, so that the cursor position is only sent to it.
+use std::{
+    collections::VecDeque,
+    sync::atomic::{ AtomicUsize, Ordering },
+    time::{ Duration, Instant },
+};
+
+// The ticket of a `raw_pos` request.
+pub const POS: usize = 0;
+// How long a `raw_pos` request holds the lock without an answer.
+const POS_TIMEOUT: u64 = 1000;
+
+
+pub struct LockQueue {
+    // Tickets held by the owner.
+    held: Vec<usize>,
+    // Owner that still has to ask the terminal for the cursor position.
+    due: Option<usize>,
+    // When the POS ticket of the owner is given up.
+    pos_deadline: Option<Instant>,
+    // Ids of the waiting handles, their tickets and deadlines (if any).
+    waiting: VecDeque<(usize, usize, Option<Instant>)>,
+}
+
+impl LockQueue {
+    pub fn new() -> LockQueue {
+        LockQueue {
+            held: Vec::new(),
+            due: None,
+            pos_deadline: None,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    pub fn lock(
+        &mut self,
+        owner: &AtomicUsize,
+        id: usize,
+        ticket: usize,
+        deadline: Option<Instant>,
+    ) -> Option<usize> {
+        // Returns the id of the handle that became the owner (if any).
+        match owner.load(Ordering::SeqCst) {
+            0 => {
+                self.waiting.push_front((id, ticket, deadline));
+                self.grant(owner, id)
+            },
+            current if current == id => {
+                self.hold(id, ticket);
+                None
+            },
+            _ => {
+                self.waiting.push_back((id, ticket, deadline));
+                None
+            },
+        }
+    }
+
+    pub fn unlock(
+        &mut self, owner: &AtomicUsize, id: usize, ticket: Option<usize>
+    ) -> Option<usize> {
+        // Releases the ticket (or every ticket) of `id`, whether it owns
+        // the lock or waits for it. Returns the id of the next owner that
+        // needs to be sent `Msg::Locked` (if any).
+        let is_released = |t: &usize| ticket.is_none() || ticket == Some(*t);
+        self.waiting.retain(|(waiter, t, _)| *waiter != id || !is_released(t));
+        if owner.load(Ordering::SeqCst) != id { return None }
+        self.held.retain(|t| !is_released(t));
+        if !self.held.contains(&POS) {
+            self.due = None;
+            self.pos_deadline = None;
+        }
+        if !self.held.is_empty() { return None }
+        match self.waiting.front() {
+            Some((next, _, _)) => {
+                let next = *next;
+                self.grant(owner, next)
+            },
+            None => {
+                owner.store(0, Ordering::SeqCst);
+                None
+            },
+        }
+    }
+
+    pub fn take_due(&mut self) -> Option<usize> {
+        // The owner to ask the terminal for the cursor position (if any).
+        self.due.take()
+    }
+
+    pub fn expire(&mut self, now: Instant) -> Vec<usize> {
+        // Withdraws (and returns) the handles whose deadline has passed.
+        let mut expired = Vec::new();
+        self.waiting.retain(|(waiter, _, deadline)| match deadline {
+            Some(d) if *d <= now => { expired.push(*waiter); false },
+            _ => true,
+        });
+        expired
+    }
+
+    pub fn overdue(&self, owner: &AtomicUsize, now: Instant) -> Option<usize> {
+        // The owner whose POS ticket is past its deadline (if any).
+        match self.pos_deadline {
+            Some(d) if d <= now => Some(owner.load(Ordering::SeqCst)),
+            _ => None,
+        }
+    }
+
+    fn grant(&mut self, owner: &AtomicUsize, id: usize) -> Option<usize> {
+        // Hands the lock to `id` along with all of its waiting tickets.
+        owner.store(id, Ordering::SeqCst);
+        self.held.clear();
+        let mut is_locked = false;
+        let waiting = std::mem::take(&mut self.waiting);
+        for (waiter, ticket, deadline) in waiting {
+            if waiter != id {
+                self.waiting.push_back((waiter, ticket, deadline));
+                continue
+            }
+            if ticket != POS { is_locked = true }
+            self.hold(id, ticket);
+        }
+        if is_locked { Some(id) } else { None }
+    }
+
+    fn hold(&mut self, id: usize, ticket: usize) {
+        if ticket == POS {
+            self.due = Some(id);
+            self.pos_deadline = Some(
+                Instant::now() + Duration::from_millis(POS_TIMEOUT));
+        }
+        self.held.push(ticket);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_queue() {
+        let owner = AtomicUsize::new(0);
+        let mut queue = LockQueue::new();
+        let now = Instant::now();
+        assert_eq!(queue.lock(&owner, 1, 1, None), Some(1));
+        // Contention queues instead of failing:
+        assert_eq!(queue.lock(&owner, 2, 1, None), None);
+        assert_eq!(queue.lock(&owner, 3, 1, Some(now)), None);
+        assert_eq!(queue.lock(&owner, 4, 1, None), None);
+        assert_eq!(queue.lock(&owner, 1, 2, None), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 1);
+
+        // Waiting handles can give up:
+        assert_eq!(queue.expire(now + Duration::from_millis(1)), [3]);
+        assert_eq!(queue.unlock(&owner, 4, None), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 1);
+
+        // The owner keeps the lock until every ticket is released:
+        assert_eq!(queue.unlock(&owner, 1, Some(1)), None);
+        assert_eq!(queue.unlock(&owner, 1, Some(1)), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 1);
+
+        // Handoff is FIFO:
+        assert_eq!(queue.lock(&owner, 5, 1, None), None);
+        assert_eq!(queue.unlock(&owner, 1, Some(2)), Some(2));
+        assert_eq!(queue.unlock(&owner, 2, None), Some(5));
+        assert_eq!(queue.unlock(&owner, 5, None), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_lock_queue_pos() {
+        let owner = AtomicUsize::new(0);
+        let mut queue = LockQueue::new();
+        let now = Instant::now();
+        // The owner asks for the position right away:
+        assert_eq!(queue.lock(&owner, 1, 1, None), Some(1));
+        assert_eq!(queue.lock(&owner, 1, POS, None), None);
+        assert_eq!(queue.take_due(), Some(1));
+        assert_eq!(queue.unlock(&owner, 1, Some(POS)), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 1);
+        let late = now + Duration::from_millis(POS_TIMEOUT * 2);
+        assert_eq!(queue.overdue(&owner, late), None);
+
+        // Others wait in line, and keep the lock until they are answered:
+        assert_eq!(queue.lock(&owner, 2, POS, None), None);
+        assert_eq!(queue.take_due(), None);
+        assert_eq!(queue.lock(&owner, 3, 1, None), None);
+        assert_eq!(queue.unlock(&owner, 1, None), None);
+        assert_eq!(owner.load(Ordering::SeqCst), 2);
+        assert_eq!(queue.take_due(), Some(2));
+        assert_eq!(queue.overdue(&owner, now), None);
+        assert_eq!(queue.overdue(&owner, late), Some(2));
+        assert_eq!(queue.unlock(&owner, 2, Some(POS)), Some(3));
+        assert_eq!(queue.overdue(&owner, late), None);
+    }
+}
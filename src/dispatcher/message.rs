@@ -1,23 +1,392 @@
+use std::time::Duration;
 use crate::tuitty_core::common::enums::*;
+// Defined with the Store that produces them.
+pub use crate::store::{
+    Snapshot, CellData, Format, MouseLevel, MouseEncoding,
+};
+#[cfg(unix)]
+use super::notify;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Msg {
     Received(InputEvent),
+    // A key event of the kitty keyboard protocol (see `PushKeyboard`).
+    Key(Key),
+    // A mouse event of the extended tracking modes (see `SetMouse`).
+    Mouse(Mouse),
+    // A mouse event within a registered Region (in region coordinates).
+    Region(String, Mouse),
+    // The pointer moved into or out of a registered Region.
+    Enter(String),
+    Leave(String),
+    // A gesture synthesized from the mouse events (see `Thresholds`).
+    Gesture(Gesture, Mouse),
+    // The handle became the owner of the lock (see `EventHandle::lock`).
+    Locked,
+    // The handle gave up waiting for the lock.
+    LockTimeout,
+    // An Action failed (sent to every handle).
+    Error(Failure),
+    // The process was continued after `Action::SuspendProcess` (sent to
+    // every handle).
+    Resumed,
+    // The process received a signal (sent to every handle), see
+    // `Dispatcher::grace`.
+    Signal(SignalKind),
     Response(Reply),
     Unsupported,
 }
 
+// Progressive enhancement flags of the kitty keyboard protocol.
+/// cbindgen:ignore
+pub mod kitty {
+    pub const DISAMBIGUATE: u8 = 1;
+    pub const EVENT_TYPES: u8 = 2;
+    pub const ALTERNATE_KEYS: u8 = 4;
+    pub const ALL_KEYS: u8 = 8;
+    pub const TEXT: u8 = 16;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyCode {
+    Char(char),
+    Esc,
+    Enter,
+    Tab,
+    Backspace,
+    Insert,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    F(u8),
+    // Keys in the private use area (eg. keypad, media and modifier keys).
+    Other(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: u8,
+    pub kind: KeyKind,
+    // The text of the key (only reported with `kitty::TEXT`).
+    pub text: String,
+}
+
+/// cbindgen:ignore
+impl Key {
+    pub const SHIFT: u8 = 1;
+    pub const ALT: u8 = 1 << 1;
+    pub const CTRL: u8 = 1 << 2;
+    pub const SUPER: u8 = 1 << 3;
+    pub const HYPER: u8 = 1 << 4;
+    pub const META: u8 = 1 << 5;
+    pub const CAPS_LOCK: u8 = 1 << 6;
+    pub const NUM_LOCK: u8 = 1 << 7;
+
+    pub fn legacy(&self) -> Option<KeyEvent> {
+        // The KeyEvent that a legacy terminal would have sent (if any).
+        if self.kind == KeyKind::Release { return None }
+        let mods = self.modifiers & !(Key::CAPS_LOCK | Key::NUM_LOCK);
+        let (shift, ctrl) = (mods == Key::SHIFT, mods == Key::CTRL);
+        let key = match (&self.code, mods) {
+            (KeyCode::Char(c), 0) => KeyEvent::Char(*c),
+            (KeyCode::Char(c), Key::ALT) => KeyEvent::Alt(*c),
+            (KeyCode::Char(c), Key::CTRL) => KeyEvent::Ctrl(*c),
+            (KeyCode::Char(c), Key::SHIFT) => {
+                let mut chars = self.text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(t), None) => KeyEvent::Char(t),
+                    _ if c.is_alphabetic() && c.is_lowercase() => {
+                        KeyEvent::Char(c.to_uppercase().next()?)
+                    },
+                    _ => return None,
+                }
+            },
+            (KeyCode::Tab, Key::SHIFT) => KeyEvent::BackTab,
+            (KeyCode::Up, _) if ctrl => KeyEvent::CtrlUp,
+            (KeyCode::Down, _) if ctrl => KeyEvent::CtrlDown,
+            (KeyCode::Left, _) if ctrl => KeyEvent::CtrlLeft,
+            (KeyCode::Right, _) if ctrl => KeyEvent::CtrlRight,
+            (KeyCode::Up, _) if shift => KeyEvent::ShiftUp,
+            (KeyCode::Down, _) if shift => KeyEvent::ShiftDown,
+            (KeyCode::Left, _) if shift => KeyEvent::ShiftLeft,
+            (KeyCode::Right, _) if shift => KeyEvent::ShiftRight,
+            (code, 0) => match code {
+                KeyCode::Esc => KeyEvent::Esc,
+                KeyCode::Enter => KeyEvent::Enter,
+                KeyCode::Tab => KeyEvent::Tab,
+                KeyCode::Backspace => KeyEvent::Backspace,
+                KeyCode::Insert => KeyEvent::Insert,
+                KeyCode::Delete => KeyEvent::Delete,
+                KeyCode::Left => KeyEvent::Left,
+                KeyCode::Right => KeyEvent::Right,
+                KeyCode::Up => KeyEvent::Up,
+                KeyCode::Down => KeyEvent::Down,
+                KeyCode::PageUp => KeyEvent::PageUp,
+                KeyCode::PageDown => KeyEvent::PageDown,
+                KeyCode::Home => KeyEvent::Home,
+                KeyCode::End => KeyEvent::End,
+                KeyCode::F(n) => KeyEvent::F(*n),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
 pub enum Cmd {
     Continue,
     Suspend(usize),
     Transmit(usize),
     Stop(usize),
-    Lock(usize),
-    Unlock,
+    // Waits for the lock with a ticket (at most the timeout, if any).
+    Lock(usize, usize, Option<Duration>),
+    // Releases a ticket (or every ticket) of the handle.
+    Unlock(usize, Option<usize>),
+    #[cfg(unix)]
+    Notify(usize, notify::Writer),
+    Filter(usize, Filter),
+    // How to handle the errors of a class (see `Dispatcher::on_error`).
+    OnError(ErrorClass, Policy),
+    // How long the handles have to respond to a `Msg::Signal`.
+    #[cfg(unix)]
+    Grace(Duration),
+    // Pushes the handle onto the focus stack (and if mouse is shared).
+    Focus(usize, bool),
+    Blur(usize),
+    AddRegion(usize, Region),
+    RemoveRegion(usize, String),
     Signal(Action),
     Request(Query)
 }
 
+pub enum Filter {
+    All,
+    Keyboard,
+    Mouse,
+    Keys(Vec<KeyEvent>),
+    // Mouse events within (col, row, w, h).
+    Region(i16, i16, i16, i16),
+    Custom(Box<dyn Fn(&InputEvent) -> bool + Send>),
+    // Matches if any of the filters match.
+    Any(Vec<Filter>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    // Buttons 8 to 11 (eg. back and forward).
+    Extra(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseKind {
+    Press,
+    Release,
+    Drag,
+    Move,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    // The button (if known) of a Press, Release or Drag.
+    pub button: Option<Button>,
+    pub col: i16,
+    pub row: i16,
+    // Key::SHIFT, Key::ALT and Key::CTRL.
+    pub modifiers: u8,
+}
+
+impl Mouse {
+    pub fn from_legacy(event: &MouseEvent) -> Option<Mouse> {
+        let (kind, button, col, row) = match *event {
+            MouseEvent::Press(button, col, row) => {
+                let button = match button {
+                    MouseButton::Left => Button::Left,
+                    MouseButton::Middle => Button::Middle,
+                    MouseButton::Right => Button::Right,
+                    MouseButton::WheelUp => Button::WheelUp,
+                    MouseButton::WheelDown => Button::WheelDown,
+                };
+                (MouseKind::Press, Some(button), col, row)
+            },
+            MouseEvent::Release(col, row) => (MouseKind::Release, None, col, row),
+            MouseEvent::Hold(col, row) => (MouseKind::Drag, None, col, row),
+            MouseEvent::Unknown => return None,
+        };
+        Some(Mouse { kind, button, col, row, modifiers: 0 })
+    }
+
+    pub fn legacy(&self) -> Option<MouseEvent> {
+        // The MouseEvent that `EnableMouse` would have sent (if any).
+        let (col, row) = (self.col, self.row);
+        let event = match (self.kind, self.button) {
+            (MouseKind::Press, Some(button)) => {
+                let button = match button {
+                    Button::Left => MouseButton::Left,
+                    Button::Middle => MouseButton::Middle,
+                    Button::Right => MouseButton::Right,
+                    Button::WheelUp => MouseButton::WheelUp,
+                    Button::WheelDown => MouseButton::WheelDown,
+                    _ => return None,
+                };
+                MouseEvent::Press(button, col, row)
+            },
+            (MouseKind::Release, _) => MouseEvent::Release(col, row),
+            (MouseKind::Drag, _) => MouseEvent::Hold(col, row),
+            _ => return None,
+        };
+        Some(event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    // Sent after the Press of the second (or third) click.
+    DoubleClick,
+    TripleClick,
+    // Sent with the Press that the drag started from.
+    DragStart,
+    DragMove,
+    DragEnd,
+    // Sent once a button has been held in place long enough.
+    LongPress,
+}
+
+// The thresholds that gestures are synthesized with (see
+// `Dispatcher::gestures`). Distances are in cells (or in pixels with
+// `MouseEncoding::SgrPixels`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    // Max time between the presses of a double or triple click.
+    pub click_interval: Duration,
+    // Max distance between the presses of a double or triple click.
+    pub click_distance: i16,
+    // Min distance from the Press before a Drag starts a drag gesture.
+    pub drag_distance: i16,
+    // Min time that a button is held in place for a long press.
+    pub long_press: Duration,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            click_interval: Duration::from_millis(500),
+            click_distance: 0,
+            drag_distance: 1,
+            long_press: Duration::from_millis(700),
+        }
+    }
+}
+
+// A named rectangle that mouse events are hit-tested against. Only the
+// topmost Region (highest z, or the one added last) under the pointer is hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    pub col: i16,
+    pub row: i16,
+    pub w: i16,
+    pub h: i16,
+    // Only hit on this screen id (or on any screen if None).
+    pub screen: Option<usize>,
+    pub z: i16,
+}
+
+impl Region {
+    pub fn new(name: &str, col: i16, row: i16, w: i16, h: i16) -> Region {
+        Region {
+            name: name.to_string(), col, row, w, h, screen: None, z: 0,
+        }
+    }
+
+    pub fn contains(&self, col: i16, row: i16) -> bool {
+        col >= self.col && col < self.col + self.w
+            && row >= self.row && row < self.row + self.h
+    }
+}
+
+impl Filter {
+    pub fn accepts(&self, msg: &Msg) -> bool {
+        match msg {
+            Msg::Received(evt) => self.matches(evt),
+            // Kitty key events are matched by their legacy KeyEvent.
+            Msg::Key(key) => match self {
+                Filter::Mouse | Filter::Region(_, _, _, _) => false,
+                Filter::Any(filters) => filters.iter().any(|f| f.accepts(msg)),
+                Filter::All | Filter::Keyboard => true,
+                filter => match key.legacy() {
+                    Some(kv) => filter.matches(&InputEvent::Keyboard(kv)),
+                    None => false,
+                },
+            },
+            // Gestures are matched by the mouse event they were sent with.
+            Msg::Mouse(mouse) | Msg::Gesture(_, mouse) => match self {
+                Filter::Keyboard | Filter::Keys(_) => false,
+                Filter::All | Filter::Mouse => true,
+                Filter::Region(col, row, w, h) => {
+                    let (c, r) = (mouse.col, mouse.row);
+                    c >= *col && c < col + w && r >= *row && r < row + h
+                },
+                Filter::Any(filters) => filters.iter().any(|f| f.accepts(msg)),
+                Filter::Custom(predicate) => match mouse.legacy() {
+                    Some(mv) => predicate(&InputEvent::Mouse(mv)),
+                    None => false,
+                },
+            },
+            _ => true,
+        }
+    }
+
+    pub fn matches(&self, evt: &InputEvent) -> bool {
+        // (imdaveho) NOTE: Cursor positions are replies to `raw_pos`
+        // requests, so they are never filtered out.
+        if let InputEvent::CursorPos(_, _) = evt { return true }
+        match self {
+            Filter::All => true,
+            Filter::Keyboard => matches!(evt, InputEvent::Keyboard(_)),
+            Filter::Mouse => matches!(evt, InputEvent::Mouse(_)),
+            Filter::Keys(keys) => match evt {
+                InputEvent::Keyboard(kv) => keys.contains(kv),
+                _ => false,
+            },
+            Filter::Region(col, row, w, h) => {
+                let (c, r) = match evt {
+                    InputEvent::Mouse(MouseEvent::Press(_, c, r)) => (c, r),
+                    InputEvent::Mouse(MouseEvent::Release(c, r)) => (c, r),
+                    InputEvent::Mouse(MouseEvent::Hold(c, r)) => (c, r),
+                    _ => return false,
+                };
+                c >= col && *c < col + w && r >= row && *r < row + h
+            },
+            Filter::Custom(predicate) => predicate(evt),
+            Filter::Any(filters) => filters.iter().any(|f| f.matches(evt)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
     // CURSOR
     Goto(i16, i16),
@@ -43,33 +412,111 @@ pub enum Action {
     ShowCursor,
     EnableMouse,
     DisableMouse,
+    SetMouse(MouseLevel, MouseEncoding),
     Raw,
     Cook,
     // INTERNAL BUFFER
     NewScreen,
     SwitchTo(usize),
+    CloseScreen(usize),
+    PushScreen(usize),
+    PopScreen,
     Resized,
+    // Updates the size of the Store without resizing the terminal.
+    SyncSize(i16, i16),
     SyncMarker(i16, i16),
     Jump,
     SyncTabSize(usize),
+    SyncBidi(bool),
+    Restore(Snapshot),
+    // RECORDING
+    StartCast(String),
+    StopCast,
+    // KEYBOARD
+    PushKeyboard(u8),
+    PopKeyboard,
+    // JOB CONTROL
+    SuspendProcess,
 }
 
+#[derive(Debug, PartialEq)]
 pub enum Query {
     Size(usize),
     Coord(usize),
     Pos(usize),
     GetCh(usize),
+    GetLn(usize),
     Screen(usize),
+    ListScreens(usize),
+    Snapshot(usize, usize),
+    Dump(usize, usize, Format),
     _IsRaw(usize),
+    LastError(usize),
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Reply {
     Size(i16, i16),
     Coord(i16, i16),
     Pos(i16, i16),
     GetCh(String),
+    GetLn(String),
     Screen(usize),
+    Screens(Vec<usize>),
+    Snapshot(Option<Snapshot>),
+    Dump(Option<String>),
     _IsRaw(bool),
+    LastError(Option<Failure>),
     Empty
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    // The terminal went away (eg. a write to a closed tty).
+    Disconnected,
+    // The call was interrupted or would have blocked.
+    Interrupted,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn of(kind: std::io::ErrorKind) -> ErrorClass {
+        use std::io::ErrorKind::*;
+        match kind {
+            BrokenPipe | NotConnected | ConnectionReset | ConnectionAborted
+                | UnexpectedEof => ErrorClass::Disconnected,
+            Interrupted | WouldBlock | TimedOut => ErrorClass::Interrupted,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    // Keep going (the handles are still sent `Msg::Error`).
+    Ignore,
+    // Try the Action again (at most n times), then shut down. Actions that
+    // cannot be repeated safely (eg. `Prints`) shut down right away.
+    Retry(u8),
+    Shutdown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    // The name of the Action (as in `wire`), eg. "flush".
+    pub action: String,
+    pub class: ErrorClass,
+    pub details: String,
+    // Whether the dispatcher shut down because of it.
+    pub is_fatal: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignalKind {
+    // SIGINT (eg. Ctrl+C in cooked mode).
+    Interrupt,
+    // SIGTERM (eg. `kill`).
+    Terminate,
+    // SIGHUP (eg. the terminal window was closed).
+    Hangup,
+}
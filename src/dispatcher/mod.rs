@@ -1,47 +1,132 @@
 mod router;
-use router::handle_action;
+use router::{ handle_action, reset_modes };
+#[cfg(unix)]
+mod input;
+#[cfg(unix)]
+mod notify;
+#[cfg(unix)]
+mod server;
+mod session;
+use session::Recorder;
+mod cast;
+use cast::{ Cast, Output };
+mod pointer;
+use pointer::Pointer;
+mod gesture;
+use gesture::Tracker;
+mod lock;
+use lock::{ LockQueue, POS };
+mod policy;
+use policy::{ Policies, report, is_idempotent };
+mod restore;
+pub use restore::catch_unwind;
+#[cfg(unix)]
+mod signals;
 
 use std::{
-    thread, collections::HashMap,
+    thread, collections::{ HashMap, VecDeque }, pin::Pin, future::Future,
+    task::{ Context, Poll, Waker },
     time::{ SystemTime, UNIX_EPOCH, Duration },
     sync::{
-        mpsc::{ channel, Sender, Receiver, SendError, TryRecvError },
-        Arc, Mutex, atomic::{ AtomicBool, AtomicUsize, Ordering },
+        mpsc::{
+            channel, sync_channel, Sender, SyncSender, Receiver, SendError,
+            TryRecvError, TrySendError,
+        },
+        Arc, Mutex, atomic::{ AtomicBool, AtomicU32, AtomicUsize, Ordering },
     },
 };
 use crate::store::Store;
 use message::{
-    Action, Cmd::{*, self},
-    Msg::{*, self}, Query::*, Reply,
+    Action, Cmd::{*, self}, Filter, Region, Thresholds, ErrorClass, Policy,
+    Failure, SignalKind,
+    Msg::{*, self}, Query::{*, self}, Reply, Format,
 };
 use crate::tuitty_core::terminal::Term;
+use crate::tuitty_core::common::enums::InputEvent;
 
+use std::cell::{ Cell, RefCell };
 #[cfg(unix)]
-use crate::tuitty_core::parser::unix;
+use std::os::unix::io::RawFd;
 #[cfg(unix)]
-use crate::tuitty_core::common::enums::InputEvent;
+use crate::tuitty_core::parser::unix;
 #[cfg(windows)]
 use crate::tuitty_core::parser::windows;
 
 pub mod message;
+pub mod keymap;
+pub mod wire;
 
 const DELAY: u64 = 3;
+// Milliseconds that the handles have to respond to a signal by default.
+#[cfg(unix)]
+const GRACE: u64 = 1000;
 
 pub struct EventHandle {
     id: usize,
     event_rx: Receiver<Msg>,
     signal_tx: Sender<Cmd>,
+    // Waker of the pending `next_event()` future (if any).
+    waker: Arc<Mutex<Option<Waker>>>,
+    // Last ticket taken for the lock (see `lock`).
+    ticket: Cell<usize>,
+    // Read end of the readiness pipe (see `fd()`).
+    #[cfg(unix)]
+    notifier: RefCell<Option<notify::Reader>>,
 }
 
 impl EventHandle {
     pub fn poll_async(&self) -> Option<Msg> {
         let mut iterator = self.event_rx.try_iter();
-        iterator.next()
+        match iterator.next() {
+            Some(msg) => Some(msg),
+            None => {
+                // (imdaveho) NOTE: Drain before checking again so that a
+                // Msg sent in between is either received here or leaves
+                // the fd readable.
+                if !self.drain() { return None }
+                iterator.next()
+            }
+        }
     }
 
     pub fn poll_latest_async(&self) -> Option<Msg> {
-        let iterator = self.event_rx.try_iter();
-        iterator.last()
+        let latest = self.event_rx.try_iter().last();
+        if !self.drain() { return latest }
+        match self.event_rx.try_iter().last() {
+            Some(msg) => Some(msg),
+            None => latest,
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn fd(&self) -> std::io::Result<RawFd> {
+        // Returns a file descriptor that is readable whenever Msgs are
+        // queued for this handle. Keep calling `poll_async` until it
+        // returns None to clear it.
+        if let Some(reader) = self.notifier.borrow().as_ref() {
+            return Ok(reader.fd())
+        }
+        let (reader, writer) = notify::pipe()?;
+        if self.signal_tx.send(Notify(self.id, writer)).is_err() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other,
+                "Error: the dispatcher has shut down"))
+        }
+        let fd = reader.fd();
+        *self.notifier.borrow_mut() = Some(reader);
+        Ok(fd)
+    }
+
+    #[cfg(unix)]
+    fn drain(&self) -> bool {
+        match self.notifier.borrow().as_ref() {
+            Some(reader) => { reader.drain(); true },
+            None => false,
+        }
+    }
+
+    #[cfg(windows)]
+    fn drain(&self) -> bool {
+        false
     }
 
     pub fn poll_sync(&self) -> Option<Msg> {
@@ -49,6 +134,12 @@ impl EventHandle {
         iterator.next()
     }
 
+    pub fn next_event(&self) -> NextEvent<'_> {
+        // Resolves to the next Msg, or None once the dispatcher has shut
+        // down. Awaiting it in a loop treats the handle as a stream.
+        NextEvent { handle: self }
+    }
+
     pub fn suspend(&self) -> Result<(), SendError<Cmd>> {
         self.signal_tx.send(Suspend(self.id))
     }
@@ -61,16 +152,68 @@ impl EventHandle {
         self.signal_tx.send(Stop(self.id))
     }
 
-    pub fn lock(&self) -> Result<(), SendError<Cmd>> {
-        self.signal_tx.send(Lock(self.id))
+    pub fn filter(&self, filter: Filter) -> Result<(), SendError<Cmd>> {
+        // Only the InputEvents that match the filter are sent to this
+        // handle. Use Filter::All to receive everything again.
+        self.signal_tx.send(Cmd::Filter(self.id, filter))
+    }
+
+    pub fn add_region(&self, region: Region) -> Result<(), SendError<Cmd>> {
+        // Mouse events within the region are also sent to this handle as
+        // `Msg::Region` (see `pointer`). Replaces a region of the same name.
+        self.signal_tx.send(Cmd::AddRegion(self.id, region))
+    }
+
+    pub fn remove_region(&self, name: &str) -> Result<(), SendError<Cmd>> {
+        self.signal_tx.send(Cmd::RemoveRegion(self.id, name.to_string()))
+    }
+
+    pub fn lock(&self) -> Result<LockGuard<'_>, SendError<Cmd>> {
+        // Only this handle receives events while it owns the lock. If
+        // another handle owns it, this one waits in line and is sent
+        // `Msg::Locked` once it takes over. The lock is held until every
+        // guard of the handle drops (or `unlock()`).
+        self.take_lock(None)
+    }
+
+    pub fn lock_timeout(
+        &self, timeout: Duration
+    ) -> Result<LockGuard<'_>, SendError<Cmd>> {
+        // Same as `lock()` but gives up waiting after the timeout (and is
+        // sent `Msg::LockTimeout` instead). Dropping the guard after that
+        // does nothing.
+        self.take_lock(Some(timeout))
     }
 
     pub fn unlock(&self) -> Result<(), SendError<Cmd>> {
-        self.signal_tx.send(Unlock)
+        // Releases the lock (of every guard), or stops waiting for it.
+        self.signal_tx.send(Unlock(self.id, None))
+    }
+
+    fn take_lock(
+        &self, timeout: Option<Duration>
+    ) -> Result<LockGuard<'_>, SendError<Cmd>> {
+        let ticket = self.ticket.get() + 1;
+        self.ticket.set(ticket);
+        self.signal_tx.send(Lock(self.id, ticket, timeout))?;
+        Ok(LockGuard { handle: self, ticket })
+    }
+
+    pub fn focus(&self, share_mouse: bool) -> Result<(), SendError<Cmd>> {
+        // Pushes this handle onto the focus stack, so that only it receives
+        // keyboard input (and mouse input, unless `share_mouse`). Focus
+        // skips over suspended handles to the next one down the stack.
+        self.signal_tx.send(Focus(self.id, share_mouse))
+    }
+
+    pub fn blur(&self) -> Result<(), SendError<Cmd>> {
+        // Removes this handle from the focus stack, which returns focus to
+        // the handle that had it before.
+        self.signal_tx.send(Blur(self.id))
     }
 
     pub fn signal(&self, action: Action) -> Result<(), SendError<Cmd>> {
-        self.signal_tx.send(Signal(action))
+        self.signal_tx.send(Cmd::Signal(action))
     }
 
     pub fn request(&self, query: &str) -> Result<Reply, SendError<Cmd>> {
@@ -85,27 +228,34 @@ impl EventHandle {
                 let mut iter = self.event_rx.iter();
                 let is_raw: bool;
                 loop {
-                    if let Some(Msg::Response(r)) = iter.next() {
-                        if let Reply::_IsRaw(b) = r {
+                    match iter.next() {
+                        Some(Msg::Response(Reply::_IsRaw(b))) => {
                             is_raw = b;
                             break
-                        }
+                        },
+                        Some(_) => continue,
+                        None => return Ok(Reply::Empty),
                     }
                 }
                 // Set it to raw temporarily, if not in raw mode.
-                if !is_raw { self.signal_tx.send(Signal(Action::Raw))? }
+                if !is_raw { self.signal_tx.send(Cmd::Signal(Action::Raw))? }
                 // Request the cursor position and 
                 self.signal_tx.send(Request(Pos(self.id)))?;
                 loop {
-                    if let Some(Msg::Received(iv)) = iter.next() {
-                        if let InputEvent::CursorPos(col, row) = iv {
-                            // Revert back to cooked mode.
-                            if !is_raw { 
-                                self.signal_tx.send(Signal(Action::Cook))?
-                            }
-                            return Ok(Reply::Pos(col, row));
-                        }
+                    let reply = match iter.next() {
+                        Some(Msg::Received(InputEvent::CursorPos(col, row))) => {
+                            Reply::Pos(col, row)
+                        },
+                        // The terminal did not answer (see `Query::Pos`).
+                        Some(Msg::Response(Reply::Empty)) => Reply::Empty,
+                        Some(_) => continue,
+                        None => return Ok(Reply::Empty),
+                    };
+                    // Revert back to cooked mode.
+                    if !is_raw { 
+                        self.signal_tx.send(Cmd::Signal(Action::Cook))?
                     }
+                    return Ok(reply);
                 }
             },
             #[cfg(windows)]
@@ -115,28 +265,298 @@ impl EventHandle {
             "getch" => {
                 self.signal_tx.send(Request(GetCh(self.id)))?;
             },
+            "getln" => {
+                self.signal_tx.send(Request(GetLn(self.id)))?;
+            },
             "size" => {
                 self.signal_tx.send(Request(Size(self.id)))?;
             },
             "screen" => {
                 self.signal_tx.send(Request(Screen(self.id)))?;
             },
+            "screens" => {
+                self.signal_tx.send(Request(ListScreens(self.id)))?;
+            },
+            "last_error" => {
+                self.signal_tx.send(Request(LastError(self.id)))?;
+            },
             _ => return Ok(Reply::Empty)
         }
         let mut iter = self.event_rx.iter();
         loop {
-            if let Some(Msg::Response(r)) = iter.next() {
-                return Ok(r)
+            match iter.next() {
+                Some(Msg::Response(r)) => return Ok(r),
+                Some(_) => continue,
+                // The dispatcher shut down before replying.
+                None => return Ok(Reply::Empty),
+            }
+        }
+    }
+
+    pub async fn request_async(
+        &self, query: &str
+    ) -> Result<Reply, SendError<Cmd>> {
+        // Same as `request()` but awaits the reply instead of blocking.
+        // Resolves to Reply::Empty if the dispatcher shuts down first.
+        let query = match query {
+            "coord" => Coord(self.id),
+            #[cfg(unix)]
+            "raw_pos" => {
+                // Determine if the current screen is in raw mode.
+                self.signal_tx.send(Request(_IsRaw(self.id)))?;
+                let is_raw = match self.response().await {
+                    Some(Reply::_IsRaw(b)) => b,
+                    _ => return Ok(Reply::Empty),
+                };
+                // Set it to raw temporarily, if not in raw mode.
+                if !is_raw { self.signal_tx.send(Cmd::Signal(Action::Raw))? }
+                self.signal_tx.send(Request(Pos(self.id)))?;
+                loop {
+                    match self.next_event().await {
+                        Some(Msg::Received(InputEvent::CursorPos(col, row))) => {
+                            // Revert back to cooked mode.
+                            if !is_raw {
+                                self.signal_tx.send(Cmd::Signal(Action::Cook))?
+                            }
+                            return Ok(Reply::Pos(col, row))
+                        },
+                        Some(_) => continue,
+                        None => return Ok(Reply::Empty),
+                    }
+                }
+            },
+            #[cfg(windows)]
+            "raw_pos" => Pos(self.id),
+            "getch" => GetCh(self.id),
+            "getln" => GetLn(self.id),
+            "size" => Size(self.id),
+            "screen" => Screen(self.id),
+            "screens" => ListScreens(self.id),
+            "last_error" => LastError(self.id),
+            _ => return Ok(Reply::Empty)
+        };
+        self.signal_tx.send(Request(query))?;
+        match self.response().await {
+            Some(r) => Ok(r),
+            None => Ok(Reply::Empty),
+        }
+    }
+
+    async fn response(&self) -> Option<Reply> {
+        loop {
+            if let Msg::Response(r) = self.next_event().await? {
+                return Some(r)
+            }
+        }
+    }
+
+    pub fn snapshot(&self, screen: usize) -> Result<Reply, SendError<Cmd>> {
+        self.fetch(Snapshot(self.id, screen))
+    }
+
+    pub fn dump(
+        &self, screen: usize, format: Format
+    ) -> Result<Reply, SendError<Cmd>> {
+        self.fetch(Dump(self.id, screen, format))
+    }
+
+    fn fetch(&self, query: Query) -> Result<Reply, SendError<Cmd>> {
+        self.signal_tx.send(Request(query))?;
+        let mut iter = self.event_rx.iter();
+        loop {
+            match iter.next() {
+                Some(Msg::Response(r)) => return Ok(r),
+                Some(_) => continue,
+                // The dispatcher shut down before replying.
+                None => return Ok(Reply::Empty),
             }
         }
     }
 }
 
 
+impl Drop for EventHandle {
+    fn drop(&mut self) {
+        // Also releases the lock (if owned) and its regions.
+        let _ = self.signal_tx.send(Stop(self.id));
+    }
+}
+
+
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct LockGuard<'a> {
+    handle: &'a EventHandle,
+    ticket: usize,
+}
+
+impl<'a> Drop for LockGuard<'a> {
+    fn drop(&mut self) {
+        let handle = self.handle;
+        let _ = handle.signal_tx.send(Unlock(handle.id, Some(self.ticket)));
+    }
+}
+
+
+pub struct NextEvent<'a> {
+    handle: &'a EventHandle,
+}
+
+impl<'a> Future for NextEvent<'a> {
+    type Output = Option<Msg>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let handle = self.handle;
+        match handle.event_rx.try_recv() {
+            Ok(msg) => return Poll::Ready(Some(msg)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => (),
+        }
+        match handle.waker.lock() {
+            Ok(mut waker) => *waker = Some(cx.waker().clone()),
+            Err(_) => return Poll::Ready(None),
+        }
+        // (imdaveho) NOTE: Check again after registering the waker in case
+        // a Msg was sent in between (which would have found no waker).
+        handle.drain();
+        match handle.event_rx.try_recv() {
+            Ok(msg) => Poll::Ready(Some(msg)),
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+
+// The channel of an EventHandle (see `DispatcherBuilder::bound`), along with
+// the replies that are waiting for room in a bounded one.
+enum EventTx {
+    Unbounded(Sender<Msg>),
+    Bounded(SyncSender<Msg>, RefCell<VecDeque<Msg>>),
+}
+
 struct EventEmitter {
-    event_tx: Sender<Msg>,
+    event_tx: EventTx,
+    waker: Arc<Mutex<Option<Waker>>>,
+    filter: Filter,
     is_suspend: bool,
     is_running: bool,
+    // Write end of the readiness pipe of the EventHandle.
+    #[cfg(unix)]
+    notifier: Option<notify::Writer>,
+}
+
+impl EventEmitter {
+    fn new(
+        id: usize, signal_tx: Sender<Cmd>, bound: Option<usize>
+    ) -> (Self, EventHandle) {
+        let (event_tx, event_rx) = match bound {
+            Some(n) => {
+                let (tx, rx) = sync_channel(n);
+                (EventTx::Bounded(tx, RefCell::new(VecDeque::new())), rx)
+            },
+            None => {
+                let (tx, rx) = channel();
+                (EventTx::Unbounded(tx), rx)
+            },
+        };
+        let waker = Arc::new(Mutex::new(None));
+        let emitter = Self {
+            event_tx, waker: waker.clone(), filter: Filter::All,
+            is_suspend: false, is_running: true,
+            #[cfg(unix)]
+            notifier: None,
+        };
+        let handle = EventHandle {
+            id, event_rx, signal_tx, waker,
+            ticket: Cell::new(POS),
+            #[cfg(unix)]
+            notifier: RefCell::new(None),
+        };
+        (emitter, handle)
+    }
+
+    fn send(&self, msg: Msg) -> Result<(), SendError<Msg>> {
+        match &self.event_tx {
+            EventTx::Unbounded(tx) => tx.send(msg)?,
+            // (imdaveho) NOTE: This is called with the roster locked, so it
+            // must not block. Replies to the handle's own commands (and the
+            // cursor position of `raw_pos`) wait in line for room (see
+            // `flush`), everything else is dropped when the handle falls
+            // behind.
+            EventTx::Bounded(tx, pending) => {
+                self.flush();
+                let is_reply = matches!(msg, Response(_) | Locked
+                    | LockTimeout | Received(InputEvent::CursorPos(_, _)));
+                let mut pending = pending.borrow_mut();
+                // Keep the replies in order.
+                if !pending.is_empty() {
+                    if is_reply { pending.push_back(msg) }
+                    return Ok(())
+                }
+                match tx.try_send(msg) {
+                    Ok(_) => (),
+                    Err(TrySendError::Full(msg)) => {
+                        if is_reply { pending.push_back(msg) }
+                        return Ok(())
+                    },
+                    Err(TrySendError::Disconnected(msg)) =>
+                        return Err(SendError(msg)),
+                }
+            },
+        }
+        self.notify();
+        Ok(())
+    }
+
+    fn flush(&self) {
+        // Sends the replies that were waiting for room (if any).
+        let (tx, pending) = match &self.event_tx {
+            EventTx::Bounded(tx, pending) => (tx, pending),
+            EventTx::Unbounded(_) => return,
+        };
+        let mut pending = pending.borrow_mut();
+        let mut is_sent = false;
+        while let Some(msg) = pending.pop_front() {
+            match tx.try_send(msg) {
+                Ok(_) => is_sent = true,
+                Err(TrySendError::Full(msg)) => {
+                    pending.push_front(msg);
+                    break
+                },
+                Err(TrySendError::Disconnected(_)) => pending.clear(),
+            }
+        }
+        drop(pending);
+        if is_sent { self.notify() }
+    }
+
+    fn notify(&self) {
+        #[cfg(unix)]
+        if let Some(writer) = &self.notifier { writer.notify() }
+        // Wake the pending `next_event()` future (if any).
+        let waker = match self.waker.lock() {
+            Ok(mut w) => w.take(),
+            Err(_) => None,
+        };
+        if let Some(w) = waker { w.wake() }
+    }
+}
+
+impl Drop for EventEmitter {
+    fn drop(&mut self) {
+        // Resolve the pending `next_event()` future (if any) to None, eg. on
+        // shutdown or when the roster is cleared.
+        //
+        // (imdaveho) NOTE: The sender is closed before waking so that the
+        // future cannot be polled (and go back to pending) in between.
+        let (closed, _) = channel();
+        drop(std::mem::replace(&mut self.event_tx, EventTx::Unbounded(closed)));
+        let waker = match self.waker.lock() {
+            Ok(mut w) => w.take(),
+            Err(e) => e.into_inner().take(),
+        };
+        if let Some(w) = waker { w.wake() }
+    }
 }
 
 
@@ -146,6 +566,9 @@ pub struct Dispatcher {
     // the event_rx handle (single produce/ single consumer)
     input_handle: Option<thread::JoinHandle<()>>,
     emitters: Arc<Mutex<HashMap<usize, EventEmitter>>>,
+    // Stack of the focused handles (see `EventHandle::focus`) and whether
+    // they share mouse events with the unfocused ones.
+    focus: Arc<Mutex<Vec<(usize, bool)>>>,
     // Broadcast to select owner(s) of the lock.
     lock_owner: Arc<AtomicUsize>,
     // The Dispatcher can also signal commands that is handled
@@ -157,36 +580,101 @@ pub struct Dispatcher {
     // inner loop (SC).
     signal_tx: Sender<Cmd>,
     signal_handle: Option<thread::JoinHandle<()>>,
+    // Thread handle that accepts IPC connections (see `serve`).
+    server_handle: Option<thread::JoinHandle<()>>,
+    // Session file that input events are recorded into (see `record`),
+    // along with the terminal size kept up to date by the signal thread.
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    size: Arc<AtomicU32>,
+    // Whether the active screen uses extended mouse tracking (see
+    // `Action::SetMouse`), kept up to date by the signal thread.
+    is_mouse_mode: Arc<AtomicBool>,
+    // Regions that mouse events are hit-tested against (see `pointer`).
+    pointer: Arc<Pointer>,
+    // Gestures synthesized from mouse events (see `gesture`).
+    tracker: Arc<Tracker>,
+    // The last fatal error of an Action (see `on_error`).
+    last_error: Arc<Mutex<Option<Failure>>>,
     // Handle graceful shutdown and clean up.
-    is_running: Arc<AtomicBool>
+    is_running: Arc<AtomicBool>,
+    // Settings of the input and server threads (see `DispatcherBuilder`).
+    delay: Duration,
+    #[cfg(unix)]
+    tty: std::path::PathBuf,
+    bound: Option<usize>,
+    is_newline: bool,
+    // Whether this Dispatcher installed the signal handlers.
+    #[cfg(unix)]
+    is_signals: bool,
 }
 
 impl Dispatcher {
     pub fn init() -> Self {
+        // Starts with the default settings (see `DispatcherBuilder`).
+        DispatcherBuilder::new().build()
+    }
+
+    pub fn builder() -> DispatcherBuilder {
+        DispatcherBuilder::new()
+    }
+
+    fn start(builder: DispatcherBuilder) -> Self {
         // Initialize struct fields.
         let emitters = Arc::new(Mutex::new(HashMap::with_capacity(8)));
+        let focus = Arc::new(Mutex::new(Vec::new()));
         let is_running = Arc::new(AtomicBool::new(true));
         let lock_owner = Arc::new(AtomicUsize::new(0));
+        let size = Arc::new(AtomicU32::new(0));
+        let is_mouse_mode = Arc::new(AtomicBool::new(false));
+        let pointer = Arc::new(Pointer::new());
+        let tracker = Arc::new(Tracker::new());
+        let last_error = Arc::new(Mutex::new(None));
 
         // Setup Atomic References to move into thread.
         let emitters_ref = emitters.clone();
+        let focus_ref = focus.clone();
         let is_running_ref = is_running.clone();
         let lock_owner_ref = lock_owner.clone();
+        let size_ref = size.clone();
+        let is_mouse_mode_ref = is_mouse_mode.clone();
+        let pointer_ref = pointer.clone();
+        let tracker_ref = tracker.clone();
+        let last_error_ref = last_error.clone();
 
         // Fetch terminal default state in main thread.
         #[cfg(unix)]
-        let (col, row, tab_size) = match fetch_defaults() {
+        restore::capture();
+        #[cfg(unix)]
+        let (col, row, tab_size) = match fetch_defaults(builder.tab_size) {
             Ok((col, row, tab_size)) =>
                 (col, row, tab_size),
-            Err(e) => panic!("Error fetching terminal defaults: {:?}", e)
+            Err(e) => {
+                // Leave the terminal (and the signal handlers) as they were.
+                restore::restore();
+                panic!("Error fetching terminal defaults: {:?}", e)
+            }
         };
+        // Ctrl+Z in cooked mode suspends through the signal thread.
+        // (imdaveho) NOTE: Installed once nothing else can fail, as the
+        // handlers only set flags for the signal thread to act on.
+        #[cfg(unix)]
+        let is_signals = builder.is_signals && signals::install();
 
         #[cfg(windows)]
-        let (mode, reset, ansi, col, row, tab_size) = match fetch_defaults() {
+        let (mode, reset, ansi, col, row, tab_size) =
+            match fetch_defaults(builder.tab_size)
+        {
             Ok((mode, reset, ansi, col, row, tab_size)) =>
                 (mode, reset, ansi, col, row, tab_size),
             Err(e) => panic!("Error fetching terminal defaults: {:?}", e)
         };
+        #[cfg(windows)]
+        restore::capture(mode, reset, ansi);
+
+        let DispatcherBuilder {
+            delay, placeholder, is_alt, is_raw, is_mouse, is_cursor,
+            is_restore_cursor, bound, ..
+        } = builder;
 
          // Start signal loop.
         let (signal_tx, signal_rx) = channel();
@@ -199,13 +687,133 @@ impl Dispatcher {
             let (w, h) = term.size().expect("Error fetching terminal size.");
             let mut store = Store::new(w, h);
             store.sync_tab_size(tab_size);
+            store.sync_placeholder(placeholder);
             store.sync_goto(col, row);
+            // Enter the initial screen modes (see `DispatcherBuilder`).
+            let mut modes = Vec::new();
+            if is_alt { modes.push(Action::NewScreen) }
+            if is_raw { modes.push(Action::Raw) }
+            if is_mouse { modes.push(Action::EnableMouse) }
+            if !is_cursor { modes.push(Action::HideCursor) }
+            if !modes.is_empty() { modes.push(Action::Flush) }
+            // How the errors of each class are handled (see `on_error`).
+            let mut policies = Policies::new();
+            // (imdaveho) NOTE: No handle has set a Policy yet, so a failure
+            // here is fatal, and is kept for `Query::LastError`.
+            let mut is_ready = true;
+            for action in modes {
+                let name = wire::name(&action);
+                let result = handle_action(action, &mut term, &mut store);
+                if let Some(failure) = policies.resolve(name, result, || None) {
+                    report(&emitters_ref, &last_error_ref, failure);
+                    is_running_ref.store(false, Ordering::SeqCst);
+                    is_ready = false;
+                    break
+                }
+            }
+            if is_alt { store.sync_tab_size(tab_size) }
+            pointer_ref.screen.store(store.id(), Ordering::SeqCst);
+            size_ref.store(pack(store.size()), Ordering::SeqCst);
+            // Asciicast of the terminal output (see `cast`).
+            let mut cast: Option<Cast> = None;
+            // Handles waiting for the lock (see `lock`).
+            let mut lock_queue = LockQueue::new();
+            // How long the handles have to respond to a signal (see
+            // `grace`), and the first signal that is waiting for them.
+            #[cfg(unix)]
+            let mut grace = Duration::from_millis(GRACE);
+            #[cfg(unix)]
+            let mut teardown: Option<(SignalKind, std::time::Instant)> = None;
+            #[cfg(unix)]
+            let mut overdue: Option<SignalKind> = None;
 
             loop {
+                if !is_ready { break }
                 // Include minor delay so the thread isn't blindly using CPU.
-                thread::sleep(Duration::from_millis(DELAY));
+                thread::sleep(delay);
+                // Send the replies that were waiting for room (see `bound`).
+                if bound.is_some() {
+                    if let Ok(roster) = emitters_ref.lock() {
+                        for tx in roster.values() { tx.flush() }
+                    }
+                }
+                // Send the long presses that are due.
+                for msg in tracker_ref.tick(std::time::Instant::now()) {
+                    emit(&msg, &emitters_ref, &lock_owner_ref, &focus_ref,
+                        &pointer_ref)
+                }
+                // Withdraw the lock requests that timed out.
+                let now = std::time::Instant::now();
+                for id in lock_queue.expire(now) {
+                    send_to(&emitters_ref, id, LockTimeout)
+                }
+                // Give up on a cursor position that was never answered.
+                if let Some(id) = lock_queue.overdue(&lock_owner_ref, now) {
+                    send_to(&emitters_ref, id, Response(Reply::Empty));
+                    let next = lock_queue
+                        .unlock(&lock_owner_ref, id, Some(POS));
+                    if let Some(next) = next {
+                        send_to(&emitters_ref, next, Locked)
+                    }
+                }
+                // Ask for the cursor position of the `raw_pos` request that
+                // owns the lock (see `Query::Pos`).
+                #[cfg(unix)]
+                if let Some(id) = lock_queue.take_due() {
+                    let result = term.query_pos();
+                    let failure = policies.resolve(
+                        "raw_pos".to_string(), result, || {
+                            thread::sleep(delay);
+                            Some(term.query_pos())
+                        });
+                    if let Some(failure) = failure {
+                        let is_fatal = failure.is_fatal;
+                        report(&emitters_ref, &last_error_ref, failure);
+                        if is_fatal {
+                            is_running_ref.store(false, Ordering::SeqCst);
+                            break
+                        }
+                        // Nothing is coming, so stop waiting for it.
+                        send_to(&emitters_ref, id, Response(Reply::Empty));
+                        let next = lock_queue
+                            .unlock(&lock_owner_ref, id, Some(POS));
+                        if let Some(next) = next {
+                            send_to(&emitters_ref, next, Locked)
+                        }
+                    }
+                }
+                // Let the handles respond to signals before tearing down.
+                #[cfg(unix)]
+                for kind in signals::take_signals() {
+                    if let Ok(roster) = emitters_ref.lock() {
+                        for tx in roster.values() {
+                            let _ = tx.send(Msg::Signal(kind));
+                        }
+                    }
+                    if teardown.is_none() {
+                        let deadline = std::time::Instant::now() + grace;
+                        teardown = Some((kind, deadline))
+                    }
+                }
+                #[cfg(unix)]
+                if let Some((kind, deadline)) = teardown {
+                    if std::time::Instant::now() >= deadline {
+                        if let Ok(mut roster) = emitters_ref.lock() {
+                            roster.clear()
+                        }
+                        overdue = Some(kind);
+                        is_running_ref.store(false, Ordering::SeqCst);
+                        break
+                    }
+                }
                 // Handle signal commands.
-                match signal_rx.try_recv() {
+                #[cfg(unix)]
+                let received = if signals::take_suspend() {
+                    Ok(Cmd::Signal(Action::SuspendProcess))
+                } else { signal_rx.try_recv() };
+                #[cfg(windows)]
+                let received = signal_rx.try_recv();
+                match received {
                     Ok(cmd) => match cmd {
                         Continue => (),
 
@@ -257,41 +865,172 @@ impl Dispatcher {
                                     }
                                 },
                             };
-                            roster.entry(id)
-                                .and_modify(|tx: &mut EventEmitter| {
-                                    tx.is_running = false
-                                });
+                            // (imdaveho) NOTE: Dropping the emitter ends
+                            // `poll_sync` for the handle, rather than waiting
+                            // for the next input event to clean it up.
+                            roster.remove(&id);
+                            pointer_ref.release(id);
+                            // Hand off the lock (if owned) and focus.
+                            let next = lock_queue
+                                .unlock(&lock_owner_ref, id, None);
+                            if let Some(tx) = next.and_then(|n| roster.get(&n)) {
+                                let _ = tx.send(Locked);
+                            }
+                            if let Ok(mut stack) = focus_ref.lock() {
+                                stack.retain(|(f, _)| *f != id)
+                            }
                         },
 
-                        Lock(id) => {
-                            match lock_owner_ref.load(Ordering::SeqCst) {
-                                0 => lock_owner_ref
-                                    .store(id, Ordering::SeqCst),
-                                _ => {
-                                    is_running_ref.store(false, 
-                                        Ordering::SeqCst);
-                                    break
+                        #[cfg(unix)]
+                        Notify(id, writer) => {
+                            let mut roster = match emitters_ref.lock() {
+                                Ok(r) => r,
+                                Err(_) => match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => {
+                                        is_running_ref.store(false, 
+                                            Ordering::SeqCst);
+                                        break
+                                    }
                                 },
+                            };
+                            if let Some(tx) = roster.get_mut(&id) {
+                                // Msgs may have been queued before the
+                                // pipe was registered.
+                                writer.notify();
+                                tx.notifier = Some(writer);
+                            }
+                        },
+
+                        OnError(class, policy) => policies.set(class, policy),
+
+                        #[cfg(unix)]
+                        Grace(period) => grace = period,
+
+                        Cmd::Filter(id, filter) => {
+                            let mut roster = match emitters_ref.lock() {
+                                Ok(r) => r,
+                                Err(_) => match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => {
+                                        is_running_ref.store(false, 
+                                            Ordering::SeqCst);
+                                        break
+                                    }
+                                },
+                            };
+                            if let Some(tx) = roster.get_mut(&id) {
+                                tx.filter = filter
+                            }
+                        },
+
+                        AddRegion(id, region) => {
+                            pointer_ref.add(id, region)
+                        },
+
+                        RemoveRegion(id, name) => {
+                            pointer_ref.remove(id, &name)
+                        },
+
+                        Focus(id, share_mouse) => {
+                            if let Ok(mut stack) = focus_ref.lock() {
+                                // Move the handle to the top (if focused).
+                                stack.retain(|(f, _)| *f != id);
+                                stack.push((id, share_mouse))
+                            }
+                        },
+
+                        Blur(id) => {
+                            if let Ok(mut stack) = focus_ref.lock() {
+                                stack.retain(|(f, _)| *f != id)
                             }
                         },
 
-                        Unlock => {
-                            match lock_owner_ref.load(Ordering::SeqCst) {
-                                0 => continue,
-                                _ => lock_owner_ref
-                                    .store(0, Ordering::SeqCst),
+                        Lock(id, ticket, timeout) => {
+                            let deadline = timeout
+                                .map(|t| std::time::Instant::now() + t);
+                            let owner = lock_queue
+                                .lock(&lock_owner_ref, id, ticket, deadline);
+                            if let Some(owner) = owner {
+                                send_to(&emitters_ref, owner, Locked)
                             }
                         },
 
-                        Signal(action) => {
-                            match handle_action(action, &mut term, &mut store) {
-                                Ok(_) => (),
-                                Err(_) => {
-                                    is_running_ref.store(false, 
+                        Unlock(id, ticket) => {
+                            let next = lock_queue
+                                .unlock(&lock_owner_ref, id, ticket);
+                            if let Some(next) = next {
+                                send_to(&emitters_ref, next, Locked)
+                            }
+                        },
+
+                        Cmd::Signal(Action::StartCast(path)) => {
+                            let path = std::path::Path::new(&path);
+                            match Cast::create(path, &store) {
+                                Ok(c) => cast = Some(c),
+                                // Not being able to record is not fatal.
+                                Err(e) => report(&emitters_ref, &last_error_ref,
+                                    Failure {
+                                        action: "start_cast".to_string(),
+                                        class: ErrorClass::of(e.kind()),
+                                        details: e.to_string(),
+                                        is_fatal: false,
+                                    }),
+                            }
+                        },
+
+                        Cmd::Signal(Action::StopCast) => cast = None,
+
+                        Cmd::Signal(action) => {
+                            let output = match cast {
+                                Some(_) => Output::of(&action, &store),
+                                None => Output::Nothing,
+                            };
+                            // Keep what is needed to report (or retry) an
+                            // error, as the Action is consumed.
+                            let name = wire::name(&action);
+                            let is_suspend =
+                                matches!(action, Action::SuspendProcess);
+                            let backup = match policies.is_retry()
+                                && is_idempotent(&action)
+                            {
+                                true => Some(action.clone()),
+                                false => None,
+                            };
+                            let result = handle_action(
+                                action, &mut term, &mut store);
+                            let failure = policies.resolve(name, result, || {
+                                let action = backup.clone()?;
+                                thread::sleep(delay);
+                                Some(handle_action(
+                                    action, &mut term, &mut store))
+                            });
+                            if let Some(failure) = failure {
+                                let is_fatal = failure.is_fatal;
+                                report(&emitters_ref, &last_error_ref, failure);
+                                if is_fatal {
+                                    is_running_ref.store(false,
                                         Ordering::SeqCst);
                                     break
                                 }
+                            } else {
+                                if let Some(c) = cast.as_mut() {
+                                    c.write(output, &store)
+                                }
+                                if is_suspend {
+                                    if let Ok(roster) = emitters_ref.lock() {
+                                        for tx in roster.values() {
+                                            let _ = tx.send(Resumed);
+                                        }
+                                    }
+                                }
                             }
+                            // Keep the size up to date for recordings.
+                            size_ref.store(pack(store.size()), Ordering::SeqCst);
+                            is_mouse_mode_ref.store(
+                                store.mouse_mode().is_some(), Ordering::SeqCst);
+                            pointer_ref.screen.store(
+                                store.id(), Ordering::SeqCst);
                         },
 
                         Request(query) => match query {
@@ -309,7 +1048,7 @@ impl Dispatcher {
                                 };
                                 if let Some(tx) = roster.get(&id) {
                                     let (w, h) = store.size();
-                                    let _ = tx.event_tx.send(Response(
+                                    let _ = tx.send(Response(
                                         Reply::Size(w, h)));
                                 }
                             },
@@ -328,100 +1067,179 @@ impl Dispatcher {
                                 };
                                 if let Some(tx) = roster.get(&id) {
                                     let (col, row) = store.coord();
-                                    let _ = tx.event_tx.send(Response(
+                                    let _ = tx.send(Response(
                                         Reply::Coord(col, row)));
                                 }
                             },
 
                             #[cfg(unix)]
                             Pos(id) => {
-                                // Lock the receiver that requested pos:
-                                match lock_owner_ref.load(Ordering::SeqCst) {
-                                    0 => lock_owner_ref
-                                        .store(id, Ordering::SeqCst),
-                                    _ => continue,
+                                // Lock the receiver that requested pos, so
+                                // that only it is sent the answer. It waits
+                                // in line if another handle owns the lock.
+                                let _ = lock_queue
+                                    .lock(&lock_owner_ref, id, POS, None);
+                            },
+
+                            #[cfg(windows)]
+                            Pos(id) => {
+                                let mut pos = (0, 0);
+                                let result = term.pos().map(|c| pos = c);
+                                let failure = policies.resolve(
+                                    "raw_pos".to_string(), result, || {
+                                        thread::sleep(delay);
+                                        Some(term.pos().map(|c| pos = c))
+                                    });
+                                let reply = match failure {
+                                    None => Reply::Pos(pos.0, pos.1),
+                                    Some(failure) => {
+                                        let is_fatal = failure.is_fatal;
+                                        report(&emitters_ref, &last_error_ref,
+                                            failure);
+                                        if is_fatal {
+                                            is_running_ref.store(false,
+                                                Ordering::SeqCst);
+                                            break
+                                        }
+                                        Reply::Empty
+                                    },
+                                };
+                                send_to(&emitters_ref, id, Response(reply))
+                            },
+
+                            GetCh(id) => {
+                                let roster = match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => match emitters_ref.lock() {
+                                        Ok(r) => r,
+                                        Err(_) => {
+                                            is_running_ref.store(false,        
+                                                Ordering::SeqCst);
+                                            break
+                                        }
+                                    },
+                                };
+                                if let Some(tx) = roster.get(&id) {
+                                    let s = store.getch();
+                                    let _ = tx.send(Response(
+                                        Reply::GetCh(s)));
                                 }
-                                match term.query_pos() {
-                                    Ok(_) => (),
-                                    Err(_) => match term.query_pos() {
-                                        Ok(_) => (),
+                            },
+
+                            GetLn(id) => {
+                                let roster = match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => match emitters_ref.lock() {
+                                        Ok(r) => r,
                                         Err(_) => {
-                                            is_running_ref.store(false, 
+                                            is_running_ref.store(false,
                                                 Ordering::SeqCst);
                                             break
                                         }
-                                    }
+                                    },
+                                };
+                                if let Some(tx) = roster.get(&id) {
+                                    let s = store.getln();
+                                    let _ = tx.send(Response(
+                                        Reply::GetLn(s)));
                                 }
-                                // Now unlock the receiver after pos call:
-                                match lock_owner_ref.load(Ordering::SeqCst) {
-                                    0 => continue,
-                                    _ => lock_owner_ref
-                                        .store(0, Ordering::SeqCst),
+                            },
+
+                            Screen(id) => {
+                                let roster = match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => match emitters_ref.lock() {
+                                        Ok(r) => r,
+                                        Err(_) => {
+                                            is_running_ref.store(false,        
+                                                Ordering::SeqCst);
+                                            break
+                                        }
+                                    },
+                                };
+                                if let Some(tx) = roster.get(&id) {
+                                    let i = store.id();
+                                    let _ = tx.send(Response(
+                                        Reply::Screen(i)));
                                 }
                             },
 
-                            #[cfg(windows)]
-                            Pos(id) => {
+                            ListScreens(id) => {
                                 let roster = match emitters_ref.lock() {
                                     Ok(r) => r,
                                     Err(_) => match emitters_ref.lock() {
                                         Ok(r) => r,
                                         Err(_) => {
-                                            is_running_ref.store(false, 
+                                            is_running_ref.store(false,
                                                 Ordering::SeqCst);
                                             break
                                         }
                                     },
                                 };
                                 if let Some(tx) = roster.get(&id) {
-                                    let (col, row) = match term.pos() {
-                                        Ok(c) => (c.0, c.1),
+                                    let ids = store.ids();
+                                    let _ = tx.send(Response(
+                                        Reply::Screens(ids)));
+                                }
+                            },
+
+                            Snapshot(id, screen) => {
+                                let roster = match emitters_ref.lock() {
+                                    Ok(r) => r,
+                                    Err(_) => match emitters_ref.lock() {
+                                        Ok(r) => r,
                                         Err(_) => {
-                                            is_running_ref.store(false,        
+                                            is_running_ref.store(false,
                                                 Ordering::SeqCst);
                                             break
                                         }
-                                    };
-                                    let _ = tx.event_tx.send(Response(
-                                        Reply::Pos(col, row)));
+                                    },
+                                };
+                                if let Some(tx) = roster.get(&id) {
+                                    let snapshot = store.snapshot(screen);
+                                    let _ = tx.send(Response(
+                                        Reply::Snapshot(snapshot)));
                                 }
                             },
 
-                            GetCh(id) => {
+                            Dump(id, screen, format) => {
                                 let roster = match emitters_ref.lock() {
                                     Ok(r) => r,
                                     Err(_) => match emitters_ref.lock() {
                                         Ok(r) => r,
                                         Err(_) => {
-                                            is_running_ref.store(false,        
+                                            is_running_ref.store(false,
                                                 Ordering::SeqCst);
                                             break
                                         }
                                     },
                                 };
                                 if let Some(tx) = roster.get(&id) {
-                                    let s = store.getch();
-                                    let _ = tx.event_tx.send(Response(
-                                        Reply::GetCh(s)));
+                                    let s = store.dump(screen, format);
+                                    let _ = tx.send(Response(
+                                        Reply::Dump(s)));
                                 }
                             },
 
-                            Screen(id) => {
+                            LastError(id) => {
                                 let roster = match emitters_ref.lock() {
                                     Ok(r) => r,
                                     Err(_) => match emitters_ref.lock() {
                                         Ok(r) => r,
                                         Err(_) => {
-                                            is_running_ref.store(false,        
+                                            is_running_ref.store(false,
                                                 Ordering::SeqCst);
                                             break
                                         }
                                     },
                                 };
                                 if let Some(tx) = roster.get(&id) {
-                                    let i = store.id();
-                                    let _ = tx.event_tx.send(Response(
-                                        Reply::Screen(i)));
+                                    let last = match last_error_ref.lock() {
+                                        Ok(l) => l.clone(),
+                                        Err(_) => None,
+                                    };
+                                    let _ = tx.send(Response(
+                                        Reply::LastError(last)));
                                 }
                             },
 
@@ -440,7 +1258,7 @@ impl Dispatcher {
                                 };
                                 if let Some(tx) = roster.get(&id) {
                                     let b = store.is_raw();
-                                    let _ = tx.event_tx.send(Response(
+                                    let _ = tx.send(Response(
                                         Reply::_IsRaw(b)));
                                 }
                             }
@@ -455,13 +1273,37 @@ impl Dispatcher {
                     }
                 } // End match.
             } // End loop.
+            let _ = reset_modes(&mut term, &store);
+            // Put the cursor back to where it was at startup.
+            if is_restore_cursor {
+                if store.id() != 0 { let _ = term.disable_alt(); }
+                let _ = term.goto(col, row);
+                let _ = term.flush();
+            }
+            // The handles did not shut down in time after a signal, so
+            // restore the terminal and let the signal end the process.
+            #[cfg(unix)]
+            if let Some(kind) = overdue {
+                restore::restore();
+                signals::terminate(kind)
+            }
         }); // End signal thread.
         
         Self {
             input_handle: None,
-            emitters, lock_owner,
+            emitters, focus, lock_owner,
             signal_tx, is_running,
             signal_handle: Some(signal_handle),
+            server_handle: None,
+            recorder: Arc::new(Mutex::new(None)),
+            size, is_mouse_mode, pointer, tracker, last_error,
+            delay,
+            #[cfg(unix)]
+            tty: builder.tty,
+            bound: builder.bound,
+            is_newline: builder.is_newline,
+            #[cfg(unix)]
+            is_signals,
         }
     }
 
@@ -474,56 +1316,98 @@ impl Dispatcher {
         let is_running = self.is_running.clone();
         let lock_owner = self.lock_owner.clone();
         let emitters_ref = self.emitters.clone();
+        let focus = self.focus.clone();
+        let recorder = self.recorder.clone();
+        let size = self.size.clone();
+        #[cfg(unix)]
+        let is_mouse_mode = self.is_mouse_mode.clone();
+        let pointer = self.pointer.clone();
+        let tracker = self.tracker.clone();
+        let delay = self.delay;
+        #[cfg(unix)]
+        let tty = self.tty.clone();
+        #[cfg(unix)]
+        let last_error = self.last_error.clone();
+        #[cfg(unix)]
+        let signal_tx = self.signal_tx.clone();
 
         // Begin reading user input.
         #[cfg(unix)] {
         self.input_handle = Some(thread::spawn(move || {
+            // Whether the tty could not be opened the last time.
+            let mut is_failing = false;
             while is_running.load(Ordering::SeqCst) {
                 let tty = match std::fs::OpenOptions::new()
-                    .read(true).write(true).open("/dev/tty")
+                    .read(true).write(true).open(&tty)
                 {
-                    Ok(f) => std::io::BufReader::new(f),
-                    Err(_) => continue
-                };
-                let (mut input, mut taken) = (
-                    [0; 12], std::io::Read::take(tty, 12));
-                let _ = std::io::Read::read(&mut taken, &mut input);
-
-                // Emitters clean up.
-                let mut roster = match emitters_ref.lock() {
-                    Ok(r) => r,
-                    Err(_) => match emitters_ref.lock() {
-                        Ok(r) => r,
-                        Err(_) => continue
+                    Ok(f) => {
+                        is_failing = false;
+                        std::io::BufReader::new(f)
                     },
-                };
-                if !roster.is_empty() {
-                    roster.retain( |_, tx: &mut EventEmitter| {
-                        tx.is_running
-                    })
-                }
-                // Parse the user input from /dev/tty.
-                let item = input[0];
-                let mut rest = input[1..].to_vec().into_iter();
-                let evt = unix::parse_event(item, &mut rest);
-                // Push user input event.
-                match lock_owner.load(Ordering::SeqCst) {
-                    0 => {
-                        for (_, tx) in roster.iter() {
-                            if tx.is_suspend { continue }
-                            let _ = tx.event_tx.send(
-                                Received(evt.clone()));
+                    Err(e) => {
+                        // Report once, rather than for every attempt.
+                        if !is_failing {
+                            report(&emitters_ref, &last_error, Failure {
+                                action: "listen".to_string(),
+                                class: ErrorClass::of(e.kind()),
+                                details: e.to_string(),
+                                is_fatal: false,
+                            })
                         }
+                        is_failing = true;
+                        thread::sleep(delay);
+                        continue
                     },
-                    id => match roster.get(&id) {
-                        Some(tx) => {
-                            let _ = tx.event_tx.send(
-                                Received(evt.clone())); 
-                        },
-                        None => lock_owner.store(0, Ordering::SeqCst),
+                };
+                // (imdaveho) NOTE: Kitty key events with text can be longer
+                // than the legacy sequences.
+                let (mut input, mut taken) = (
+                    [0; 32], std::io::Read::take(tty, 32));
+                let n = std::io::Read::read(&mut taken, &mut input)
+                    .unwrap_or(0);
+
+                // Parse the user input from /dev/tty, one sequence at a time.
+                // (imdaveho) NOTE: Mouse events are left to the tuitty_core
+                // parser unless the active screen set extended tracking.
+                let is_mouse = is_mouse_mode.load(Ordering::SeqCst);
+                let mut bytes = &input[..n];
+                while !bytes.is_empty() {
+                    let parsed = input::csi_len(bytes).and_then(|len| {
+                        let seq = &bytes[..len];
+                        match input::parse_key(seq) {
+                            Some(key) => Some((Key(key), len)),
+                            None if is_mouse => input::parse_mouse(seq)
+                                .map(|mouse| (Mouse(mouse), len)),
+                            None => None,
+                        }
+                    });
+                    let (msg, len) = match parsed {
+                        Some(parsed) => parsed,
+                        None => {
+                            let mut rest = bytes[1..].to_vec().into_iter();
+                            let evt = unix::parse_event(bytes[0], &mut rest);
+                            (Received(evt), bytes.len() - rest.len())
+                        }
+                    };
+                    bytes = &bytes[len..];
+                    record(&recorder, &size, &msg);
+                    // Push user input event (and the gestures it completes).
+                    let at = std::time::Instant::now();
+                    emit(&msg, &emitters_ref, &lock_owner, &focus, &pointer);
+                    for gesture in tracker.feed(&msg, at) {
+                        emit(&gesture, &emitters_ref, &lock_owner, &focus,
+                            &pointer)
+                    }
+                    // The `raw_pos` request was answered (see `Query::Pos`).
+                    if let Received(InputEvent::CursorPos(_, _)) = msg {
+                        match lock_owner.load(Ordering::SeqCst) {
+                            0 => (),
+                            owner => { let _ = signal_tx
+                                .send(Unlock(owner, Some(POS))); },
+                        }
                     }
                 }
-                thread::sleep(Duration::from_millis(DELAY));
+                thread::sleep(delay);
             }
         }))}
 
@@ -532,93 +1416,167 @@ impl Dispatcher {
             while is_running.load(Ordering::SeqCst) {
                 let (_, evts) = windows::read_input_events();
                 for evt in evts {
-                    // Emitters clean up.
-                    let mut roster = match emitters_ref.lock() {
-                        Ok(r) => r,
-                        Err(_) => match emitters_ref.lock() {
-                            Ok(r) => r,
-                            Err(_) => continue
-                        },
-                    };
-                    if !roster.is_empty() {
-                        roster.retain( |_, tx: &mut EventEmitter| {
-                            tx.is_running
-                        })
-                    }
-                    // Push user input event.
-                    match lock_owner.load(Ordering::SeqCst) {
-                        0 => {
-                            for (_, tx) in roster.iter() {
-                                if tx.is_suspend { continue }
-                                let _ = tx.event_tx.send(
-                                    Received(evt.clone()));
-                            }
-                        },
-                        id => match roster.get(&id) {
-                            Some(tx) => { 
-                                let _ = tx.event_tx.send(
-                                    Received(evt.clone()));
-                            },
-                            None => lock_owner.store(0, Ordering::SeqCst),
-                        }
+                    let msg = Received(evt);
+                    record(&recorder, &size, &msg);
+                    // Push user input event (and the gestures it completes).
+                    let at = std::time::Instant::now();
+                    emit(&msg, &emitters_ref, &lock_owner, &focus, &pointer);
+                    for gesture in tracker.feed(&msg, at) {
+                        emit(&gesture, &emitters_ref, &lock_owner, &focus,
+                            &pointer)
                     }
                 }
-                thread::sleep(Duration::from_millis(DELAY));
+                thread::sleep(delay);
             }
         }))}
 
         self.spawn()
     }
 
-    fn randomish(&self) -> usize {
-        match self.emitters.lock() {
-            Ok(senders) => {
-                let mut key: usize;
-                loop {
-                    key = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Error fetching duration since 1970")
-                        .subsec_nanos() as usize;
-                    if key == 0 { continue }
-                    if !senders.contains_key(&key) { break }
+    pub fn replay<P: AsRef<std::path::Path>>(
+        &mut self, path: P, speed: f64
+    ) -> std::io::Result<EventHandle> {
+        // Feeds the events of a recorded session to all EventHandles instead
+        // of reading the terminal. A speed of 1.0 keeps the original pace,
+        // 2.0 is twice as fast, and 0.0 replays without any delay.
+        if self.input_handle.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other,
+                "Error: the dispatcher is already listening for input"))
+        }
+        let entries = session::load(path.as_ref())?;
+
+        let is_running = self.is_running.clone();
+        let lock_owner = self.lock_owner.clone();
+        let emitters_ref = self.emitters.clone();
+        let focus = self.focus.clone();
+        let size = self.size.clone();
+        let signal_tx = self.signal_tx.clone();
+        let pointer = self.pointer.clone();
+        let tracker = self.tracker.clone();
+
+        self.input_handle = Some(thread::spawn(move || {
+            // Long presses follow the recording too (see `gesture`).
+            tracker.sync_replay(true);
+            let start = std::time::Instant::now();
+            for entry in entries {
+                if !is_running.load(Ordering::SeqCst) { break }
+                if speed > 0.0 {
+                    let due = entry.elapsed.div_f64(speed);
+                    let elapsed = start.elapsed();
+                    if due > elapsed { thread::sleep(due - elapsed) }
+                }
+                // Match the size of the Store at the time of the recording
+                // (but leave the size of the real terminal alone).
+                if pack(entry.size) != size.load(Ordering::SeqCst) {
+                    let (w, h) = entry.size;
+                    let _ = signal_tx.send(Cmd::Signal(Action::SyncSize(w, h)));
+                }
+                emit(&entry.msg, &emitters_ref, &lock_owner, &focus, &pointer);
+                // Gestures keep the timing of the recording.
+                for gesture in tracker.feed(&entry.msg, start + entry.elapsed) {
+                    emit(&gesture, &emitters_ref, &lock_owner, &focus, &pointer)
                 }
-                key
-            },
-            Err(_) => {
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Error fetching duration since 1970")
-                    .subsec_nanos() as usize
             }
+            tracker.sync_replay(false);
+        }));
+
+        Ok(self.spawn())
+    }
+
+    pub fn record<P: AsRef<std::path::Path>>(
+        &self, path: P
+    ) -> std::io::Result<()> {
+        // Records every InputEvent read by the input thread into a session
+        // file (see `session`) until `stop_recording` is called.
+        let recorder = Recorder::create(path.as_ref())?;
+        match self.recorder.lock() {
+            Ok(mut r) => { *r = Some(recorder); Ok(()) },
+            Err(_) => Err(std::io::Error::new(std::io::ErrorKind::Other,
+                "Error: the recorder is poisoned")),
+        }
+    }
+
+    pub fn stop_recording(&self) {
+        if let Ok(mut r) = self.recorder.lock() { *r = None }
+    }
+
+    pub fn gestures(&self, thresholds: Thresholds) {
+        // Synthesizes `Msg::Gesture` (eg. double clicks and drags) from the
+        // mouse events, sent right after the events that complete them,
+        // until `stop_gestures` is called.
+        self.tracker.set(Some(thresholds))
+    }
+
+    pub fn stop_gestures(&self) {
+        self.tracker.set(None)
+    }
+
+    pub fn restore_on_panic(&self) {
+        // Installs a panic hook that restores the terminal (to the modes
+        // from before `init`) before the panic message is printed.
+        //
+        // (imdaveho) NOTE: The hook cannot tell whether a panic is caught
+        // with `std::panic::catch_unwind`. Use `dispatcher::catch_unwind`
+        // instead to recover from a panic and keep drawing (the C ABI
+        // already does).
+        restore::hook()
+    }
+
+    pub fn restore_on_exit(&self) {
+        // Restores the terminal if the process exits without dropping the
+        // Dispatcher (eg. `std::process::exit`).
+        restore::register()
+    }
+
+    pub fn on_error(
+        &self, class: ErrorClass, policy: Policy
+    ) -> Result<(), SendError<Cmd>> {
+        // Sets how the errors of an Action are handled. The handles are
+        // sent `Msg::Error` either way. By default, every error shuts down
+        // the dispatcher (see `last_error`).
+        self.signal_tx.send(OnError(class, policy))
+    }
+
+    #[cfg(unix)]
+    pub fn grace(&self, period: Duration) -> Result<(), SendError<Cmd>> {
+        // Sets how long the handles have to respond to a `Msg::Signal` (eg.
+        // by shutting down) before the dispatcher restores the terminal,
+        // shuts down and lets the signal end the process (1s by default).
+        self.signal_tx.send(Grace(period))
+    }
+
+    pub fn last_error(&self) -> Option<Failure> {
+        // The error that shut down the dispatcher (if any). Errors that are
+        // not fatal are only sent to the handles as `Msg::Error`.
+        match self.last_error.lock() {
+            Ok(last) => last.clone(),
+            Err(_) => None,
         }
     }
 
     pub fn spawn(&self) -> EventHandle {
-        // let err_msg = "Error obtaining emitter registry lock";
-        let (event_tx, event_rx) = channel();
-        let id = self.randomish();
-        let (is_suspend, is_running) = (false, true);
-        match self.emitters.lock() {
-            Ok(mut roster) => {
-                roster.insert(id, EventEmitter{
-                    event_tx, is_suspend, is_running
-                });
-            },
-            Err(_) => match self.emitters.lock() {
-                Ok(mut roster) => {
-                    roster.insert(id, EventEmitter{
-                        event_tx, is_suspend, is_running 
-                    });
-                },
-                Err(e) => {}
-            },
-        };
-        let signal_tx = self.signal_tx.clone(); 
-        EventHandle { id, event_rx, signal_tx }
+        register(&self.emitters, &self.signal_tx, self.bound)
+    }
+
+    #[cfg(unix)]
+    pub fn serve<P: AsRef<std::path::Path>>(
+        &mut self, path: P
+    ) -> std::io::Result<()> {
+        // Listen for IPC connections on a Unix domain socket at `path`.
+        // Each connection is given its own EventHandle (see `server`).
+        if self.server_handle.is_some() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other,
+                "Error: the dispatcher is already serving"))
+        }
+        self.server_handle = Some(server::serve(
+            path.as_ref(), self.emitters.clone(),
+            self.signal_tx.clone(), self.is_running.clone(),
+            self.delay, self.bound)?);
+        Ok(())
     }
 
     pub fn signal(&self, action: Action) -> Result<(), SendError<Cmd>> {
-        self.signal_tx.send(Signal(action))
+        self.signal_tx.send(Cmd::Signal(action))
     }
 
     fn shutdown(&mut self) -> std::thread::Result<()> {
@@ -639,11 +1597,15 @@ impl Dispatcher {
                 Err(e) => {}
             },
         }
+        if let Some(t) = self.server_handle.take() { t.join()? }
         if let Some(t) = self.signal_handle.take() { t.join()? }
         // (imdaveho) NOTE: `term` should have drop/closed when the
         // signal_handle joined/finished.
+        restore::release();
+        #[cfg(unix)]
+        if self.is_signals { signals::uninstall() }
 
-        println!("\r\n");
+        if self.is_newline { println!("\r\n") }
 
         Ok(())
     }
@@ -656,28 +1618,525 @@ impl Drop for Dispatcher {
 }
 
 
+pub struct DispatcherBuilder {
+    // How long the threads sleep between polls.
+    delay: Duration,
+    // Where input is read from.
+    #[cfg(unix)]
+    tty: std::path::PathBuf,
+    // The tab size, or None to probe the terminal by printing a tab.
+    tab_size: Option<usize>,
+    placeholder: char,
+    // Initial screen modes.
+    is_alt: bool,
+    is_raw: bool,
+    is_mouse: bool,
+    is_cursor: bool,
+    // How many Msgs each EventHandle can queue, or None for no limit.
+    bound: Option<usize>,
+    // Whether to print a newline on shutdown.
+    is_newline: bool,
+    // Whether to move the cursor back to where it was on shutdown.
+    is_restore_cursor: bool,
+    // Whether to handle SIGTSTP, SIGINT, SIGTERM and SIGHUP (see `signals`).
+    #[cfg(unix)]
+    is_signals: bool,
+}
+
+impl Default for DispatcherBuilder {
+    fn default() -> Self {
+        // The settings of `Dispatcher::init`.
+        DispatcherBuilder {
+            delay: Duration::from_millis(DELAY),
+            #[cfg(unix)]
+            tty: std::path::PathBuf::from("/dev/tty"),
+            tab_size: None,
+            placeholder: '🚧',
+            is_alt: false,
+            is_raw: false,
+            is_mouse: false,
+            is_cursor: true,
+            bound: None,
+            is_newline: true,
+            is_restore_cursor: false,
+            #[cfg(unix)]
+            is_signals: true,
+        }
+    }
+}
+
+impl DispatcherBuilder {
+    pub fn new() -> DispatcherBuilder {
+        DispatcherBuilder::default()
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        // Lower delays are more responsive but use more CPU.
+        self.delay = delay;
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn tty<P: AsRef<std::path::Path>>(mut self, path: P) -> Self {
+        // Reads input from `path` (eg. a pty) instead of /dev/tty.
+        self.tty = path.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn tab_size(mut self, n: usize) -> Self {
+        // Skips probing the terminal (which prints a tab at the cursor).
+        self.tab_size = Some(n);
+        self
+    }
+
+    pub fn placeholder(mut self, ch: char) -> Self {
+        // The (wide) glyph that complex characters are rendered as.
+        self.placeholder = ch;
+        self
+    }
+
+    pub fn alt_screen(mut self, state: bool) -> Self {
+        // Starts on a new alternate screen (see `Action::NewScreen`).
+        self.is_alt = state;
+        self
+    }
+
+    pub fn raw(mut self, state: bool) -> Self {
+        self.is_raw = state;
+        self
+    }
+
+    pub fn mouse(mut self, state: bool) -> Self {
+        self.is_mouse = state;
+        self
+    }
+
+    pub fn cursor(mut self, state: bool) -> Self {
+        // Whether the cursor is shown.
+        self.is_cursor = state;
+        self
+    }
+
+    pub fn bound(mut self, n: usize) -> Self {
+        // Limits how many Msgs each EventHandle can queue. Once a handle
+        // falls that far behind, further input events and broadcasts are
+        // dropped for it. Replies to its own commands and requests
+        // (including the cursor position of `raw_pos`) are kept and sent
+        // as it catches up, without blocking the dispatcher.
+        self.bound = Some(n.max(1));
+        self
+    }
+
+    pub fn newline_on_exit(mut self, state: bool) -> Self {
+        self.is_newline = state;
+        self
+    }
+
+    pub fn restore_cursor(mut self, state: bool) -> Self {
+        // Moves the cursor back to where it was at startup on shutdown.
+        self.is_restore_cursor = state;
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn signals(mut self, state: bool) -> Self {
+        // Leaves the signal handlers of the host alone when false, in which
+        // case there is no `Msg::Signal` and Ctrl+Z (in cooked mode) stops
+        // the process without restoring the terminal.
+        self.is_signals = state;
+        self
+    }
+
+    pub fn build(self) -> Dispatcher {
+        Dispatcher::start(self)
+    }
+}
+
+
+fn pack((w, h): (i16, i16)) -> u32 {
+    ((w as u16 as u32) << 16) | h as u16 as u32
+}
+
+fn record(
+    recorder: &Mutex<Option<Recorder>>, size: &AtomicU32, msg: &Msg
+) {
+    if let Ok(mut r) = recorder.lock() {
+        if let Some(r) = r.as_mut() {
+            let size = size.load(Ordering::SeqCst);
+            r.record(((size >> 16) as i16, size as u16 as i16), msg);
+        }
+    }
+}
+
+fn emit(
+    msg: &Msg,
+    emitters: &Mutex<HashMap<usize, EventEmitter>>,
+    lock_owner: &AtomicUsize,
+    focus: &Mutex<Vec<(usize, bool)>>,
+    pointer: &Pointer,
+) {
+    // Emitters clean up.
+    let mut roster = match emitters.lock() {
+        Ok(r) => r,
+        Err(_) => match emitters.lock() {
+            Ok(r) => r,
+            Err(_) => return
+        },
+    };
+    if !roster.is_empty() {
+        roster.retain( |_, tx: &mut EventEmitter| {
+            tx.is_running
+        })
+    }
+    // Handles that can receive the event.
+    let eligible: Vec<usize> = match lock_owner.load(Ordering::SeqCst) {
+        0 => {
+            let active: Vec<usize> = roster.iter()
+                .filter(|(_, tx)| !tx.is_suspend)
+                .map(|(id, _)| *id)
+                .collect();
+            // The topmost focused handle that is not suspended (if any).
+            let focused = match focus.lock() {
+                Ok(stack) => stack.iter().rev()
+                    .find(|(id, _)| active.contains(id))
+                    .copied(),
+                Err(_) => None,
+            };
+            let is_mouse = matches!(msg, Received(InputEvent::Mouse(_))
+                | Mouse(_) | Gesture(_, _));
+            match (focused, msg) {
+                (None, _) => active,
+                // Cursor positions are replies to `raw_pos` requests.
+                (_, Received(InputEvent::CursorPos(_, _))) => active,
+                (Some((_, true)), _) if is_mouse => active,
+                (Some((id, _)), _) => vec![id],
+            }
+        },
+        id => match roster.contains_key(&id) {
+            true => vec![id],
+            false => {
+                lock_owner.store(0, Ordering::SeqCst);
+                return
+            },
+        }
+    };
+    // Push user input event.
+    for id in eligible.iter() {
+        if let Some(tx) = roster.get(id) {
+            if !tx.filter.accepts(msg) { continue }
+            let _ = tx.send(msg.clone());
+        }
+    }
+    // Tag mouse events with the region that was hit (if any).
+    pointer.dispatch(msg, &roster, &eligible);
+}
+
+fn send_to(
+    emitters: &Mutex<HashMap<usize, EventEmitter>>, id: usize, msg: Msg
+) {
+    if let Ok(roster) = emitters.lock() {
+        if let Some(tx) = roster.get(&id) { let _ = tx.send(msg); }
+    }
+}
+
+fn randomish(emitters: &Mutex<HashMap<usize, EventEmitter>>) -> usize {
+    match emitters.lock() {
+        Ok(senders) => {
+            let mut key: usize;
+            loop {
+                key = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Error fetching duration since 1970")
+                    .subsec_nanos() as usize;
+                if key == 0 { continue }
+                if !senders.contains_key(&key) { break }
+            }
+            key
+        },
+        Err(_) => {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Error fetching duration since 1970")
+                .subsec_nanos() as usize
+        }
+    }
+}
+
+fn register(
+    emitters: &Mutex<HashMap<usize, EventEmitter>>,
+    signal_tx: &Sender<Cmd>,
+    bound: Option<usize>,
+) -> EventHandle {
+    // Adds a new emitter to the roster and returns its EventHandle.
+    let id = randomish(emitters);
+    let (emitter, handle) = EventEmitter::new(id, signal_tx.clone(), bound);
+    match emitters.lock() {
+        Ok(mut roster) => {
+            roster.insert(id, emitter);
+        },
+        Err(_) => match emitters.lock() {
+            Ok(mut roster) => {
+                roster.insert(id, emitter);
+            },
+            Err(_) => {}
+        },
+    };
+    handle
+}
+
+
 #[cfg(unix)]
-fn fetch_defaults() -> std::io::Result<(i16, i16, usize)> {
+fn fetch_defaults(
+    tab_size: Option<usize>
+) -> std::io::Result<(i16, i16, usize)> {
     let term = Term::new()?;
     term.raw()?;
     let (col, row) = term.raw_pos()?;
-    term.printf("\t")?;
-    let (tab_col, _) = term.raw_pos()?;
+    let tab_size = match tab_size {
+        Some(n) => n,
+        None => {
+            term.printf("\t")?;
+            let (tab_col, _) = term.raw_pos()?;
+            term.printf("\r")?;
+            (tab_col - col) as usize
+        },
+    };
     term.cook()?;
-    let tab_size = (tab_col - col) as usize;
-    term.printf("\r")?;
     Ok((col, row, tab_size))
 }
 
 
 #[cfg(windows)]
-fn fetch_defaults() -> std::io::Result<(u32, u16, bool, i16, i16, usize)> {
+fn fetch_defaults(
+    tab_size: Option<usize>
+) -> std::io::Result<(u32, u16, bool, i16, i16, usize)> {
     let term = Term::new()?;
     let (mode, reset, ansi) = term.init_data();
     let (col, row) = term.pos()?;
-    term.printf("\t")?;
-    let (tab_col, _) = term.pos()?;
-    let tab_size = (tab_col - col) as usize;
-    term.printf("\r")?;
+    let tab_size = match tab_size {
+        Some(n) => n,
+        None => {
+            term.printf("\t")?;
+            let (tab_col, _) = term.pos()?;
+            term.printf("\r")?;
+            (tab_col - col) as usize
+        },
+    };
     Ok((mode, reset, ansi, col, row, tab_size))
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_next_event_wakes() {
+        let (signal_tx, _signal_rx) = channel();
+        let (emitter, handle) = EventEmitter::new(1, signal_tx, None);
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut future = handle.next_event();
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        assert!(!flag.0.load(Ordering::SeqCst));
+
+        // Sending a Msg wakes the pending future:
+        emitter.send(Unsupported).unwrap();
+        assert!(flag.0.load(Ordering::SeqCst));
+        match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(Some(Unsupported)) => (),
+            _ => panic!("Expected the Msg to be ready"),
+        }
+
+        // Dropping the emitter wakes the pending future, which resolves
+        // to None:
+        flag.0.store(false, Ordering::SeqCst);
+        let mut future = handle.next_event();
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+        drop(emitter);
+        assert!(flag.0.load(Ordering::SeqCst));
+        match Pin::new(&mut future).poll(&mut cx) {
+            Poll::Ready(None) => (),
+            _ => panic!("Expected the stream to have ended"),
+        }
+    }
+
+    #[test]
+    fn test_bounded_emitter() {
+        let (signal_tx, _signal_rx) = channel();
+        let (emitter, handle) = EventEmitter::new(1, signal_tx, Some(1));
+        emitter.send(Unsupported).unwrap();
+        // A handle that falls behind misses events:
+        emitter.send(Enter("panel".into())).unwrap();
+        assert!(matches!(handle.poll_async(), Some(Unsupported)));
+        assert!(handle.poll_async().is_none());
+        emitter.send(Locked).unwrap();
+        assert!(matches!(handle.poll_async(), Some(Locked)));
+        // Replies wait for room (in order) instead of blocking:
+        emitter.send(Unsupported).unwrap();
+        emitter.send(LockTimeout).unwrap();
+        emitter.send(Locked).unwrap();
+        emitter.send(Enter("panel".into())).unwrap();
+        assert!(matches!(handle.poll_async(), Some(Unsupported)));
+        emitter.flush();
+        assert!(matches!(handle.poll_async(), Some(LockTimeout)));
+        emitter.send(Enter("panel".into())).unwrap();
+        assert!(matches!(handle.poll_async(), Some(Locked)));
+        assert!(handle.poll_async().is_none());
+        // Until it is gone:
+        drop(handle);
+        assert!(emitter.send(Unsupported).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bounded_raw_pos() {
+        let (signal_tx, signal_rx) = channel();
+        let (emitter, handle) = EventEmitter::new(1, signal_tx, Some(1));
+        let wait_for = |is_cmd: &dyn Fn(&Cmd) -> bool| loop {
+            match signal_rx.recv_timeout(Duration::from_millis(1)) {
+                Ok(cmd) => if is_cmd(&cmd) { break },
+                Err(_) => emitter.flush(),
+            }
+        };
+        // The queue is full when the position is requested:
+        emitter.send(Unsupported).unwrap();
+        let requester = thread::spawn(move || handle.request("raw_pos"));
+        wait_for(&|cmd| matches!(cmd, Request(_IsRaw(1))));
+        emitter.send(Response(Reply::_IsRaw(true))).unwrap();
+        wait_for(&|cmd| matches!(cmd, Request(Pos(1))));
+        emitter.send(Unsupported).unwrap();
+        emitter.send(Received(InputEvent::CursorPos(3, 4))).unwrap();
+        while !requester.is_finished() {
+            emitter.flush();
+            thread::sleep(Duration::from_millis(1));
+        }
+        match requester.join().unwrap() {
+            Ok(Reply::Pos(3, 4)) => (),
+            _ => panic!("Expected the cursor position"),
+        }
+    }
+
+    #[test]
+    fn test_lock_guards() {
+        let (signal_tx, signal_rx) = channel();
+        let (_emitter, handle) = EventEmitter::new(1, signal_tx, None);
+        // Each guard releases its own ticket only:
+        let first = handle.lock().unwrap();
+        let second = handle.lock_timeout(Duration::from_millis(5)).unwrap();
+        assert!(matches!(signal_rx.try_recv(), Ok(Lock(1, 1, None))));
+        assert!(matches!(signal_rx.try_recv(), Ok(Lock(1, 2, Some(_)))));
+        drop(first);
+        assert!(matches!(signal_rx.try_recv(), Ok(Unlock(1, Some(1)))));
+        drop(second);
+        assert!(matches!(signal_rx.try_recv(), Ok(Unlock(1, Some(2)))));
+        handle.unlock().unwrap();
+        assert!(matches!(signal_rx.try_recv(), Ok(Unlock(1, None))));
+    }
+
+    #[test]
+    fn test_emit_filters() {
+        use crate::tuitty_core::common::enums::{ KeyEvent, MouseEvent, MouseButton };
+        let (signal_tx, _signal_rx) = channel();
+        let emitters = Mutex::new(HashMap::new());
+        let lock_owner = AtomicUsize::new(0);
+        let focus = Mutex::new(Vec::new());
+        let pointer = Pointer::new();
+        let (mut keys, keys_handle) =
+            EventEmitter::new(1, signal_tx.clone(), None);
+        let (mut area, area_handle) =
+            EventEmitter::new(2, signal_tx.clone(), None);
+        let (mut odd, odd_handle) = EventEmitter::new(3, signal_tx, None);
+        keys.filter = Filter::Keys(vec![KeyEvent::Enter, KeyEvent::Esc]);
+        area.filter = Filter::Region(5, 5, 10, 2);
+        odd.filter = Filter::Custom(Box::new(|evt| match evt {
+            InputEvent::Mouse(MouseEvent::Release(col, _)) => col % 2 == 1,
+            _ => false,
+        }));
+        {
+            let mut roster = emitters.lock().unwrap();
+            roster.insert(1, keys);
+            roster.insert(2, area);
+            roster.insert(3, odd);
+        }
+
+        let events = [
+            InputEvent::Keyboard(KeyEvent::Char('a')),
+            InputEvent::Keyboard(KeyEvent::Enter),
+            InputEvent::Mouse(MouseEvent::Press(MouseButton::Left, 5, 6)),
+            InputEvent::Mouse(MouseEvent::Release(15, 6)),
+            InputEvent::Mouse(MouseEvent::Hold(14, 4)),
+            InputEvent::CursorPos(0, 0),
+        ];
+        for evt in events.iter() {
+            emit(&Received(evt.clone()), &emitters, &lock_owner, &focus,
+                &pointer)
+        }
+
+        let count = |handle: &EventHandle| {
+            let mut n = 0;
+            while handle.poll_async().is_some() { n += 1 }
+            n
+        };
+        // Enter and CursorPos.
+        assert_eq!(count(&keys_handle), 2);
+        // Press(5, 6) and CursorPos.
+        assert_eq!(count(&area_handle), 2);
+        // Release(15, 6) and CursorPos.
+        assert_eq!(count(&odd_handle), 2);
+    }
+
+    #[test]
+    fn test_emit_focus() {
+        use crate::tuitty_core::common::enums::{ KeyEvent, MouseEvent, MouseButton };
+        let (signal_tx, _signal_rx) = channel();
+        let emitters = Mutex::new(HashMap::new());
+        let lock_owner = AtomicUsize::new(0);
+        let pointer = Pointer::new();
+        let mut handles = Vec::new();
+        {
+            let mut roster = emitters.lock().unwrap();
+            for id in 1..=3 {
+                let (emitter, handle) =
+                    EventEmitter::new(id, signal_tx.clone(), None);
+                roster.insert(id, emitter);
+                handles.push(handle);
+            }
+        }
+        let key = Received(InputEvent::Keyboard(KeyEvent::Enter));
+        let click = Received(InputEvent::Mouse(
+            MouseEvent::Press(MouseButton::Left, 0, 0)));
+        let received = |focus: &Mutex<Vec<(usize, bool)>>, msg: &Msg| {
+            emit(msg, &emitters, &lock_owner, focus, &pointer);
+            handles.iter()
+                .map(|h| std::iter::from_fn(|| h.poll_async()).count())
+                .collect::<Vec<usize>>()
+        };
+
+        // Without focus, input is broadcast:
+        let focus = Mutex::new(Vec::new());
+        assert_eq!(received(&focus, &key), [1, 1, 1]);
+        // A dialog (2) over a dialog (3) that shares the mouse:
+        let focus = Mutex::new(vec![(3, true), (2, false)]);
+        assert_eq!(received(&focus, &key), [0, 1, 0]);
+        assert_eq!(received(&focus, &click), [0, 1, 0]);
+        // Suspended handles are skipped (as if the dialog was popped):
+        emitters.lock().unwrap().get_mut(&2).unwrap().is_suspend = true;
+        assert_eq!(received(&focus, &key), [0, 0, 1]);
+        assert_eq!(received(&focus, &click), [1, 0, 1]);
+        // The lock still takes precedence:
+        lock_owner.store(1, Ordering::SeqCst);
+        assert_eq!(received(&focus, &key), [1, 0, 0]);
+    }
+}
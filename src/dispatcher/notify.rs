@@ -0,0 +1,136 @@
+// This module provides a self-pipe that foreign event loops (eg. asyncio,
+// libuv) can add to their selector. The write end is kept by the emitter and
+// a byte is written whenever a Msg is queued. The read end is kept by the
+// EventHandle and becomes readable until it is drained.
+//
+// The "pipe" is a socketpair so that writing after the EventHandle (and its
+// read end) has been dropped fails with EPIPE instead of raising SIGPIPE,
+// which would otherwise end a host process that has not ignored it.
+//
+// (imdaveho) NOTE: The pipe is only a readiness hint. A readable fd may be
+// spurious (eg. the Msg was already consumed by `poll_sync`) so callers should
+// keep calling `poll_async` until it returns None.
+use std::io::{ Error, Result };
+use std::os::unix::io::RawFd;
+use libc::{ c_void, c_int, F_GETFL, F_SETFL, F_SETFD, FD_CLOEXEC, O_NONBLOCK };
+
+
+pub struct Reader(RawFd);
+
+pub struct Writer(RawFd);
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const SEND_FLAGS: c_int = 0;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const SEND_FLAGS: c_int = libc::MSG_NOSIGNAL;
+
+pub fn pipe() -> Result<(Reader, Writer)> {
+    let mut fds: [c_int; 2] = [0; 2];
+    if unsafe { libc::socketpair(
+        libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()
+    ) } == -1 {
+        return Err(Error::last_os_error())
+    }
+    // Wrap the fds first so that they are closed if configuring them fails.
+    let (reader, writer) = (Reader(fds[0]), Writer(fds[1]));
+    for &fd in fds.iter() {
+        unsafe {
+            let flags = libc::fcntl(fd, F_GETFL);
+            if flags == -1
+                || libc::fcntl(fd, F_SETFL, flags | O_NONBLOCK) == -1
+                || libc::fcntl(fd, F_SETFD, FD_CLOEXEC) == -1 {
+                return Err(Error::last_os_error())
+            }
+        }
+    }
+    // (imdaveho) NOTE: macOS has no MSG_NOSIGNAL, the socket option
+    // does the same for every send on the write end.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    unsafe {
+        let on: c_int = 1;
+        if libc::setsockopt(writer.0, libc::SOL_SOCKET, libc::SO_NOSIGPIPE,
+            &on as *const c_int as *const c_void,
+            std::mem::size_of::<c_int>() as libc::socklen_t) == -1 {
+            return Err(Error::last_os_error())
+        }
+    }
+    Ok((reader, writer))
+}
+
+impl Reader {
+    pub fn fd(&self) -> RawFd {
+        self.0
+    }
+
+    pub fn drain(&self) {
+        // Read until the pipe would block (or is closed).
+        let mut buf = [0u8; 64];
+        loop {
+            let n = unsafe {
+                libc::read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len())
+            };
+            if n <= 0 { break }
+        }
+    }
+}
+
+impl Writer {
+    pub fn notify(&self) {
+        // A full pipe is already readable and a closed one has nobody to
+        // notify, so the error can be ignored.
+        let byte = [1u8];
+        unsafe {
+            libc::send(self.0, byte.as_ptr() as *const c_void, 1, SEND_FLAGS);
+        }
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0); }
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0); }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_readable(fd: RawFd) -> bool {
+        let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(&mut pfd, 1, 0) == 1 }
+    }
+
+    #[test]
+    fn test_notify_pipe() {
+        let (reader, writer) = pipe().unwrap();
+        assert!(!is_readable(reader.fd()));
+        writer.notify();
+        writer.notify();
+        assert!(is_readable(reader.fd()));
+        reader.drain();
+        assert!(!is_readable(reader.fd()));
+        // Draining an empty pipe does not block.
+        reader.drain();
+    }
+
+    #[test]
+    fn test_notify_closed() {
+        // (imdaveho) NOTE: The test harness ignores SIGPIPE (as every Rust
+        // binary does), so put back the default that a C host would have.
+        let (reader, writer) = pipe().unwrap();
+        drop(reader);
+        unsafe {
+            let previous = libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+            writer.notify();
+            writer.notify();
+            libc::signal(libc::SIGPIPE, previous);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// This module provides the hit-testing of mouse events against the Regions
+// that EventHandles register (see `EventHandle::add_region`).
+//
+// After a mouse event is emitted as usual, the owner of the topmost Region
+// under the pointer also receives `Msg::Region` with the event in region
+// coordinates. With motion tracking (see `Action::SetMouse`), the owners
+// receive `Msg::Enter` and `Msg::Leave` as the pointer crosses Regions.
+use std::{
+    collections::HashMap,
+    sync::{ Mutex, atomic::{ AtomicUsize, Ordering } },
+};
+use super::EventEmitter;
+use super::message::{ Msg, Mouse, MouseKind, Region };
+use crate::tuitty_core::common::enums::InputEvent;
+
+
+pub struct Pointer {
+    // Id of the active screen, kept up to date by the signal thread.
+    pub screen: AtomicUsize,
+    // Regions and the ids of their owners (in the order they were added).
+    regions: Mutex<Vec<(usize, Region)>>,
+    // Owner and name of the Region under the pointer.
+    hover: Mutex<Option<(usize, String)>>,
+}
+
+impl Pointer {
+    pub fn new() -> Pointer {
+        Pointer {
+            screen: AtomicUsize::new(0),
+            regions: Mutex::new(Vec::new()),
+            hover: Mutex::new(None),
+        }
+    }
+
+    pub fn add(&self, owner: usize, region: Region) {
+        if let Ok(mut regions) = self.regions.lock() {
+            // Replace a Region of the same name.
+            regions.retain(|(id, r)| *id != owner || r.name != region.name);
+            regions.push((owner, region));
+        }
+    }
+
+    pub fn remove(&self, owner: usize, name: &str) {
+        if let Ok(mut regions) = self.regions.lock() {
+            regions.retain(|(id, r)| *id != owner || r.name != name);
+        }
+    }
+
+    pub fn release(&self, owner: usize) {
+        // Drops every Region of a handle that has gone away.
+        if let Ok(mut regions) = self.regions.lock() {
+            regions.retain(|(id, _)| *id != owner);
+        }
+        if let Ok(mut hover) = self.hover.lock() {
+            if matches!(*hover, Some((id, _)) if id == owner) { *hover = None }
+        }
+    }
+
+    fn hit(
+        &self, col: i16, row: i16, eligible: &[usize]
+    ) -> Option<(usize, Region)> {
+        let screen = self.screen.load(Ordering::SeqCst);
+        let regions = self.regions.lock().ok()?;
+        let mut topmost: Option<&(usize, Region)> = None;
+        for entry in regions.iter() {
+            let (owner, region) = entry;
+            if !eligible.contains(owner) { continue }
+            if region.screen.is_some() && region.screen != Some(screen) { continue }
+            if !region.contains(col, row) { continue }
+            // Later Regions win ties.
+            match topmost {
+                Some((_, top)) if top.z > region.z => (),
+                _ => topmost = Some(entry),
+            }
+        }
+        topmost.cloned()
+    }
+
+    pub fn dispatch(
+        &self,
+        msg: &Msg,
+        roster: &HashMap<usize, EventEmitter>,
+        eligible: &[usize],
+    ) {
+        let mouse = match msg {
+            Msg::Received(InputEvent::Mouse(mv)) => Mouse::from_legacy(mv),
+            Msg::Mouse(mouse) => Some(mouse.clone()),
+            _ => None,
+        };
+        let mouse = match mouse {
+            Some(m) => m,
+            None => return,
+        };
+        if let Ok(mut regions) = self.regions.lock() {
+            // Drop the Regions of handles that have gone away.
+            regions.retain(|(owner, _)| roster.contains_key(owner));
+        }
+        let hit = self.hit(mouse.col, mouse.row, eligible);
+        // Only to handles whose Filter lets the mouse event through.
+        let send = |owner: &usize, region_msg: Msg| {
+            if let Some(tx) = roster.get(owner) {
+                if tx.filter.accepts(msg) { let _ = tx.send(region_msg); }
+            }
+        };
+
+        // Motion updates the Region under the pointer.
+        if let MouseKind::Move | MouseKind::Drag = mouse.kind {
+            if let Ok(mut hover) = self.hover.lock() {
+                let current = hit.as_ref()
+                    .map(|(owner, region)| (*owner, region.name.to_string()));
+                if *hover != current {
+                    if let Some((owner, name)) = hover.take() {
+                        send(&owner, Msg::Leave(name))
+                    }
+                    if let Some((owner, name)) = current.as_ref() {
+                        send(owner, Msg::Enter(name.to_string()))
+                    }
+                    *hover = current;
+                }
+            }
+        }
+
+        if let Some((owner, region)) = hit {
+            let mut local = mouse;
+            local.col -= region.col;
+            local.row -= region.row;
+            send(&owner, Msg::Region(region.name, local))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use crate::tuitty_core::common::enums::{ MouseEvent, MouseButton };
+    use super::super::message::{ Button, Filter };
+
+    #[test]
+    fn test_pointer_regions() {
+        let (signal_tx, _signal_rx) = channel();
+        let (a, a_handle) = EventEmitter::new(1, signal_tx.clone(), None);
+        let (b, b_handle) = EventEmitter::new(2, signal_tx, None);
+        let mut roster = HashMap::new();
+        roster.insert(1, a);
+        roster.insert(2, b);
+        let eligible = [1, 2];
+
+        let pointer = Pointer::new();
+        pointer.add(1, Region::new("panel", 0, 0, 10, 10));
+        pointer.add(2, Region { z: 1, ..Region::new("button", 2, 2, 4, 2) });
+        pointer.add(2, Region {
+            screen: Some(3), z: 9, ..Region::new("other", 0, 0, 10, 10)
+        });
+
+        let press = Msg::Received(InputEvent::Mouse(
+            MouseEvent::Press(MouseButton::Left, 3, 3)));
+        pointer.dispatch(&press, &roster, &eligible);
+        match b_handle.poll_async() {
+            Some(Msg::Region(name, mouse)) => {
+                assert_eq!(name, "button");
+                assert_eq!((mouse.col, mouse.row), (1, 1));
+                assert_eq!(mouse.button, Some(Button::Left));
+            },
+            _ => panic!("Expected the topmost region to be hit"),
+        }
+        assert!(a_handle.poll_async().is_none());
+
+        // Moving out of the button and into the panel:
+        let motion = |col, row| Msg::Mouse(Mouse {
+            kind: MouseKind::Move, button: None, col, row, modifiers: 0,
+        });
+        pointer.dispatch(&motion(3, 3), &roster, &eligible);
+        assert!(matches!(b_handle.poll_async(), Some(Msg::Enter(_))));
+        assert!(matches!(b_handle.poll_async(), Some(Msg::Region(_, _))));
+        pointer.dispatch(&motion(8, 8), &roster, &eligible);
+        match b_handle.poll_async() {
+            Some(Msg::Leave(name)) => assert_eq!(name, "button"),
+            _ => panic!("Expected to leave the button"),
+        }
+        match a_handle.poll_async() {
+            Some(Msg::Enter(name)) => assert_eq!(name, "panel"),
+            _ => panic!("Expected to enter the panel"),
+        }
+        assert!(matches!(a_handle.poll_async(), Some(Msg::Region(_, _))));
+
+        // Regions of other handles are not hit while one holds the lock:
+        pointer.dispatch(&press, &roster, &[1]);
+        match a_handle.poll_async() {
+            Some(Msg::Region(name, _)) => assert_eq!(name, "panel"),
+            _ => panic!("Expected the panel to be hit"),
+        }
+        assert!(b_handle.poll_async().is_none());
+        pointer.remove(1, "panel");
+        pointer.dispatch(&press, &roster, &[1]);
+        assert!(a_handle.poll_async().is_none());
+
+        // Nor are the Regions of handles that filter out mouse events:
+        if let Some(tx) = roster.get_mut(&2) { tx.filter = Filter::Keyboard }
+        pointer.dispatch(&press, &roster, &eligible);
+        assert!(b_handle.poll_async().is_none());
+        if let Some(tx) = roster.get_mut(&2) { tx.filter = Filter::All }
+
+        // Nor those of a handle that has been released:
+        pointer.release(2);
+        pointer.dispatch(&press, &roster, &eligible);
+        assert!(b_handle.poll_async().is_none());
+    }
+}
@@ -0,0 +1,222 @@
+// This module resolves how the errors of Actions are handled (see
+// `Dispatcher::on_error`).
+//
+// Each ErrorClass has its own Policy, which is `Shutdown` by default. A
+// failed Action is retried (at most n times) under `Retry(n)` if it can be
+// applied again without changing the outcome (see `is_idempotent`), and the
+// Failure that remains is reported to every handle as `Msg::Error` and kept
+// for `Query::LastError` if it is fatal (unless the Policy is `Ignore`).
+use std::{ collections::HashMap, io::Result, sync::Mutex };
+use super::EventEmitter;
+use super::message::{ ErrorClass, Policy, Failure, Msg, Action };
+
+
+pub struct Policies {
+    classes: [(ErrorClass, Policy); 3],
+}
+
+impl Policies {
+    pub fn new() -> Policies {
+        Policies { classes: [
+            (ErrorClass::Disconnected, Policy::Shutdown),
+            (ErrorClass::Interrupted, Policy::Shutdown),
+            (ErrorClass::Other, Policy::Shutdown),
+        ]}
+    }
+
+    pub fn set(&mut self, class: ErrorClass, policy: Policy) {
+        for (c, p) in self.classes.iter_mut() {
+            if *c == class { *p = policy }
+        }
+    }
+
+    pub fn get(&self, class: ErrorClass) -> Policy {
+        self.classes.iter()
+            .find(|(c, _)| *c == class)
+            .map_or(Policy::Shutdown, |(_, p)| *p)
+    }
+
+    pub fn is_retry(&self) -> bool {
+        // Whether the Action needs to be kept around for a retry.
+        self.classes.iter().any(|(_, p)| matches!(p, Policy::Retry(_)))
+    }
+
+    pub fn resolve(
+        &self,
+        action: String,
+        result: Result<()>,
+        mut retry: impl FnMut() -> Option<Result<()>>,
+    ) -> Option<Failure> {
+        // Returns the Failure of the Action (if it did not succeed in the
+        // end). `retry` handles the Action again, or returns None if it
+        // cannot be retried.
+        let mut result = result;
+        let mut attempts = 0;
+        loop {
+            let e = match result {
+                Ok(_) => return None,
+                Err(e) => e,
+            };
+            let class = ErrorClass::of(e.kind());
+            let policy = self.get(class);
+            if let Policy::Retry(n) = policy {
+                if attempts < n {
+                    if let Some(r) = retry() {
+                        attempts += 1;
+                        result = r;
+                        continue
+                    }
+                }
+            }
+            return Some(Failure {
+                action, class,
+                details: e.to_string(),
+                is_fatal: policy != Policy::Ignore,
+            })
+        }
+    }
+}
+
+pub fn is_idempotent(action: &Action) -> bool {
+    // (imdaveho) NOTE: An Action that failed may have already updated the
+    // Store (eg. `Prints` moves the cursor), so only the ones that set a
+    // state (rather than change it) are safe to handle again.
+    use Action::*;
+    matches!(action,
+        Goto(_, _) | Clear(_) | Flush | Render | Resize(_, _)
+        | SetFx(_) | SetFg(_) | SetBg(_) | SetStyles(_, _, _) | ResetStyles
+        | HideCursor | ShowCursor | EnableMouse | DisableMouse
+        | SetMouse(_, _) | Raw | Cook | SwitchTo(_) | Resized
+        | SyncSize(_, _) | SyncMarker(_, _) | Jump | SyncTabSize(_)
+        | SyncBidi(_) | Restore(_))
+}
+
+pub fn report(
+    emitters: &Mutex<HashMap<usize, EventEmitter>>,
+    last_error: &Mutex<Option<Failure>>,
+    failure: Failure,
+) {
+    // Only the error that shut down the dispatcher is kept.
+    if failure.is_fatal {
+        if let Ok(mut last) = last_error.lock() {
+            *last = Some(failure.clone())
+        }
+    }
+    if let Ok(mut roster) = emitters.lock() {
+        for tx in roster.values() {
+            let _ = tx.send(Msg::Error(failure.clone()));
+        }
+        // (imdaveho) NOTE: Dropping the emitters lets the handles know that
+        // no reply is coming, rather than waiting on it.
+        if failure.is_fatal { roster.clear() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ Error, ErrorKind };
+    use std::sync::mpsc::channel;
+
+    fn interrupted() -> Result<()> {
+        Err(Error::new(ErrorKind::Interrupted, "Error: interrupted"))
+    }
+
+    #[test]
+    fn test_policy_resolve() {
+        let mut policies = Policies::new();
+        assert!(!policies.is_retry());
+
+        // Shutdown by default:
+        let failure = policies.resolve("flush".into(), interrupted(), || {
+            panic!("Expected no retry")
+        }).unwrap();
+        assert_eq!(failure.action, "flush");
+        assert_eq!(failure.class, ErrorClass::Interrupted);
+        assert!(failure.is_fatal);
+        assert!(policies.resolve("flush".into(), Ok(()), || None).is_none());
+
+        // Ignore is reported but not fatal:
+        policies.set(ErrorClass::Interrupted, Policy::Ignore);
+        let failure = policies.resolve("flush".into(), interrupted(), || {
+            panic!("Expected no retry")
+        }).unwrap();
+        assert!(!failure.is_fatal);
+
+        // Retry(n) gives up after n more attempts:
+        policies.set(ErrorClass::Interrupted, Policy::Retry(2));
+        assert!(policies.is_retry());
+        let mut attempts = 0;
+        let failure = policies.resolve("flush".into(), interrupted(), || {
+            attempts += 1;
+            Some(interrupted())
+        }).unwrap();
+        assert_eq!(attempts, 2);
+        assert!(failure.is_fatal);
+        // Or stops once an attempt succeeds:
+        let mut attempts = 0;
+        let failure = policies.resolve("flush".into(), interrupted(), || {
+            attempts += 1;
+            Some(if attempts < 2 { interrupted() } else { Ok(()) })
+        });
+        assert_eq!(attempts, 2);
+        assert!(failure.is_none());
+        // Or when the Action cannot be retried:
+        let failure = policies.resolve("flush".into(), interrupted(), || None);
+        assert!(failure.unwrap().is_fatal);
+
+        // Other classes keep their own Policy:
+        let failure = policies.resolve("flush".into(),
+            Err(Error::new(ErrorKind::BrokenPipe, "Error: gone")), || {
+                panic!("Expected no retry")
+            }).unwrap();
+        assert_eq!(failure.class, ErrorClass::Disconnected);
+        assert!(failure.is_fatal);
+
+        // Only the Actions that are safe to repeat are retried:
+        assert!(is_idempotent(&Action::Flush));
+        assert!(is_idempotent(&Action::Goto(1, 2)));
+        assert!(!is_idempotent(&Action::Prints("a".into())));
+        assert!(!is_idempotent(&Action::Up(1)));
+        assert!(!is_idempotent(&Action::NewScreen));
+    }
+
+    #[test]
+    fn test_policy_report() {
+        let (signal_tx, _signal_rx) = channel();
+        let emitters = Mutex::new(HashMap::new());
+        let last_error = Mutex::new(None);
+        let mut handles = Vec::new();
+        for id in 1..=2 {
+            let (emitter, handle) =
+                EventEmitter::new(id, signal_tx.clone(), None);
+            emitters.lock().unwrap().insert(id, emitter);
+            handles.push(handle);
+        }
+        let failure = |is_fatal| Failure {
+            action: "flush".into(), class: ErrorClass::Other,
+            details: "Error: failed".into(), is_fatal,
+        };
+
+        // Every handle is sent the error:
+        report(&emitters, &last_error, failure(false));
+        for handle in handles.iter() {
+            assert_eq!(handle.poll_async(), Some(Msg::Error(failure(false))));
+        }
+        assert_eq!(*last_error.lock().unwrap(), None);
+        assert_eq!(emitters.lock().unwrap().len(), 2);
+
+        // A fatal error is kept for LastError and hangs up on the handles:
+        report(&emitters, &last_error, failure(true));
+        for handle in handles.iter() {
+            assert_eq!(handle.poll_async(), Some(Msg::Error(failure(true))));
+            assert_eq!(handle.poll_sync(), None);
+        }
+        assert_eq!(*last_error.lock().unwrap(), Some(failure(true)));
+        assert!(emitters.lock().unwrap().is_empty());
+        // And is not replaced by later errors that are not fatal:
+        report(&emitters, &last_error, failure(false));
+        assert_eq!(*last_error.lock().unwrap(), Some(failure(true)));
+    }
+}
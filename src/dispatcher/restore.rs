@@ -0,0 +1,187 @@
+// This module restores the terminal when the Dispatcher does not get to
+// drop (eg. a panic in another thread, `panic = "abort"` or a call to
+// `std::process::exit`), see `Dispatcher::restore_on_panic` and
+// `Dispatcher::restore_on_exit`.
+//
+// The original modes are captured at `Dispatcher::init` (before raw mode is
+// entered) and restored along with turning off everything that the
+// dispatcher may have turned on: mouse tracking, bracketed paste, kitty
+// keyboard flags, hidden cursor, styles and the alternate screen.
+//
+// (imdaveho) NOTE: This does not go through the `Term` of the signal thread,
+// which may be the one that panicked (or be in the middle of a write).
+// Panics that are caught with `catch_unwind` (including at the C ABI) keep
+// the terminal as it is.
+use std::{
+    cell::Cell, panic::{ self, UnwindSafe }, thread::Result,
+    sync::{ Mutex, atomic::{ AtomicBool, Ordering } },
+};
+use super::router::MOUSE_RESET;
+#[cfg(windows)]
+use crate::tuitty_core::terminal::Term;
+
+#[cfg(unix)]
+static DEFAULTS: Mutex<Option<libc::termios>> = Mutex::new(None);
+#[cfg(windows)]
+static DEFAULTS: Mutex<Option<(u32, u16, bool)>> = Mutex::new(None);
+
+// Whether a Dispatcher is running (and has not restored the terminal).
+static IS_ACTIVE: AtomicBool = AtomicBool::new(false);
+static IS_HOOKED: AtomicBool = AtomicBool::new(false);
+static IS_REGISTERED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // How many `catch_unwind` calls this thread is inside of.
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(windows)]
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> std::os::raw::c_int;
+}
+#[cfg(unix)]
+use libc::atexit;
+
+
+#[cfg(unix)]
+pub fn capture() {
+    use std::os::unix::io::AsRawFd;
+    IS_ACTIVE.store(true, Ordering::SeqCst);
+    let mut defaults = match DEFAULTS.lock() {
+        Ok(d) => d,
+        Err(e) => e.into_inner(),
+    };
+    // Keep the modes from before the first Dispatcher.
+    if defaults.is_some() { return }
+    let tty = match std::fs::File::open("/dev/tty") {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(tty.as_raw_fd(), &mut termios) } == 0 {
+        *defaults = Some(termios)
+    }
+}
+
+#[cfg(windows)]
+pub fn capture(mode: u32, reset: u16, ansi: bool) {
+    IS_ACTIVE.store(true, Ordering::SeqCst);
+    let mut defaults = match DEFAULTS.lock() {
+        Ok(d) => d,
+        Err(e) => e.into_inner(),
+    };
+    if defaults.is_none() { *defaults = Some((mode, reset, ansi)) }
+}
+
+pub fn release() {
+    // The Dispatcher shut down (and restored the terminal) normally.
+    IS_ACTIVE.store(false, Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+pub fn restore() {
+    use std::{ io::Write, os::unix::io::AsRawFd };
+    if !IS_ACTIVE.swap(false, Ordering::SeqCst) { return }
+    let defaults = match DEFAULTS.lock() {
+        Ok(d) => *d,
+        Err(e) => *e.into_inner(),
+    };
+    let mut tty = match std::fs::OpenOptions::new()
+        .read(true).write(true).open("/dev/tty")
+    {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+    let _ = write!(tty, "{}\x1B[?1015l\x1B[?2004l\x1B[=0;1u\x1B[0m\x1B[?25h\
+        \x1B[?1049l", MOUSE_RESET);
+    let _ = tty.flush();
+    if let Some(termios) = defaults {
+        unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSANOW, &termios); }
+    }
+}
+
+#[cfg(windows)]
+pub fn restore() {
+    if !IS_ACTIVE.swap(false, Ordering::SeqCst) { return }
+    let defaults = match DEFAULTS.lock() {
+        Ok(d) => *d,
+        Err(e) => *e.into_inner(),
+    };
+    let (mode, reset, ansi) = match defaults {
+        Some(d) => d,
+        None => return,
+    };
+    let mut term = match Term::new() {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    term.with(mode, reset, ansi);
+    if ansi { let _ = term.printf(&format!("{}\x1B[?2004l", MOUSE_RESET)); }
+    let _ = term.disable_mouse();
+    let _ = term.reset_styles();
+    let _ = term.show_cursor();
+    let _ = term.disable_alt();
+    let _ = term.cook();
+    let _ = term.flush();
+}
+
+pub fn hook() {
+    // Restores the terminal before the panic message is printed, unless the
+    // panic is going to be caught by `catch_unwind`.
+    if IS_HOOKED.swap(true, Ordering::SeqCst) { return }
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING.with(|c| c.get()) == 0 { restore() }
+        previous(info)
+    }));
+}
+
+pub fn catch_unwind<T>(f: impl FnOnce() -> T + UnwindSafe) -> Result<T> {
+    // `std::panic::catch_unwind` that keeps the terminal as it is (see
+    // `hook`), as the caller recovers from the panic.
+    CATCHING.with(|c| c.set(c.get() + 1));
+    let result = panic::catch_unwind(f);
+    CATCHING.with(|c| c.set(c.get() - 1));
+    result
+}
+
+extern "C" fn on_exit() {
+    restore()
+}
+
+pub fn register() {
+    // Restores the terminal when the process exits without dropping the
+    // Dispatcher (eg. `std::process::exit`).
+    if IS_REGISTERED.swap(true, Ordering::SeqCst) { return }
+    unsafe { atexit(on_exit); }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_lifecycle() {
+        // Captured while a Dispatcher runs, released when it shuts down:
+        #[cfg(unix)]
+        capture();
+        #[cfg(windows)]
+        capture(0, 0, true);
+        assert!(IS_ACTIVE.load(Ordering::SeqCst));
+        release();
+        assert!(!IS_ACTIVE.load(Ordering::SeqCst));
+        // So there is nothing left to restore:
+        restore();
+        assert!(!IS_ACTIVE.load(Ordering::SeqCst));
+
+        // Panics that are caught keep the terminal as it is:
+        IS_ACTIVE.store(true, Ordering::SeqCst);
+        hook();
+        let result = catch_unwind(|| panic!("Caught"));
+        assert!(result.is_err());
+        assert!(IS_ACTIVE.load(Ordering::SeqCst));
+        assert_eq!(CATCHING.with(|c| c.get()), 0);
+        release();
+    }
+}
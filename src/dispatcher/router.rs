@@ -2,8 +2,12 @@ use crate::tuitty_core::terminal::Term;
 use crate::tuitty_core::common::enums::{
     Color::*, Effect, Clear, Style
 };
-use crate::store::Store;
-use super::message::Action::{*, self};
+use crate::store::{ Store, bidi };
+use super::message::{ Action::{*, self}, MouseLevel, MouseEncoding };
+
+// Turns off every extended mouse tracking mode and encoding.
+pub const MOUSE_RESET: &str =
+    "\x1B[?1003l\x1B[?1002l\x1B[?1000l\x1B[?1016l\x1B[?1006l";
 
 
 // pub fn match_signal(action: Action, term: &mut Term, store: &mut Store) {
@@ -11,6 +15,24 @@ pub fn handle_action(
     action: Action, term: &mut Term, store: &mut Store
 ) -> std::io::Result<()> {
     match action {
+        // Handled by the signal thread.
+        StartCast(_) | StopCast => Ok(()),
+
+        // (imdaveho) NOTE: The flags are kept per Screen in the Store and
+        // the active ones are set (rather than pushed) on the terminal, as
+        // all of the alternate screens share one stack in the terminal.
+        PushKeyboard(flags) => {
+            store.push_keyboard(flags);
+            sync_keyboard(term, store)?;
+            term.flush()
+        },
+
+        PopKeyboard => {
+            store.pop_keyboard();
+            sync_keyboard(term, store)?;
+            term.flush()
+        },
+
         Goto(col, row) => {
             // Prevent out-of-bounds.
             let (w, h) = store.size();
@@ -75,7 +97,10 @@ pub fn handle_action(
         },
         
         Prints(s) => {
-            term.prints(&s)?;
+            // In bidi mode, the terminal receives the same visual order
+            // that is stored in the screen buffer.
+            if store.is_bidi() { term.prints(&bidi::reorder(&s))? }
+            else { term.prints(&s)? }
             store.sync_content(&s);
             Ok(())
         },
@@ -134,17 +159,31 @@ pub fn handle_action(
         },
 
         EnableMouse => {
+            if store.is_mouse_mode_used() { term.printf(MOUSE_RESET)? }
             term.enable_mouse()?;
             store.sync_mouse(true);
+            store.sync_mouse_mode(None);
             Ok(())
         },
 
         DisableMouse => {
+            if store.is_mouse_mode_used() { term.printf(MOUSE_RESET)? }
             term.disable_mouse()?;
             store.sync_mouse(false);
+            store.sync_mouse_mode(None);
             Ok(())
         },
 
+        SetMouse(level, encoding) => {
+            if level == MouseLevel::Off {
+                return handle_action(DisableMouse, term, store)
+            }
+            store.sync_mouse(true);
+            store.sync_mouse_mode(Some((level, encoding)));
+            set_mouse_mode(term, level, encoding)?;
+            term.flush()
+        },
+
         // EnableAlt => term.enable_alt(),
         // DisableAlt => term.disable_alt(),
 
@@ -175,37 +214,39 @@ pub fn handle_action(
             term.flush()?;
             Ok(())
         },
-        SwitchTo(id) => {
-            let current = store.id();
+        SwitchTo(id) => switch_to(id, term, store),
+
+        CloseScreen(id) => {
             // Bounds checking:
-            if current == id { return Ok(()) }
-            if store.exists(id) { store.set(id)? }
-            else { return Ok(()) }
-            // Handle screen switch:
-            // Disable if you are reverting back to main.
-            if id == 0 { term.disable_alt()? }
-            else {
-                // Enable as you are on the main screen
-                // switching to alternate.
-                if current == 0 { term.enable_alt()? }
-                term.clear(Clear::All)?;
+            if id == 0 || !store.exists(id) { return Ok(()) }
+            // Fall back to the previous screen if closing the active one.
+            if store.id() == id {
+                let previous = store.previous();
+                switch_to(previous, term, store)?;
             }
-            if id != 0 { store.render(&term)? }
-            // Restore settings based on metadata.
-            let (raw, mouse, show) = (
-                store.is_raw(),
-                store.is_mouse(),
-                store.is_cursor() );
+            store.close(id)
+        },
 
-            if raw { term.raw()? }
-            else { term.cook()? }
-            if mouse { term.enable_mouse()? }
-            else { term.disable_mouse()? }
-            if show { term.show_cursor()? }
-            else { term.hide_cursor()? }
-            #[cfg(unix)]
-            term.flush()?;
-            Ok(())
+        PushScreen(id) => {
+            // Bounds checking:
+            if store.id() == id || !store.exists(id) { return Ok(()) }
+            store.push();
+            switch_to(id, term, store)
+        },
+
+        PopScreen => match store.pop() {
+            // The raw, mouse, and cursor modes of the previous
+            // screen are restored by switching back to it.
+            Some(id) => switch_to(id, term, store),
+            None => Ok(()),
+        },
+
+        Restore(snapshot) => {
+            store.restore(&snapshot);
+            term.clear(Clear::All)?;
+            store.render(&term)?;
+            // Restore settings based on metadata.
+            sync_modes(term, store)
         },
 
         // INTERNAL BUFFER UPDATES
@@ -214,8 +255,125 @@ pub fn handle_action(
             store.sync_size(w, h);
             Ok(())
         },
+        SyncSize(w, h) => Ok(store.sync_size(w, h)),
         SyncMarker(c,r) => Ok(store.sync_marker(c,r)),
         Jump => Ok(store.jump()),
         SyncTabSize(n) => Ok(store.sync_tab_size(n)),
+        SyncBidi(b) => Ok(store.sync_bidi(b)),
+
+        // JOB CONTROL
+        SuspendProcess => suspend(term, store),
+    }
+}
+
+
+#[cfg(unix)]
+fn suspend(term: &mut Term, store: &mut Store) -> std::io::Result<()> {
+    // Hand the terminal back as it was before `init`.
+    reset_modes(term, store)?;
+    term.disable_mouse()?;
+    term.reset_styles()?;
+    term.show_cursor()?;
+    if store.id() != 0 { term.disable_alt()? }
+    term.cook()?;
+    term.flush()?;
+    // Blocks until the process is continued (SIGCONT).
+    super::signals::stop();
+    // Re-enter the active screen.
+    if store.id() != 0 {
+        term.enable_alt()?;
+        term.clear(Clear::All)?;
+        store.render(&term)?;
+    }
+    sync_modes(term, store)
+}
+
+// (imdaveho) NOTE: There is no job control on Windows.
+#[cfg(windows)]
+fn suspend(_: &mut Term, _: &mut Store) -> std::io::Result<()> {
+    Ok(())
+}
+
+
+fn switch_to(
+    id: usize, term: &mut Term, store: &mut Store
+) -> std::io::Result<()> {
+    let current = store.id();
+    // Bounds checking:
+    if current == id { return Ok(()) }
+    if store.exists(id) { store.set(id)? }
+    else { return Ok(()) }
+    // Handle screen switch:
+    // Disable if you are reverting back to main.
+    if id == 0 { term.disable_alt()? }
+    else {
+        // Enable as you are on the main screen
+        // switching to alternate.
+        if current == 0 { term.enable_alt()? }
+        term.clear(Clear::All)?;
+    }
+    if id != 0 { store.render(&term)? }
+    // Restore settings based on metadata.
+    sync_modes(term, store)
+}
+
+fn sync_modes(term: &mut Term, store: &mut Store) -> std::io::Result<()> {
+    let (raw, mouse, show) = (
+        store.is_raw(),
+        store.is_mouse(),
+        store.is_cursor() );
+
+    if raw { term.raw()? }
+    else { term.cook()? }
+    match store.mouse_mode() {
+        Some((level, encoding)) => set_mouse_mode(term, level, encoding)?,
+        None => {
+            if store.is_mouse_mode_used() { term.printf(MOUSE_RESET)? }
+            if mouse { term.enable_mouse()? }
+            else { term.disable_mouse()? }
+        }
+    }
+    if show { term.show_cursor()? }
+    else { term.hide_cursor()? }
+    sync_keyboard(term, store)?;
+    #[cfg(unix)]
+    term.flush()?;
+    Ok(())
+}
+
+fn set_mouse_mode(
+    term: &mut Term, level: MouseLevel, encoding: MouseEncoding
+) -> std::io::Result<()> {
+    let mut modes = String::from(MOUSE_RESET);
+    match level {
+        MouseLevel::Off => return term.printf(&modes),
+        MouseLevel::Button => modes.push_str("\x1B[?1000h"),
+        MouseLevel::Drag => modes.push_str("\x1B[?1000h\x1B[?1002h"),
+        MouseLevel::Motion => modes.push_str("\x1B[?1000h\x1B[?1003h"),
+    }
+    match encoding {
+        MouseEncoding::Legacy => (),
+        MouseEncoding::Sgr => modes.push_str("\x1B[?1006h"),
+        MouseEncoding::SgrPixels => modes.push_str("\x1B[?1006h\x1B[?1016h"),
+    }
+    term.printf(&modes)
+}
+
+fn sync_keyboard(term: &mut Term, store: &Store) -> std::io::Result<()> {
+    // Only talk the kitty keyboard protocol if it was asked for.
+    if !store.is_keyboard_used() { return Ok(()) }
+    term.printf(&format!("\x1B[={};1u", store.keyboard()))
+}
+
+pub fn reset_modes(term: &mut Term, store: &Store) -> std::io::Result<()> {
+    // Turns off the modes that the Term doesn't know about on exit.
+    if store.is_mouse_mode_used() { term.printf(MOUSE_RESET)? }
+    // The main and alternate screens have their own flags in the terminal.
+    if !store.is_keyboard_used() { return term.flush() }
+    term.printf("\x1B[=0;1u")?;
+    if store.id() != 0 {
+        term.disable_alt()?;
+        term.printf("\x1B[=0;1u")?;
     }
+    term.flush()
 }
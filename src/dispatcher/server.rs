@@ -0,0 +1,214 @@
+// This module provides the IPC server that allows other processes to drive a
+// Dispatcher over a Unix domain socket (see `Dispatcher::serve`).
+//
+// Each connection is given its own EventHandle and speaks the line-delimited
+// text form from `wire`. The server first writes its `tuitty <VERSION>`
+// header. The client may send its own header (the connection is refused if
+// the versions differ), followed by one message per line:
+//
+//   action <Action>     eg. `action goto 10 4`
+//   query <Query>       eg. `query size 0`
+//
+// The handle id of a query is replaced with the id of the connection's own
+// EventHandle, so clients can send 0. The server writes one line per Msg that
+// is queued for the connection (input events and replies to queries), or an
+// error if a line from the client could not be decoded:
+//
+//   msg <Msg>           eg. `msg received key char "a"`
+//   error "<reason>"    eg. `error "Error: unknown variant: fly"`
+//
+// (imdaveho) NOTE: Input events are only forwarded once `Dispatcher::listen`
+// has started the input thread. Actions that touch the filesystem of the
+// dispatcher's process (`start_cast`) are refused, as the client may not
+// have the same permissions.
+use std::{
+    thread, path::{ Path, PathBuf }, time::Duration,
+    collections::HashMap,
+    io::{ BufRead, BufReader, Write, ErrorKind, Result },
+    net::Shutdown,
+    os::unix::net::{ UnixListener, UnixStream },
+    sync::{
+        mpsc::Sender, Arc, Mutex, atomic::{ AtomicBool, Ordering },
+    },
+};
+use super::{ EventEmitter, EventHandle, register };
+use super::message::{ Cmd::{ self, * }, Action, Query };
+use super::wire;
+
+
+pub fn serve(
+    path: &Path,
+    emitters: Arc<Mutex<HashMap<usize, EventEmitter>>>,
+    signal_tx: Sender<Cmd>,
+    is_running: Arc<AtomicBool>,
+    delay: Duration,
+    bound: Option<usize>,
+) -> Result<thread::JoinHandle<()>> {
+    // A socket left behind by a process that did not shut down cleanly
+    // can be replaced, but not one that is still being served.
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(std::io::Error::new(ErrorKind::AddrInUse,
+                format!("Error: {} is already being served", path.display())))
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // Accept without blocking so that the loop can observe shutdown.
+    listener.set_nonblocking(true)?;
+    let path = PathBuf::from(path);
+    Ok(thread::spawn(move || {
+        // The open connections, to hang up on when the dispatcher stops.
+        let streams = Arc::new(Mutex::new(HashMap::new()));
+        while is_running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let handle = register(&emitters, &signal_tx, bound);
+                    let _ = connect(stream, handle, &streams);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(delay)
+                },
+                Err(_) => break,
+            }
+        }
+        if let Ok(mut streams) = streams.lock() {
+            for (_, stream) in streams.drain() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        let _ = std::fs::remove_file(&path);
+    }))
+}
+
+fn connect(
+    stream: UnixStream,
+    handle: EventHandle,
+    streams: &Arc<Mutex<HashMap<usize, UnixStream>>>,
+) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    let (id, signal_tx) = (handle.id, handle.signal_tx.clone());
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let hangup = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    send(&writer, wire::header())?;
+    if let Ok(mut streams) = streams.lock() { streams.insert(id, hangup); }
+    let streams = streams.clone();
+
+    // Forward queued Msgs to the client.
+    let msg_writer = writer.clone();
+    thread::spawn(move || {
+        while let Some(msg) = handle.poll_sync() {
+            if send(&msg_writer, wire::to_text(&msg)).is_err() { break }
+        }
+    });
+
+    // Decode and dispatch the client's messages.
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            let cmd = match decode(&line, id) {
+                Ok(Some(cmd)) => cmd,
+                Ok(None) => continue,
+                Err(e) => {
+                    let error = format!("error {}", wire::quote(&e.to_string()));
+                    if send(&writer, error).is_err() { break }
+                    // Do not continue talking in a different version.
+                    if line.starts_with("tuitty ") { break }
+                    continue
+                },
+            };
+            if signal_tx.send(cmd).is_err() { break }
+        }
+        // The client hung up (or the dispatcher shut down).
+        if let Ok(mut streams) = streams.lock() { streams.remove(&id); }
+        let _ = signal_tx.send(Stop(id));
+    });
+    Ok(())
+}
+
+fn decode(line: &str, id: usize) -> Result<Option<Cmd>> {
+    // Ignore blank lines and a matching header.
+    if line.trim().is_empty() || line == wire::header() { return Ok(None) }
+    let cmd = match line.split(' ').next() {
+        Some("action") => match wire::from_text::<Action>(line)? {
+            Action::StartCast(_) => return Err(std::io::Error::new(
+                ErrorKind::Other, "Error: start_cast is not allowed over IPC")),
+            action => Signal(action),
+        },
+        Some("query") => {
+            // Replace the handle id with the connection's own.
+            let query = match wire::from_text::<Query>(line)? {
+                Query::Size(_) => Query::Size(id),
+                Query::Coord(_) => Query::Coord(id),
+                Query::Pos(_) => Query::Pos(id),
+                Query::GetCh(_) => Query::GetCh(id),
+                Query::GetLn(_) => Query::GetLn(id),
+                Query::Screen(_) => Query::Screen(id),
+                Query::ListScreens(_) => Query::ListScreens(id),
+                Query::Snapshot(_, s) => Query::Snapshot(id, s),
+                Query::Dump(_, s, f) => Query::Dump(id, s, f),
+                Query::_IsRaw(_) => Query::_IsRaw(id),
+                Query::LastError(_) => Query::LastError(id),
+            };
+            Request(query)
+        },
+        Some("tuitty") => return Err(std::io::Error::new(ErrorKind::Other,
+            format!("Error: unsupported version: {}", line))),
+        _ => return Err(std::io::Error::new(ErrorKind::Other,
+            format!("Error: unknown message: {}", line))),
+    };
+    Ok(Some(cmd))
+}
+
+fn send(writer: &Arc<Mutex<UnixStream>>, line: String) -> Result<()> {
+    let mut line = line;
+    line.push('\n');
+    match writer.lock() {
+        Ok(mut stream) => stream.write_all(line.as_bytes()),
+        Err(_) => Err(std::io::Error::new(ErrorKind::Other,
+            "Error: the connection is poisoned")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::message::Format;
+
+    #[test]
+    fn test_server_decode() {
+        // Queries are rewritten to the connection's own handle id:
+        match decode("query size 0", 7) {
+            Ok(Some(Request(Query::Size(7)))) => (),
+            _ => panic!("Expected the query for handle 7"),
+        }
+        let line = wire::to_text(&Query::Dump(0, 2, Format::Plain));
+        match decode(&line, 7) {
+            Ok(Some(Request(Query::Dump(7, 2, Format::Plain)))) => (),
+            _ => panic!("Expected the dump for handle 7"),
+        }
+        match decode("action goto 10 4", 7) {
+            Ok(Some(Signal(Action::Goto(10, 4)))) => (),
+            _ => panic!("Expected the action"),
+        }
+        // Blank lines and a matching header are skipped:
+        assert!(matches!(decode("", 7), Ok(None)));
+        assert!(matches!(decode(&wire::header(), 7), Ok(None)));
+
+        // Recording to a file is refused:
+        let line = wire::to_text(&Action::StartCast("/tmp/x.cast".into()));
+        let e = decode(&line, 7).err().expect("Expected start_cast to fail");
+        assert!(e.to_string().contains("start_cast is not allowed"));
+
+        // As are other versions and unknown messages:
+        let line = format!("tuitty {}", wire::VERSION + 1);
+        let e = decode(&line, 7).err().expect("Expected the version to fail");
+        assert!(e.to_string().contains("unsupported version"));
+        assert!(decode("fly away", 7).is_err());
+    }
+}
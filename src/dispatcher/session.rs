@@ -0,0 +1,126 @@
+// This module provides the session files that the input thread records into
+// (see `Dispatcher::record`) and that can be replayed instead of reading the
+// terminal (see `Dispatcher::replay`).
+//
+// A session is a text file that begins with the `tuitty <VERSION>` header
+// from `wire`, followed by one line per input Msg (an InputEvent, or a kitty
+// key or extended mouse event) with the microseconds elapsed since the
+// recording started and the terminal size at the time:
+//
+//   tuitty 1
+//   0 80 24 msg received key char "h"
+//   152310 80 24 msg received key char "i"
+//   901004 120 40 msg received mouse press left 10 4
+//   950112 120 40 msg key char "a" 8 release ""
+use std::{
+    fs::File, path::Path, time::{ Duration, Instant },
+    io::{ BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write },
+};
+use super::message::Msg;
+use super::wire;
+
+
+pub struct Entry {
+    pub elapsed: Duration,
+    pub size: (i16, i16),
+    pub msg: Msg,
+}
+
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", wire::header())?;
+        file.flush()?;
+        Ok(Recorder { file, start: Instant::now() })
+    }
+
+    pub fn record(&mut self, size: (i16, i16), msg: &Msg) {
+        let elapsed = self.start.elapsed().as_micros();
+        let msg = wire::to_text(msg);
+        // (imdaveho) NOTE: Flush every entry so that the session survives
+        // a crash (which is usually what is being reproduced).
+        let _ = writeln!(self.file, "{} {} {} {}", elapsed, size.0, size.1, msg)
+            .and_then(|_| self.file.flush());
+    }
+}
+
+fn invalid(n: usize, reason: &str) -> Error {
+    Error::new(ErrorKind::Other,
+        format!("Error: invalid session entry on line {}: {}", n, reason))
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    match lines.next() {
+        Some(Ok(ref header)) if *header == wire::header() => (),
+        _ => return Err(Error::new(ErrorKind::Other,
+            "Error: unsupported session file")),
+    }
+    let mut entries = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let (line, n) = (line?, i + 2);
+        if line.trim().is_empty() { continue }
+        let mut fields = line.splitn(4, ' ');
+        let mut number = |reason| match fields.next().map(str::parse::<u64>) {
+            Some(Ok(value)) => Ok(value),
+            _ => Err(invalid(n, reason)),
+        };
+        let elapsed = Duration::from_micros(number("bad timestamp")?);
+        let (w, h) = (number("bad width")?, number("bad height")?);
+        let msg = match fields.next().map(wire::from_text::<Msg>) {
+            Some(Ok(msg @ Msg::Received(_))) | Some(Ok(msg @ Msg::Key(_)))
+                | Some(Ok(msg @ Msg::Mouse(_))) => msg,
+            Some(Err(e)) => return Err(invalid(n, &e.to_string())),
+            _ => return Err(invalid(n, "expected an input event")),
+        };
+        entries.push(Entry { elapsed, size: (w as i16, h as i16), msg });
+    }
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tuitty_core::common::enums::{
+        InputEvent, KeyEvent, MouseEvent, MouseButton,
+    };
+    use super::super::message::{ Key, KeyCode, KeyKind };
+
+    #[test]
+    fn test_session_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("tuitty-session-{}.txt", std::process::id()));
+        let msgs = [
+            Msg::Received(InputEvent::Keyboard(KeyEvent::Char('"'))),
+            Msg::Received(InputEvent::Mouse(
+                MouseEvent::Press(MouseButton::Left, 3, 4))),
+            Msg::Received(InputEvent::Keyboard(KeyEvent::Ctrl('q'))),
+            Msg::Key(Key {
+                code: KeyCode::Char('a'), modifiers: Key::SUPER,
+                kind: KeyKind::Release, text: String::new(),
+            }),
+        ];
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder.record((80, 24), &msgs[0]);
+        recorder.record((80, 24), &msgs[1]);
+        recorder.record((120, 40), &msgs[2]);
+        recorder.record((120, 40), &msgs[3]);
+        drop(recorder);
+
+        let entries = load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(entries.len(), 4);
+        for (entry, msg) in entries.iter().zip(msgs.iter()) {
+            assert_eq!(wire::to_text(&entry.msg), wire::to_text(msg));
+        }
+        assert_eq!(entries[2].size, (120, 40));
+        assert!(entries[0].elapsed <= entries[1].elapsed);
+        assert!(entries[1].elapsed <= entries[2].elapsed);
+    }
+}
@@ -0,0 +1,154 @@
+// This module handles the process signals that the dispatcher cares about.
+//
+// The handlers only set a flag, which the signal thread picks up on its
+// next iteration (as nothing else is async-signal-safe):
+//
+//   SIGTSTP   eg. Ctrl+Z in cooked mode, handled as `Action::SuspendProcess`
+//   SIGINT    eg. Ctrl+C in cooked mode     sent to the handles as
+//   SIGTERM   eg. `kill`                    `Msg::Signal`, before the
+//   SIGHUP    eg. a closed terminal window  teardown (see `Dispatcher::grace`)
+//
+// (imdaveho) NOTE: In raw mode, Ctrl+Z and Ctrl+C do not send signals but
+// arrive as `KeyEvent::Ctrl`, so apps signal `Action::SuspendProcess` (or
+// shut down) themselves.
+use std::sync::{ Mutex, atomic::{ AtomicBool, AtomicU8, Ordering } };
+use libc::{
+    c_int, sighandler_t, SIGTSTP, SIGINT, SIGTERM, SIGHUP, SIG_DFL, SIG_IGN
+};
+use super::message::SignalKind;
+
+const KINDS: [(c_int, SignalKind); 3] = [
+    (SIGINT, SignalKind::Interrupt),
+    (SIGTERM, SignalKind::Terminate),
+    (SIGHUP, SignalKind::Hangup),
+];
+
+static IS_SUSPEND: AtomicBool = AtomicBool::new(false);
+// Bit `i` is set when the signal of `KINDS[i]` is pending.
+static PENDING: AtomicU8 = AtomicU8::new(0);
+// The handlers that were replaced by `install`.
+static PREVIOUS: Mutex<Vec<(c_int, libc::sigaction)>> = Mutex::new(Vec::new());
+
+
+extern "C" fn on_signal(signal: c_int) {
+    if signal == SIGTSTP { IS_SUSPEND.store(true, Ordering::SeqCst) }
+    for (i, (s, _)) in KINDS.iter().enumerate() {
+        if *s == signal { PENDING.fetch_or(1 << i, Ordering::SeqCst); }
+    }
+}
+
+fn handler() -> sighandler_t {
+    on_signal as extern "C" fn(c_int) as sighandler_t
+}
+
+fn handle(signal: c_int, handler: sighandler_t) -> libc::sigaction {
+    // Returns the previous action.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        // Do not interrupt the blocking read of the input thread.
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, &mut previous);
+        previous
+    }
+}
+
+pub fn install() -> bool {
+    // Returns whether the handlers were installed by this call.
+    let mut previous = match PREVIOUS.lock() {
+        Ok(p) => p,
+        Err(e) => e.into_inner(),
+    };
+    // Already installed (by another Dispatcher).
+    if !previous.is_empty() { return false }
+    let signals = [SIGTSTP, SIGINT, SIGTERM, SIGHUP];
+    for signal in signals.iter() {
+        let action = handle(*signal, handler());
+        // (imdaveho) NOTE: Signals that were ignored (eg. SIGHUP under
+        // `nohup` or SIGINT in a background job) stay ignored.
+        if action.sa_sigaction == SIG_IGN {
+            unsafe { libc::sigaction(*signal, &action, std::ptr::null_mut()); }
+        }
+        previous.push((*signal, action))
+    }
+    true
+}
+
+pub fn uninstall() {
+    let mut previous = match PREVIOUS.lock() {
+        Ok(p) => p,
+        Err(e) => e.into_inner(),
+    };
+    for (signal, action) in previous.drain(..) {
+        unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()); }
+    }
+}
+
+pub fn take_suspend() -> bool {
+    IS_SUSPEND.swap(false, Ordering::SeqCst)
+}
+
+pub fn take_signals() -> Vec<SignalKind> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    KINDS.iter().enumerate()
+        .filter(|(i, _)| pending & 1 << i != 0)
+        .map(|(_, (_, kind))| *kind)
+        .collect()
+}
+
+pub fn stop() {
+    // Stops the process (as the default SIGTSTP would) and returns once it
+    // is continued (SIGCONT).
+    handle(SIGTSTP, SIG_DFL);
+    unsafe { libc::raise(SIGTSTP); }
+    handle(SIGTSTP, handler());
+}
+
+pub fn terminate(kind: SignalKind) {
+    // Hands the signal to the handler that was replaced by `install` (the
+    // default action ends the process).
+    let signal = match KINDS.iter().find(|(_, k)| *k == kind) {
+        Some((signal, _)) => *signal,
+        None => return,
+    };
+    let previous = match PREVIOUS.lock() {
+        Ok(p) => p.iter().find(|(s, _)| *s == signal).map(|(_, a)| *a),
+        Err(e) => e.into_inner().iter()
+            .find(|(s, _)| *s == signal).map(|(_, a)| *a),
+    };
+    match previous {
+        Some(action) => unsafe {
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        },
+        None => { handle(signal, SIG_DFL); },
+    }
+    unsafe { libc::raise(signal); }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_flags() {
+        assert!(install());
+        assert!(!install());
+        assert!(!take_suspend());
+        assert!(take_signals().is_empty());
+        unsafe {
+            libc::raise(SIGTSTP);
+            libc::raise(SIGHUP);
+            libc::raise(SIGINT);
+            libc::raise(SIGINT);
+        }
+        assert!(take_suspend());
+        assert!(!take_suspend());
+        assert_eq!(take_signals(),
+            [SignalKind::Interrupt, SignalKind::Hangup]);
+        assert!(take_signals().is_empty());
+        uninstall();
+    }
+}
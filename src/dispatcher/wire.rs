@@ -0,0 +1,1649 @@
+// This module provides a stable, versioned encoding of Action, Query, Reply
+// and Msg (and the types that they carry) for logging, IPC and bindings. Each
+// message can be written as human-readable text or as compact binary.
+//
+// TEXT FORM
+//
+// A message is written on a single line as a sequence of space separated
+// tokens. A token is either a word (eg. a variant name, number or boolean) or
+// a double quoted string. Strings escape `\`, `"` and control characters
+// (eg. `\n`, `\t` and `\u{1b}`) so that a message never spans lines. Streams
+// of text messages (eg. the IPC server) begin with a `tuitty <VERSION>` line.
+//
+//   action goto 10 4
+//   action set_styles red rgb 0 0 0 1024
+//   action prints "hello\nworld"
+//   query size 0
+//   msg received key ctrl "q"
+//   msg response size 80 24
+//
+// BINARY FORM
+//
+// A message is written as `<VERSION:u8> <KIND:u8> <payload>`. In the payload,
+// variant names are replaced by their index in the tables below (as a u8),
+// integers are LEB128 varints (zigzag encoded if signed), booleans are a u8
+// (0 or 1) and strings are a varint byte length followed by UTF-8 bytes.
+//
+// PAYLOAD
+//
+// Variants and their fields are written in declaration order with names in
+// snake_case. Nested values are written inline:
+//
+//   Color       reset | black | dark_grey | ... | rgb R G B | ansi N
+//   Clear       all | cursor_dn | cursor_up | current_ln | new_ln
+//   Format      plain | ansi | html
+//   Style       <fg:Color> <bg:Color> <fx>
+//   Option<T>   none | some <T>
+//   Vec<T>      <len> <T>...
+//   InputEvent  key <KeyEvent> | mouse <MouseEvent> | unsupported <Vec<u8>>
+//               | unknown | cursor_pos COL ROW
+//   KeyEvent    backspace | enter | ... | f N | char "c" | alt "c" | ctrl "c"
+//   MouseEvent  press <left|right|middle|wheel_up|wheel_down> COL ROW
+//               | release COL ROW | hold COL ROW | unknown
+//   Key         <KeyCode> MODIFIERS <press|repeat|release> "text"
+//   KeyCode     char "c" | esc | enter | ... | f N | other N
+//   Mouse       <press|release|drag|move> <Option<Button>> COL ROW MODIFIERS
+//   Button      left | middle | right | wheel_up | ... | extra N
+//   Msg         ... | region "name" <Mouse> | enter "name" | leave "name"
+//   Snapshot    W H COL ROW MCOL MROW TAB <Style> RAW MOUSE CURSOR BIDI
+//               <Option<LEVEL ENCODING>> <Vec<FLAGS>> <Vec<Option<Cell>>>
+//   Cell        "glyph" WIDE PART <Style> <Option<INDEX MIRROR>>
+//
+// (imdaveho) NOTE: Variants are only ever appended to the tables so that
+// existing binary indexes remain valid. Removing or reordering a variant,
+// or changing its fields, requires bumping VERSION.
+use std::{ io::{ Error, ErrorKind, Result }, convert::TryFrom };
+use crate::tuitty_core::common::enums::{
+    Clear, Color, InputEvent, KeyEvent, MouseEvent, MouseButton,
+};
+use super::message::{
+    Action::{ self, * }, Query, Reply, Msg, Format, Snapshot, CellData,
+    Key, KeyCode, KeyKind, Mouse, MouseKind, Button, MouseLevel,
+    MouseEncoding, Gesture, Failure, ErrorClass, SignalKind,
+};
+
+
+pub const VERSION: u8 = 1;
+
+const KINDS: &[&str] = &["action", "query", "reply", "msg"];
+
+const ACTIONS: &[&str] = &[
+    "goto", "up", "down", "left", "right", "clear", "prints", "set_content",
+    "flush", "render", "resize", "set_fx", "set_fg", "set_bg", "set_styles",
+    "reset_styles", "hide_cursor", "show_cursor", "enable_mouse",
+    "disable_mouse", "raw", "cook", "new_screen", "switch_to", "close_screen",
+    "push_screen", "pop_screen", "resized", "sync_marker", "jump",
+    "sync_tab_size", "sync_bidi", "restore", "start_cast", "stop_cast",
+    "push_keyboard", "pop_keyboard", "set_mouse", "suspend_process",
+    "sync_size",
+];
+
+const QUERIES: &[&str] = &[
+    "size", "coord", "pos", "getch", "getln", "screen", "screens",
+    "snapshot", "dump", "is_raw", "last_error",
+];
+
+const REPLIES: &[&str] = &[
+    "size", "coord", "pos", "getch", "getln", "screen", "screens",
+    "snapshot", "dump", "is_raw", "empty", "last_error",
+];
+
+const MSGS: &[&str] = &[
+    "received", "response", "unsupported", "key", "mouse", "region", "enter",
+    "leave", "gesture", "locked", "lock_timeout", "error", "resumed",
+    "signal",
+];
+
+const EVENTS: &[&str] = &[
+    "key", "mouse", "unsupported", "unknown", "cursor_pos",
+];
+
+const KEYS: &[&str] = &[
+    "backspace", "enter", "left", "right", "up", "down", "home", "end",
+    "page_up", "page_down", "tab", "back_tab", "delete", "insert", "f",
+    "char", "alt", "ctrl", "null", "esc", "ctrl_up", "ctrl_down",
+    "ctrl_right", "ctrl_left", "shift_up", "shift_down", "shift_right",
+    "shift_left",
+];
+
+const KEY_CODES: &[&str] = &[
+    "char", "esc", "enter", "tab", "backspace", "insert", "delete", "left",
+    "right", "up", "down", "page_up", "page_down", "home", "end", "f",
+    "other",
+];
+
+const KEY_KINDS: &[&str] = &["press", "repeat", "release"];
+
+const MOUSE_LEVELS: &[&str] = &["off", "button", "drag", "motion"];
+
+const MOUSE_ENCODINGS: &[&str] = &["legacy", "sgr", "sgr_pixels"];
+
+const MOUSE_KINDS: &[&str] = &["press", "release", "drag", "move"];
+
+const GESTURES: &[&str] = &[
+    "double_click", "triple_click", "drag_start", "drag_move", "drag_end",
+    "long_press",
+];
+
+const ERROR_CLASSES: &[&str] = &["disconnected", "interrupted", "other"];
+
+const SIGNALS: &[&str] = &["interrupt", "terminate", "hangup"];
+
+const MOUSE_BUTTONS: &[&str] = &[
+    "left", "middle", "right", "wheel_up", "wheel_down", "wheel_left",
+    "wheel_right", "extra",
+];
+
+const MOUSE: &[&str] = &["press", "release", "hold", "unknown"];
+
+const BUTTONS: &[&str] = &[
+    "left", "right", "middle", "wheel_up", "wheel_down",
+];
+
+const COLORS: &[&str] = &[
+    "reset", "black", "dark_grey", "red", "dark_red", "green", "dark_green",
+    "yellow", "dark_yellow", "blue", "dark_blue", "magenta", "dark_magenta",
+    "cyan", "dark_cyan", "white", "grey", "rgb", "ansi",
+];
+
+const CLEARS: &[&str] = &[
+    "all", "cursor_dn", "cursor_up", "current_ln", "new_ln",
+];
+
+const FORMATS: &[&str] = &["plain", "ansi", "html"];
+
+const OPTION: &[&str] = &["none", "some"];
+
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::Other, format!("Error: {}", msg))
+}
+
+fn unknown<T>(tag: &str) -> Result<T> {
+    Err(invalid(format!("unknown variant: {}", tag)))
+}
+
+
+// Integers are widened to i64 on the wire and narrowed (with a bounds
+// check) when they are decoded.
+pub trait Number: Copy {
+    fn widen(self) -> i64;
+    fn narrow(n: i64) -> Option<Self>;
+}
+
+macro_rules! number {
+    ($($t:ty),*) => {$(
+        impl Number for $t {
+            fn widen(self) -> i64 { self as i64 }
+            fn narrow(n: i64) -> Option<Self> { <$t>::try_from(n).ok() }
+        }
+    )*}
+}
+
+number!(u8, i16, u32, usize);
+
+
+pub trait Encoder {
+    fn tag(&mut self, tags: &[&str], tag: &str) -> &mut Self;
+    fn int<T: Number>(&mut self, n: T) -> &mut Self;
+    fn boolean(&mut self, b: bool) -> &mut Self;
+    fn string(&mut self, s: &str) -> &mut Self;
+}
+
+pub trait Decoder {
+    fn tag(&mut self, tags: &[&'static str]) -> Result<&'static str>;
+    fn int<T: Number>(&mut self) -> Result<T>;
+    fn boolean(&mut self) -> Result<bool>;
+    fn string(&mut self) -> Result<String>;
+    fn end(&mut self) -> Result<()>;
+}
+
+pub trait Wire: Sized {
+    const KIND: &'static str;
+    fn encode<E: Encoder>(&self, out: &mut E);
+    fn decode<D: Decoder>(input: &mut D) -> Result<Self>;
+}
+
+
+pub fn to_text<T: Wire>(value: &T) -> String {
+    let mut out = TextEncoder { buf: String::with_capacity(32) };
+    out.tag(KINDS, T::KIND);
+    value.encode(&mut out);
+    out.buf
+}
+
+pub fn from_text<T: Wire>(line: &str) -> Result<T> {
+    let mut input = TextDecoder::parse(line)?;
+    if input.tag(KINDS)? != T::KIND {
+        return Err(invalid(format!("expected a message of kind {}", T::KIND)))
+    }
+    let value = T::decode(&mut input)?;
+    input.end()?;
+    Ok(value)
+}
+
+pub fn to_bytes<T: Wire>(value: &T) -> Vec<u8> {
+    let mut out = BinaryEncoder { buf: vec![VERSION] };
+    out.tag(KINDS, T::KIND);
+    value.encode(&mut out);
+    out.buf
+}
+
+pub fn from_bytes<T: Wire>(bytes: &[u8]) -> Result<T> {
+    let mut input = BinaryDecoder { bytes, index: 0 };
+    match input.byte()? {
+        VERSION => (),
+        v => return Err(invalid(format!("unsupported version: {}", v))),
+    }
+    if input.tag(KINDS)? != T::KIND {
+        return Err(invalid(format!("expected a message of kind {}", T::KIND)))
+    }
+    let value = T::decode(&mut input)?;
+    input.end()?;
+    Ok(value)
+}
+
+pub fn name<T: Wire>(value: &T) -> String {
+    // The first tag of the value, eg. "flush" for `Action::Flush`.
+    let mut out = NameEncoder { name: None };
+    value.encode(&mut out);
+    out.name.unwrap_or_default()
+}
+
+pub fn header() -> String {
+    format!("tuitty {}", VERSION)
+}
+
+pub fn quote(s: &str) -> String {
+    // Escapes a string the same way as the text form.
+    let mut out = TextEncoder { buf: String::with_capacity(s.len() + 2) };
+    out.string(s);
+    out.buf
+}
+
+
+// TEXT
+
+struct TextEncoder {
+    buf: String,
+}
+
+impl TextEncoder {
+    fn word(&mut self, w: &str) -> &mut Self {
+        if !self.buf.is_empty() { self.buf.push(' ') }
+        self.buf.push_str(w);
+        self
+    }
+}
+
+impl Encoder for TextEncoder {
+    fn tag(&mut self, _: &[&str], tag: &str) -> &mut Self {
+        self.word(tag)
+    }
+
+    fn int<T: Number>(&mut self, n: T) -> &mut Self {
+        self.word(&n.widen().to_string())
+    }
+
+    fn boolean(&mut self, b: bool) -> &mut Self {
+        self.word(if b { "true" } else { "false" })
+    }
+
+    fn string(&mut self, s: &str) -> &mut Self {
+        self.word("\"");
+        for ch in s.chars() {
+            match ch {
+                '\\' => self.buf.push_str("\\\\"),
+                '"' => self.buf.push_str("\\\""),
+                '\n' => self.buf.push_str("\\n"),
+                '\r' => self.buf.push_str("\\r"),
+                '\t' => self.buf.push_str("\\t"),
+                c if c.is_control() => self.buf
+                    .push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => self.buf.push(c),
+            }
+        }
+        self.buf.push('"');
+        self
+    }
+}
+
+// NAME
+
+struct NameEncoder {
+    name: Option<String>,
+}
+
+impl Encoder for NameEncoder {
+    fn tag(&mut self, _: &[&str], tag: &str) -> &mut Self {
+        if self.name.is_none() { self.name = Some(tag.to_string()) }
+        self
+    }
+
+    fn int<T: Number>(&mut self, _: T) -> &mut Self { self }
+
+    fn boolean(&mut self, _: bool) -> &mut Self { self }
+
+    fn string(&mut self, _: &str) -> &mut Self { self }
+}
+
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+struct TextDecoder {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl TextDecoder {
+    fn parse(line: &str) -> Result<TextDecoder> {
+        let mut tokens = Vec::new();
+        let mut chars = line.trim_end_matches(&['\r', '\n'][..])
+            .chars().peekable();
+        while let Some(&ch) = chars.peek() {
+            if ch == ' ' { chars.next(); continue }
+            if ch != '"' {
+                let mut w = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c == ' ' { break }
+                    w.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(w));
+                continue
+            }
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => s.push(unescape(&mut chars)?),
+                    Some(c) => s.push(c),
+                    None => return Err(invalid("unterminated string".into())),
+                }
+            }
+            tokens.push(Token::Str(s));
+        }
+        Ok(TextDecoder { tokens: tokens.into_iter() })
+    }
+
+    fn word(&mut self) -> Result<String> {
+        match self.tokens.next() {
+            Some(Token::Word(w)) => Ok(w),
+            Some(Token::Str(s)) => Err(
+                invalid(format!("expected a word, found \"{}\"", s))),
+            None => Err(invalid("unexpected end of message".into())),
+        }
+    }
+}
+
+fn unescape<I: Iterator<Item = char>>(chars: &mut I) -> Result<char> {
+    let escaped = match chars.next() {
+        Some('\\') => '\\',
+        Some('"') => '"',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('u') if chars.next() == Some('{') => {
+            let hex: String = chars.take_while(|&c| c != '}').collect();
+            match u32::from_str_radix(&hex, 16).ok()
+                .and_then(std::char::from_u32) {
+                Some(c) => c,
+                None => return Err(invalid("bad escape".into())),
+            }
+        },
+        _ => return Err(invalid("bad escape".into())),
+    };
+    Ok(escaped)
+}
+
+impl Decoder for TextDecoder {
+    fn tag(&mut self, tags: &[&'static str]) -> Result<&'static str> {
+        let w = self.word()?;
+        match tags.iter().find(|&&t| t == w) {
+            Some(t) => Ok(t),
+            None => unknown(&w),
+        }
+    }
+
+    fn int<T: Number>(&mut self) -> Result<T> {
+        let w = self.word()?;
+        match w.parse().ok().and_then(T::narrow) {
+            Some(n) => Ok(n),
+            None => Err(invalid(format!("expected a number: {}", w))),
+        }
+    }
+
+    fn boolean(&mut self) -> Result<bool> {
+        match self.word()?.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            w => Err(invalid(format!("expected a boolean: {}", w))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.tokens.next() {
+            Some(Token::Str(s)) => Ok(s),
+            Some(Token::Word(w)) => Err(
+                invalid(format!("expected a string: {}", w))),
+            None => Err(invalid("unexpected end of message".into())),
+        }
+    }
+
+    fn end(&mut self) -> Result<()> {
+        match self.tokens.next() {
+            None => Ok(()),
+            Some(_) => Err(invalid("unexpected trailing tokens".into())),
+        }
+    }
+}
+
+
+// BINARY
+
+struct BinaryEncoder {
+    buf: Vec<u8>,
+}
+
+impl BinaryEncoder {
+    fn varint(&mut self, n: u64) {
+        let mut n = n;
+        while n >= 0x80 {
+            self.buf.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.buf.push(n as u8);
+    }
+}
+
+impl Encoder for BinaryEncoder {
+    fn tag(&mut self, tags: &[&str], tag: &str) -> &mut Self {
+        // Tags are only ever passed from the tables above.
+        let index = tags.iter().position(|&t| t == tag)
+            .expect("Error finding the wire tag");
+        self.buf.push(index as u8);
+        self
+    }
+
+    fn int<T: Number>(&mut self, n: T) -> &mut Self {
+        let n = n.widen();
+        self.varint(((n << 1) ^ (n >> 63)) as u64);
+        self
+    }
+
+    fn boolean(&mut self, b: bool) -> &mut Self {
+        self.buf.push(b as u8);
+        self
+    }
+
+    fn string(&mut self, s: &str) -> &mut Self {
+        self.varint(s.len() as u64);
+        self.buf.extend_from_slice(s.as_bytes());
+        self
+    }
+}
+
+struct BinaryDecoder<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> BinaryDecoder<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        match self.bytes.get(self.index) {
+            Some(&b) => { self.index += 1; Ok(b) },
+            None => Err(invalid("unexpected end of message".into())),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let (mut n, mut shift) = (0u64, 0);
+        loop {
+            let b = self.byte()?;
+            if shift > 63 { return Err(invalid("varint overflow".into())) }
+            n |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 { return Ok(n) }
+            shift += 7;
+        }
+    }
+}
+
+impl<'a> Decoder for BinaryDecoder<'a> {
+    fn tag(&mut self, tags: &[&'static str]) -> Result<&'static str> {
+        let index = self.byte()?;
+        match tags.get(index as usize) {
+            Some(t) => Ok(t),
+            None => unknown(&index.to_string()),
+        }
+    }
+
+    fn int<T: Number>(&mut self) -> Result<T> {
+        let n = self.varint()?;
+        let n = ((n >> 1) as i64) ^ -((n & 1) as i64);
+        match T::narrow(n) {
+            Some(n) => Ok(n),
+            None => Err(invalid(format!("number out of range: {}", n))),
+        }
+    }
+
+    fn boolean(&mut self) -> Result<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => Err(invalid(format!("expected a boolean: {}", b))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length: usize = match usize::try_from(self.varint()?) {
+            Ok(n) => n,
+            Err(_) => return Err(invalid("string too long".into())),
+        };
+        let end = match self.index.checked_add(length) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return Err(invalid("unexpected end of message".into())),
+        };
+        let s = match std::str::from_utf8(&self.bytes[self.index..end]) {
+            Ok(s) => s.to_string(),
+            Err(_) => return Err(invalid("invalid utf-8".into())),
+        };
+        self.index = end;
+        Ok(s)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        match self.index == self.bytes.len() {
+            true => Ok(()),
+            false => Err(invalid("unexpected trailing bytes".into())),
+        }
+    }
+}
+
+
+// PAYLOADS
+
+fn encode_color<E: Encoder>(c: &Color, out: &mut E) {
+    match c {
+        Color::Reset => out.tag(COLORS, "reset"),
+        Color::Black => out.tag(COLORS, "black"),
+        Color::DarkGrey => out.tag(COLORS, "dark_grey"),
+        Color::Red => out.tag(COLORS, "red"),
+        Color::DarkRed => out.tag(COLORS, "dark_red"),
+        Color::Green => out.tag(COLORS, "green"),
+        Color::DarkGreen => out.tag(COLORS, "dark_green"),
+        Color::Yellow => out.tag(COLORS, "yellow"),
+        Color::DarkYellow => out.tag(COLORS, "dark_yellow"),
+        Color::Blue => out.tag(COLORS, "blue"),
+        Color::DarkBlue => out.tag(COLORS, "dark_blue"),
+        Color::Magenta => out.tag(COLORS, "magenta"),
+        Color::DarkMagenta => out.tag(COLORS, "dark_magenta"),
+        Color::Cyan => out.tag(COLORS, "cyan"),
+        Color::DarkCyan => out.tag(COLORS, "dark_cyan"),
+        Color::White => out.tag(COLORS, "white"),
+        Color::Grey => out.tag(COLORS, "grey"),
+        Color::Rgb{r, g, b} => out.tag(COLORS, "rgb").int(*r).int(*g).int(*b),
+        Color::AnsiValue(n) => out.tag(COLORS, "ansi").int(*n),
+    };
+}
+
+fn decode_color<D: Decoder>(input: &mut D) -> Result<Color> {
+    Ok(match input.tag(COLORS)? {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "dark_grey" => Color::DarkGrey,
+        "red" => Color::Red,
+        "dark_red" => Color::DarkRed,
+        "green" => Color::Green,
+        "dark_green" => Color::DarkGreen,
+        "yellow" => Color::Yellow,
+        "dark_yellow" => Color::DarkYellow,
+        "blue" => Color::Blue,
+        "dark_blue" => Color::DarkBlue,
+        "magenta" => Color::Magenta,
+        "dark_magenta" => Color::DarkMagenta,
+        "cyan" => Color::Cyan,
+        "dark_cyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" => Color::Grey,
+        "rgb" => Color::Rgb{
+            r: input.int()?, g: input.int()?, b: input.int()?
+        },
+        "ansi" => Color::AnsiValue(input.int()?),
+        t => return unknown(t),
+    })
+}
+
+fn encode_style<E: Encoder>(s: &(Color, Color, u32), out: &mut E) {
+    encode_color(&s.0, out);
+    encode_color(&s.1, out);
+    out.int(s.2);
+}
+
+fn decode_style<D: Decoder>(input: &mut D) -> Result<(Color, Color, u32)> {
+    Ok((decode_color(input)?, decode_color(input)?, input.int()?))
+}
+
+fn encode_clear<E: Encoder>(c: &Clear, out: &mut E) {
+    out.tag(CLEARS, match c {
+        Clear::All => "all",
+        Clear::CursorDn => "cursor_dn",
+        Clear::CursorUp => "cursor_up",
+        Clear::CurrentLn => "current_ln",
+        Clear::NewLn => "new_ln",
+    });
+}
+
+fn decode_clear<D: Decoder>(input: &mut D) -> Result<Clear> {
+    Ok(match input.tag(CLEARS)? {
+        "all" => Clear::All,
+        "cursor_dn" => Clear::CursorDn,
+        "cursor_up" => Clear::CursorUp,
+        "current_ln" => Clear::CurrentLn,
+        "new_ln" => Clear::NewLn,
+        t => return unknown(t),
+    })
+}
+
+fn encode_format<E: Encoder>(f: &Format, out: &mut E) {
+    out.tag(FORMATS, match f {
+        Format::Plain => "plain",
+        Format::Ansi => "ansi",
+        Format::Html => "html",
+    });
+}
+
+fn decode_format<D: Decoder>(input: &mut D) -> Result<Format> {
+    Ok(match input.tag(FORMATS)? {
+        "plain" => Format::Plain,
+        "ansi" => Format::Ansi,
+        "html" => Format::Html,
+        t => return unknown(t),
+    })
+}
+
+fn decode_is_some<D: Decoder>(input: &mut D) -> Result<bool> {
+    Ok(input.tag(OPTION)? == "some")
+}
+
+fn encode_mouse_mode<E: Encoder>(
+    level: MouseLevel, encoding: MouseEncoding, out: &mut E
+) {
+    out.tag(MOUSE_LEVELS, match level {
+        MouseLevel::Off => "off",
+        MouseLevel::Button => "button",
+        MouseLevel::Drag => "drag",
+        MouseLevel::Motion => "motion",
+    });
+    out.tag(MOUSE_ENCODINGS, match encoding {
+        MouseEncoding::Legacy => "legacy",
+        MouseEncoding::Sgr => "sgr",
+        MouseEncoding::SgrPixels => "sgr_pixels",
+    });
+}
+
+fn decode_mouse_mode<D: Decoder>(
+    input: &mut D
+) -> Result<(MouseLevel, MouseEncoding)> {
+    let level = match input.tag(MOUSE_LEVELS)? {
+        "off" => MouseLevel::Off,
+        "button" => MouseLevel::Button,
+        "drag" => MouseLevel::Drag,
+        "motion" => MouseLevel::Motion,
+        t => return unknown(t),
+    };
+    let encoding = match input.tag(MOUSE_ENCODINGS)? {
+        "legacy" => MouseEncoding::Legacy,
+        "sgr" => MouseEncoding::Sgr,
+        "sgr_pixels" => MouseEncoding::SgrPixels,
+        t => return unknown(t),
+    };
+    Ok((level, encoding))
+}
+
+fn encode_snapshot<E: Encoder>(s: &Snapshot, out: &mut E) {
+    out.int(s.size.0).int(s.size.1)
+        .int(s.cursor.0).int(s.cursor.1)
+        .int(s.marker.0).int(s.marker.1)
+        .int(s.tab_size);
+    encode_style(&s.style, out);
+    out.boolean(s.is_raw).boolean(s.is_mouse)
+        .boolean(s.is_cursor).boolean(s.is_bidi);
+    match s.mouse_mode {
+        None => { out.tag(OPTION, "none"); },
+        Some((level, encoding)) => {
+            out.tag(OPTION, "some");
+            encode_mouse_mode(level, encoding, out);
+        },
+    }
+    out.int(s.keyboard.len());
+    for flags in s.keyboard.iter() { out.int(*flags); }
+    out.int(s.cells.len());
+    for cell in s.cells.iter() {
+        match cell {
+            None => { out.tag(OPTION, "none"); },
+            Some(c) => {
+                out.tag(OPTION, "some").string(&c.glyph)
+                    .boolean(c.is_wide).boolean(c.is_part);
+                encode_style(&c.style, out);
+                match c.logical {
+                    None => out.tag(OPTION, "none"),
+                    Some((i, m)) => out.tag(OPTION, "some").int(i).boolean(m),
+                };
+            },
+        }
+    }
+}
+
+fn decode_snapshot<D: Decoder>(input: &mut D) -> Result<Snapshot> {
+    let size = (input.int()?, input.int()?);
+    let cursor = (input.int()?, input.int()?);
+    let marker = (input.int()?, input.int()?);
+    let tab_size = input.int()?;
+    let style = decode_style(input)?;
+    let (is_raw, is_mouse) = (input.boolean()?, input.boolean()?);
+    let (is_cursor, is_bidi) = (input.boolean()?, input.boolean()?);
+    let mouse_mode = match decode_is_some(input)? {
+        true => Some(decode_mouse_mode(input)?),
+        false => None,
+    };
+    let depth: usize = input.int()?;
+    let mut keyboard = Vec::with_capacity(depth.min(16));
+    for _ in 0..depth { keyboard.push(input.int()?) }
+    let length: usize = input.int()?;
+    // Do not trust the length to preallocate.
+    let mut cells = Vec::with_capacity(length.min(4096));
+    for _ in 0..length {
+        if !decode_is_some(input)? { cells.push(None); continue }
+        let glyph = input.string()?;
+        let (is_wide, is_part) = (input.boolean()?, input.boolean()?);
+        let style = decode_style(input)?;
+        let logical = match decode_is_some(input)? {
+            true => Some((input.int()?, input.boolean()?)),
+            false => None,
+        };
+        cells.push(Some(CellData { glyph, is_wide, is_part, style, logical }));
+    }
+    Ok(Snapshot {
+        size, cursor, marker, tab_size, style, cells,
+        is_raw, is_mouse, is_cursor, is_bidi, mouse_mode, keyboard,
+    })
+}
+
+fn encode_key<E: Encoder>(kv: &KeyEvent, out: &mut E) {
+    match kv {
+        KeyEvent::Backspace => out.tag(KEYS, "backspace"),
+        KeyEvent::Enter => out.tag(KEYS, "enter"),
+        KeyEvent::Left => out.tag(KEYS, "left"),
+        KeyEvent::Right => out.tag(KEYS, "right"),
+        KeyEvent::Up => out.tag(KEYS, "up"),
+        KeyEvent::Down => out.tag(KEYS, "down"),
+        KeyEvent::Home => out.tag(KEYS, "home"),
+        KeyEvent::End => out.tag(KEYS, "end"),
+        KeyEvent::PageUp => out.tag(KEYS, "page_up"),
+        KeyEvent::PageDown => out.tag(KEYS, "page_down"),
+        KeyEvent::Tab => out.tag(KEYS, "tab"),
+        KeyEvent::BackTab => out.tag(KEYS, "back_tab"),
+        KeyEvent::Delete => out.tag(KEYS, "delete"),
+        KeyEvent::Insert => out.tag(KEYS, "insert"),
+        KeyEvent::F(n) => out.tag(KEYS, "f").int(*n),
+        KeyEvent::Char(c) => out.tag(KEYS, "char").string(&c.to_string()),
+        KeyEvent::Alt(c) => out.tag(KEYS, "alt").string(&c.to_string()),
+        KeyEvent::Ctrl(c) => out.tag(KEYS, "ctrl").string(&c.to_string()),
+        KeyEvent::Null => out.tag(KEYS, "null"),
+        KeyEvent::Esc => out.tag(KEYS, "esc"),
+        KeyEvent::CtrlUp => out.tag(KEYS, "ctrl_up"),
+        KeyEvent::CtrlDown => out.tag(KEYS, "ctrl_down"),
+        KeyEvent::CtrlRight => out.tag(KEYS, "ctrl_right"),
+        KeyEvent::CtrlLeft => out.tag(KEYS, "ctrl_left"),
+        KeyEvent::ShiftUp => out.tag(KEYS, "shift_up"),
+        KeyEvent::ShiftDown => out.tag(KEYS, "shift_down"),
+        KeyEvent::ShiftRight => out.tag(KEYS, "shift_right"),
+        KeyEvent::ShiftLeft => out.tag(KEYS, "shift_left"),
+    };
+}
+
+fn decode_char<D: Decoder>(input: &mut D) -> Result<char> {
+    let s = input.string()?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(invalid(format!("expected a single char: {}", s))),
+    }
+}
+
+fn decode_key<D: Decoder>(input: &mut D) -> Result<KeyEvent> {
+    Ok(match input.tag(KEYS)? {
+        "backspace" => KeyEvent::Backspace,
+        "enter" => KeyEvent::Enter,
+        "left" => KeyEvent::Left,
+        "right" => KeyEvent::Right,
+        "up" => KeyEvent::Up,
+        "down" => KeyEvent::Down,
+        "home" => KeyEvent::Home,
+        "end" => KeyEvent::End,
+        "page_up" => KeyEvent::PageUp,
+        "page_down" => KeyEvent::PageDown,
+        "tab" => KeyEvent::Tab,
+        "back_tab" => KeyEvent::BackTab,
+        "delete" => KeyEvent::Delete,
+        "insert" => KeyEvent::Insert,
+        "f" => KeyEvent::F(input.int()?),
+        "char" => KeyEvent::Char(decode_char(input)?),
+        "alt" => KeyEvent::Alt(decode_char(input)?),
+        "ctrl" => KeyEvent::Ctrl(decode_char(input)?),
+        "null" => KeyEvent::Null,
+        "esc" => KeyEvent::Esc,
+        "ctrl_up" => KeyEvent::CtrlUp,
+        "ctrl_down" => KeyEvent::CtrlDown,
+        "ctrl_right" => KeyEvent::CtrlRight,
+        "ctrl_left" => KeyEvent::CtrlLeft,
+        "shift_up" => KeyEvent::ShiftUp,
+        "shift_down" => KeyEvent::ShiftDown,
+        "shift_right" => KeyEvent::ShiftRight,
+        "shift_left" => KeyEvent::ShiftLeft,
+        t => return unknown(t),
+    })
+}
+
+fn encode_key_code<E: Encoder>(code: &KeyCode, out: &mut E) {
+    match code {
+        KeyCode::Char(c) => {
+            out.tag(KEY_CODES, "char").string(&c.to_string());
+        },
+        KeyCode::Esc => { out.tag(KEY_CODES, "esc"); },
+        KeyCode::Enter => { out.tag(KEY_CODES, "enter"); },
+        KeyCode::Tab => { out.tag(KEY_CODES, "tab"); },
+        KeyCode::Backspace => { out.tag(KEY_CODES, "backspace"); },
+        KeyCode::Insert => { out.tag(KEY_CODES, "insert"); },
+        KeyCode::Delete => { out.tag(KEY_CODES, "delete"); },
+        KeyCode::Left => { out.tag(KEY_CODES, "left"); },
+        KeyCode::Right => { out.tag(KEY_CODES, "right"); },
+        KeyCode::Up => { out.tag(KEY_CODES, "up"); },
+        KeyCode::Down => { out.tag(KEY_CODES, "down"); },
+        KeyCode::PageUp => { out.tag(KEY_CODES, "page_up"); },
+        KeyCode::PageDown => { out.tag(KEY_CODES, "page_down"); },
+        KeyCode::Home => { out.tag(KEY_CODES, "home"); },
+        KeyCode::End => { out.tag(KEY_CODES, "end"); },
+        KeyCode::F(n) => { out.tag(KEY_CODES, "f").int(*n); },
+        KeyCode::Other(n) => { out.tag(KEY_CODES, "other").int(*n); },
+    }
+}
+
+fn decode_key_code<D: Decoder>(input: &mut D) -> Result<KeyCode> {
+    Ok(match input.tag(KEY_CODES)? {
+        "char" => KeyCode::Char(decode_char(input)?),
+        "esc" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "insert" => KeyCode::Insert,
+        "delete" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "page_up" => KeyCode::PageUp,
+        "page_down" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "f" => KeyCode::F(input.int()?),
+        "other" => KeyCode::Other(input.int()?),
+        t => return unknown(t),
+    })
+}
+
+fn encode_mouse_button<E: Encoder>(button: &Button, out: &mut E) {
+    match button {
+        Button::Left => { out.tag(MOUSE_BUTTONS, "left"); },
+        Button::Middle => { out.tag(MOUSE_BUTTONS, "middle"); },
+        Button::Right => { out.tag(MOUSE_BUTTONS, "right"); },
+        Button::WheelUp => { out.tag(MOUSE_BUTTONS, "wheel_up"); },
+        Button::WheelDown => { out.tag(MOUSE_BUTTONS, "wheel_down"); },
+        Button::WheelLeft => { out.tag(MOUSE_BUTTONS, "wheel_left"); },
+        Button::WheelRight => { out.tag(MOUSE_BUTTONS, "wheel_right"); },
+        Button::Extra(n) => { out.tag(MOUSE_BUTTONS, "extra").int(*n); },
+    }
+}
+
+fn decode_mouse_button<D: Decoder>(input: &mut D) -> Result<Button> {
+    Ok(match input.tag(MOUSE_BUTTONS)? {
+        "left" => Button::Left,
+        "middle" => Button::Middle,
+        "right" => Button::Right,
+        "wheel_up" => Button::WheelUp,
+        "wheel_down" => Button::WheelDown,
+        "wheel_left" => Button::WheelLeft,
+        "wheel_right" => Button::WheelRight,
+        "extra" => Button::Extra(input.int()?),
+        t => return unknown(t),
+    })
+}
+
+fn encode_failure<E: Encoder>(failure: &Failure, out: &mut E) {
+    out.string(&failure.action).tag(ERROR_CLASSES, match failure.class {
+        ErrorClass::Disconnected => "disconnected",
+        ErrorClass::Interrupted => "interrupted",
+        ErrorClass::Other => "other",
+    }).string(&failure.details).boolean(failure.is_fatal);
+}
+
+fn decode_failure<D: Decoder>(input: &mut D) -> Result<Failure> {
+    let action = input.string()?;
+    let class = match input.tag(ERROR_CLASSES)? {
+        "disconnected" => ErrorClass::Disconnected,
+        "interrupted" => ErrorClass::Interrupted,
+        "other" => ErrorClass::Other,
+        t => return unknown(t),
+    };
+    Ok(Failure {
+        action, class, details: input.string()?, is_fatal: input.boolean()?,
+    })
+}
+
+fn encode_pointer<E: Encoder>(mouse: &Mouse, out: &mut E) {
+    out.tag(MOUSE_KINDS, match mouse.kind {
+        MouseKind::Press => "press",
+        MouseKind::Release => "release",
+        MouseKind::Drag => "drag",
+        MouseKind::Move => "move",
+    });
+    match mouse.button {
+        None => { out.tag(OPTION, "none"); },
+        Some(button) => {
+            out.tag(OPTION, "some");
+            encode_mouse_button(&button, out);
+        },
+    }
+    out.int(mouse.col).int(mouse.row).int(mouse.modifiers);
+}
+
+fn decode_pointer<D: Decoder>(input: &mut D) -> Result<Mouse> {
+    let kind = match input.tag(MOUSE_KINDS)? {
+        "press" => MouseKind::Press,
+        "release" => MouseKind::Release,
+        "drag" => MouseKind::Drag,
+        "move" => MouseKind::Move,
+        t => return unknown(t),
+    };
+    let button = match decode_is_some(input)? {
+        true => Some(decode_mouse_button(input)?),
+        false => None,
+    };
+    Ok(Mouse {
+        kind, button,
+        col: input.int()?, row: input.int()?,
+        modifiers: input.int()?,
+    })
+}
+
+fn encode_button<E: Encoder>(mb: &MouseButton, out: &mut E) {
+    out.tag(BUTTONS, match mb {
+        MouseButton::Left => "left",
+        MouseButton::Right => "right",
+        MouseButton::Middle => "middle",
+        MouseButton::WheelUp => "wheel_up",
+        MouseButton::WheelDown => "wheel_down",
+    });
+}
+
+fn decode_button<D: Decoder>(input: &mut D) -> Result<MouseButton> {
+    Ok(match input.tag(BUTTONS)? {
+        "left" => MouseButton::Left,
+        "right" => MouseButton::Right,
+        "middle" => MouseButton::Middle,
+        "wheel_up" => MouseButton::WheelUp,
+        "wheel_down" => MouseButton::WheelDown,
+        t => return unknown(t),
+    })
+}
+
+fn encode_mouse<E: Encoder>(mv: &MouseEvent, out: &mut E) {
+    match mv {
+        MouseEvent::Press(mb, c, r) => {
+            out.tag(MOUSE, "press");
+            encode_button(mb, out);
+            out.int(*c).int(*r);
+        },
+        MouseEvent::Release(c, r) => {
+            out.tag(MOUSE, "release").int(*c).int(*r);
+        },
+        MouseEvent::Hold(c, r) => { out.tag(MOUSE, "hold").int(*c).int(*r); },
+        MouseEvent::Unknown => { out.tag(MOUSE, "unknown"); },
+    }
+}
+
+fn decode_mouse<D: Decoder>(input: &mut D) -> Result<MouseEvent> {
+    Ok(match input.tag(MOUSE)? {
+        "press" => MouseEvent::Press(
+            decode_button(input)?, input.int()?, input.int()?),
+        "release" => MouseEvent::Release(input.int()?, input.int()?),
+        "hold" => MouseEvent::Hold(input.int()?, input.int()?),
+        "unknown" => MouseEvent::Unknown,
+        t => return unknown(t),
+    })
+}
+
+pub fn encode_event<E: Encoder>(evt: &InputEvent, out: &mut E) {
+    match evt {
+        InputEvent::Keyboard(kv) => {
+            out.tag(EVENTS, "key");
+            encode_key(kv, out)
+        },
+        InputEvent::Mouse(mv) => {
+            out.tag(EVENTS, "mouse");
+            encode_mouse(mv, out)
+        },
+        InputEvent::Unsupported(bytes) => {
+            out.tag(EVENTS, "unsupported").int(bytes.len());
+            for b in bytes { out.int(*b); }
+        },
+        InputEvent::Unknown => { out.tag(EVENTS, "unknown"); },
+        InputEvent::CursorPos(c, r) => {
+            out.tag(EVENTS, "cursor_pos").int(*c).int(*r);
+        },
+    }
+}
+
+pub fn decode_event<D: Decoder>(input: &mut D) -> Result<InputEvent> {
+    Ok(match input.tag(EVENTS)? {
+        "key" => InputEvent::Keyboard(decode_key(input)?),
+        "mouse" => InputEvent::Mouse(decode_mouse(input)?),
+        "unsupported" => {
+            let length: usize = input.int()?;
+            let mut bytes = Vec::with_capacity(length.min(64));
+            for _ in 0..length { bytes.push(input.int()?) }
+            InputEvent::Unsupported(bytes)
+        },
+        "unknown" => InputEvent::Unknown,
+        "cursor_pos" => InputEvent::CursorPos(input.int()?, input.int()?),
+        t => return unknown(t),
+    })
+}
+
+
+impl Wire for Action {
+    const KIND: &'static str = "action";
+
+    fn encode<E: Encoder>(&self, out: &mut E) {
+        match self {
+            Goto(c, r) => { out.tag(ACTIONS, "goto").int(*c).int(*r); },
+            Up(n) => { out.tag(ACTIONS, "up").int(*n); },
+            Down(n) => { out.tag(ACTIONS, "down").int(*n); },
+            Left(n) => { out.tag(ACTIONS, "left").int(*n); },
+            Right(n) => { out.tag(ACTIONS, "right").int(*n); },
+            Clear(c) => { out.tag(ACTIONS, "clear"); encode_clear(c, out) },
+            Prints(s) => { out.tag(ACTIONS, "prints").string(s); },
+            SetContent(s, c, r) => {
+                out.tag(ACTIONS, "set_content").string(s).int(*c).int(*r);
+            },
+            Flush => { out.tag(ACTIONS, "flush"); },
+            Render => { out.tag(ACTIONS, "render"); },
+            Resize(w, h) => { out.tag(ACTIONS, "resize").int(*w).int(*h); },
+            SetFx(fx) => { out.tag(ACTIONS, "set_fx").int(*fx); },
+            SetFg(c) => { out.tag(ACTIONS, "set_fg"); encode_color(c, out) },
+            SetBg(c) => { out.tag(ACTIONS, "set_bg"); encode_color(c, out) },
+            SetStyles(fg, bg, fx) => {
+                out.tag(ACTIONS, "set_styles");
+                encode_style(&(*fg, *bg, *fx), out)
+            },
+            ResetStyles => { out.tag(ACTIONS, "reset_styles"); },
+            HideCursor => { out.tag(ACTIONS, "hide_cursor"); },
+            ShowCursor => { out.tag(ACTIONS, "show_cursor"); },
+            EnableMouse => { out.tag(ACTIONS, "enable_mouse"); },
+            DisableMouse => { out.tag(ACTIONS, "disable_mouse"); },
+            Raw => { out.tag(ACTIONS, "raw"); },
+            Cook => { out.tag(ACTIONS, "cook"); },
+            NewScreen => { out.tag(ACTIONS, "new_screen"); },
+            SwitchTo(id) => { out.tag(ACTIONS, "switch_to").int(*id); },
+            CloseScreen(id) => { out.tag(ACTIONS, "close_screen").int(*id); },
+            PushScreen(id) => { out.tag(ACTIONS, "push_screen").int(*id); },
+            PopScreen => { out.tag(ACTIONS, "pop_screen"); },
+            Resized => { out.tag(ACTIONS, "resized"); },
+            SyncSize(w, h) => {
+                out.tag(ACTIONS, "sync_size").int(*w).int(*h);
+            },
+            SyncMarker(c, r) => {
+                out.tag(ACTIONS, "sync_marker").int(*c).int(*r);
+            },
+            Jump => { out.tag(ACTIONS, "jump"); },
+            SyncTabSize(n) => { out.tag(ACTIONS, "sync_tab_size").int(*n); },
+            SyncBidi(b) => { out.tag(ACTIONS, "sync_bidi").boolean(*b); },
+            Restore(s) => {
+                out.tag(ACTIONS, "restore");
+                encode_snapshot(s, out)
+            },
+            StartCast(path) => { out.tag(ACTIONS, "start_cast").string(path); },
+            StopCast => { out.tag(ACTIONS, "stop_cast"); },
+            PushKeyboard(flags) => {
+                out.tag(ACTIONS, "push_keyboard").int(*flags);
+            },
+            PopKeyboard => { out.tag(ACTIONS, "pop_keyboard"); },
+            SuspendProcess => { out.tag(ACTIONS, "suspend_process"); },
+            SetMouse(level, encoding) => {
+                out.tag(ACTIONS, "set_mouse");
+                encode_mouse_mode(*level, *encoding, out);
+            },
+        }
+    }
+
+    fn decode<D: Decoder>(input: &mut D) -> Result<Self> {
+        Ok(match input.tag(ACTIONS)? {
+            "goto" => Goto(input.int()?, input.int()?),
+            "up" => Up(input.int()?),
+            "down" => Down(input.int()?),
+            "left" => Left(input.int()?),
+            "right" => Right(input.int()?),
+            "clear" => Clear(decode_clear(input)?),
+            "prints" => Prints(input.string()?),
+            "set_content" => SetContent(
+                input.string()?, input.int()?, input.int()?),
+            "flush" => Flush,
+            "render" => Render,
+            "resize" => Resize(input.int()?, input.int()?),
+            "set_fx" => SetFx(input.int()?),
+            "set_fg" => SetFg(decode_color(input)?),
+            "set_bg" => SetBg(decode_color(input)?),
+            "set_styles" => {
+                let (fg, bg, fx) = decode_style(input)?;
+                SetStyles(fg, bg, fx)
+            },
+            "reset_styles" => ResetStyles,
+            "hide_cursor" => HideCursor,
+            "show_cursor" => ShowCursor,
+            "enable_mouse" => EnableMouse,
+            "disable_mouse" => DisableMouse,
+            "raw" => Raw,
+            "cook" => Cook,
+            "new_screen" => NewScreen,
+            "switch_to" => SwitchTo(input.int()?),
+            "close_screen" => CloseScreen(input.int()?),
+            "push_screen" => PushScreen(input.int()?),
+            "pop_screen" => PopScreen,
+            "resized" => Resized,
+            "sync_size" => SyncSize(input.int()?, input.int()?),
+            "sync_marker" => SyncMarker(input.int()?, input.int()?),
+            "jump" => Jump,
+            "sync_tab_size" => SyncTabSize(input.int()?),
+            "sync_bidi" => SyncBidi(input.boolean()?),
+            "restore" => Restore(decode_snapshot(input)?),
+            "start_cast" => StartCast(input.string()?),
+            "stop_cast" => StopCast,
+            "push_keyboard" => PushKeyboard(input.int()?),
+            "pop_keyboard" => PopKeyboard,
+            "suspend_process" => SuspendProcess,
+            "set_mouse" => {
+                let (level, encoding) = decode_mouse_mode(input)?;
+                SetMouse(level, encoding)
+            },
+            t => return unknown(t),
+        })
+    }
+}
+
+impl Wire for Query {
+    const KIND: &'static str = "query";
+
+    fn encode<E: Encoder>(&self, out: &mut E) {
+        match self {
+            Query::Size(id) => { out.tag(QUERIES, "size").int(*id); },
+            Query::Coord(id) => { out.tag(QUERIES, "coord").int(*id); },
+            Query::Pos(id) => { out.tag(QUERIES, "pos").int(*id); },
+            Query::GetCh(id) => { out.tag(QUERIES, "getch").int(*id); },
+            Query::GetLn(id) => { out.tag(QUERIES, "getln").int(*id); },
+            Query::Screen(id) => { out.tag(QUERIES, "screen").int(*id); },
+            Query::ListScreens(id) => {
+                out.tag(QUERIES, "screens").int(*id);
+            },
+            Query::Snapshot(id, screen) => {
+                out.tag(QUERIES, "snapshot").int(*id).int(*screen);
+            },
+            Query::Dump(id, screen, f) => {
+                out.tag(QUERIES, "dump").int(*id).int(*screen);
+                encode_format(f, out)
+            },
+            Query::_IsRaw(id) => { out.tag(QUERIES, "is_raw").int(*id); },
+            Query::LastError(id) => {
+                out.tag(QUERIES, "last_error").int(*id);
+            },
+        }
+    }
+
+    fn decode<D: Decoder>(input: &mut D) -> Result<Self> {
+        Ok(match input.tag(QUERIES)? {
+            "size" => Query::Size(input.int()?),
+            "coord" => Query::Coord(input.int()?),
+            "pos" => Query::Pos(input.int()?),
+            "getch" => Query::GetCh(input.int()?),
+            "getln" => Query::GetLn(input.int()?),
+            "screen" => Query::Screen(input.int()?),
+            "screens" => Query::ListScreens(input.int()?),
+            "snapshot" => Query::Snapshot(input.int()?, input.int()?),
+            "dump" => Query::Dump(
+                input.int()?, input.int()?, decode_format(input)?),
+            "is_raw" => Query::_IsRaw(input.int()?),
+            "last_error" => Query::LastError(input.int()?),
+            t => return unknown(t),
+        })
+    }
+}
+
+impl Wire for Reply {
+    const KIND: &'static str = "reply";
+
+    fn encode<E: Encoder>(&self, out: &mut E) {
+        match self {
+            Reply::Size(w, h) => { out.tag(REPLIES, "size").int(*w).int(*h); },
+            Reply::Coord(c, r) => {
+                out.tag(REPLIES, "coord").int(*c).int(*r);
+            },
+            Reply::Pos(c, r) => { out.tag(REPLIES, "pos").int(*c).int(*r); },
+            Reply::GetCh(s) => { out.tag(REPLIES, "getch").string(s); },
+            Reply::GetLn(s) => { out.tag(REPLIES, "getln").string(s); },
+            Reply::Screen(id) => { out.tag(REPLIES, "screen").int(*id); },
+            Reply::Screens(ids) => {
+                out.tag(REPLIES, "screens").int(ids.len());
+                for id in ids { out.int(*id); }
+            },
+            Reply::Snapshot(s) => {
+                out.tag(REPLIES, "snapshot");
+                match s {
+                    None => { out.tag(OPTION, "none"); },
+                    Some(s) => {
+                        out.tag(OPTION, "some");
+                        encode_snapshot(s, out)
+                    },
+                }
+            },
+            Reply::Dump(s) => {
+                out.tag(REPLIES, "dump");
+                match s {
+                    None => out.tag(OPTION, "none"),
+                    Some(s) => out.tag(OPTION, "some").string(s),
+                };
+            },
+            Reply::_IsRaw(b) => { out.tag(REPLIES, "is_raw").boolean(*b); },
+            Reply::LastError(f) => {
+                out.tag(REPLIES, "last_error");
+                match f {
+                    None => { out.tag(OPTION, "none"); },
+                    Some(f) => {
+                        out.tag(OPTION, "some");
+                        encode_failure(f, out)
+                    },
+                }
+            },
+            Reply::Empty => { out.tag(REPLIES, "empty"); },
+        }
+    }
+
+    fn decode<D: Decoder>(input: &mut D) -> Result<Self> {
+        Ok(match input.tag(REPLIES)? {
+            "size" => Reply::Size(input.int()?, input.int()?),
+            "coord" => Reply::Coord(input.int()?, input.int()?),
+            "pos" => Reply::Pos(input.int()?, input.int()?),
+            "getch" => Reply::GetCh(input.string()?),
+            "getln" => Reply::GetLn(input.string()?),
+            "screen" => Reply::Screen(input.int()?),
+            "screens" => {
+                let length: usize = input.int()?;
+                let mut ids = Vec::with_capacity(length.min(64));
+                for _ in 0..length { ids.push(input.int()?) }
+                Reply::Screens(ids)
+            },
+            "snapshot" => Reply::Snapshot(match decode_is_some(input)? {
+                true => Some(decode_snapshot(input)?),
+                false => None,
+            }),
+            "dump" => Reply::Dump(match decode_is_some(input)? {
+                true => Some(input.string()?),
+                false => None,
+            }),
+            "is_raw" => Reply::_IsRaw(input.boolean()?),
+            "empty" => Reply::Empty,
+            "last_error" => Reply::LastError(match decode_is_some(input)? {
+                true => Some(decode_failure(input)?),
+                false => None,
+            }),
+            t => return unknown(t),
+        })
+    }
+}
+
+impl Wire for Msg {
+    const KIND: &'static str = "msg";
+
+    fn encode<E: Encoder>(&self, out: &mut E) {
+        match self {
+            Msg::Received(evt) => {
+                out.tag(MSGS, "received");
+                encode_event(evt, out)
+            },
+            Msg::Response(r) => {
+                out.tag(MSGS, "response");
+                r.encode(out)
+            },
+            Msg::Unsupported => { out.tag(MSGS, "unsupported"); },
+            Msg::Key(key) => {
+                out.tag(MSGS, "key");
+                encode_key_code(&key.code, out);
+                out.int(key.modifiers);
+                out.tag(KEY_KINDS, match key.kind {
+                    KeyKind::Press => "press",
+                    KeyKind::Repeat => "repeat",
+                    KeyKind::Release => "release",
+                }).string(&key.text);
+            },
+            Msg::Mouse(mouse) => {
+                out.tag(MSGS, "mouse");
+                encode_pointer(mouse, out)
+            },
+            Msg::Region(name, mouse) => {
+                out.tag(MSGS, "region").string(name);
+                encode_pointer(mouse, out)
+            },
+            Msg::Enter(name) => { out.tag(MSGS, "enter").string(name); },
+            Msg::Leave(name) => { out.tag(MSGS, "leave").string(name); },
+            Msg::Gesture(gesture, mouse) => {
+                out.tag(MSGS, "gesture").tag(GESTURES, match gesture {
+                    Gesture::DoubleClick => "double_click",
+                    Gesture::TripleClick => "triple_click",
+                    Gesture::DragStart => "drag_start",
+                    Gesture::DragMove => "drag_move",
+                    Gesture::DragEnd => "drag_end",
+                    Gesture::LongPress => "long_press",
+                });
+                encode_pointer(mouse, out)
+            },
+            Msg::Locked => { out.tag(MSGS, "locked"); },
+            Msg::LockTimeout => { out.tag(MSGS, "lock_timeout"); },
+            Msg::Error(f) => {
+                out.tag(MSGS, "error");
+                encode_failure(f, out)
+            },
+            Msg::Resumed => { out.tag(MSGS, "resumed"); },
+            Msg::Signal(kind) => {
+                out.tag(MSGS, "signal").tag(SIGNALS, match kind {
+                    SignalKind::Interrupt => "interrupt",
+                    SignalKind::Terminate => "terminate",
+                    SignalKind::Hangup => "hangup",
+                });
+            },
+        }
+    }
+
+    fn decode<D: Decoder>(input: &mut D) -> Result<Self> {
+        Ok(match input.tag(MSGS)? {
+            "received" => Msg::Received(decode_event(input)?),
+            "response" => Msg::Response(Reply::decode(input)?),
+            "unsupported" => Msg::Unsupported,
+            "key" => {
+                let code = decode_key_code(input)?;
+                let modifiers = input.int()?;
+                let kind = match input.tag(KEY_KINDS)? {
+                    "press" => KeyKind::Press,
+                    "repeat" => KeyKind::Repeat,
+                    "release" => KeyKind::Release,
+                    t => return unknown(t),
+                };
+                Msg::Key(Key { code, modifiers, kind, text: input.string()? })
+            },
+            "mouse" => Msg::Mouse(decode_pointer(input)?),
+            "region" => Msg::Region(input.string()?, decode_pointer(input)?),
+            "enter" => Msg::Enter(input.string()?),
+            "leave" => Msg::Leave(input.string()?),
+            "gesture" => {
+                let gesture = match input.tag(GESTURES)? {
+                    "double_click" => Gesture::DoubleClick,
+                    "triple_click" => Gesture::TripleClick,
+                    "drag_start" => Gesture::DragStart,
+                    "drag_move" => Gesture::DragMove,
+                    "drag_end" => Gesture::DragEnd,
+                    "long_press" => Gesture::LongPress,
+                    t => return unknown(t),
+                };
+                Msg::Gesture(gesture, decode_pointer(input)?)
+            },
+            "locked" => Msg::Locked,
+            "lock_timeout" => Msg::LockTimeout,
+            "error" => Msg::Error(decode_failure(input)?),
+            "resumed" => Msg::Resumed,
+            "signal" => Msg::Signal(match input.tag(SIGNALS)? {
+                "interrupt" => SignalKind::Interrupt,
+                "terminate" => SignalKind::Terminate,
+                "hangup" => SignalKind::Hangup,
+                t => return unknown(t),
+            }),
+            t => return unknown(t),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let cell = |glyph: &str, is_wide, is_part, logical| Some(CellData {
+            glyph: glyph.to_string(), is_wide, is_part, logical,
+            style: (Color::Rgb{ r: 1, g: 2, b: 3 }, Color::AnsiValue(200), 7),
+        });
+        Snapshot {
+            size: (3, 1), cursor: (2, 0), marker: (-1, 0), tab_size: 8,
+            style: (Color::Red, Color::Reset, 0),
+            cells: vec![
+                cell("你", true, false, Some((1, true))),
+                cell("", false, true, None),
+                None,
+            ],
+            is_raw: true, is_mouse: false, is_cursor: true, is_bidi: false,
+            mouse_mode: Some((MouseLevel::Drag, MouseEncoding::Sgr)),
+            keyboard: vec![1, 3],
+        }
+    }
+
+    fn roundtrip<T: Wire + PartialEq + std::fmt::Debug>(value: &T) {
+        // Both forms decode back to the original value.
+        let text = to_text(value);
+        let bytes = to_bytes(value);
+        let from_text: T = from_text(&text)
+            .unwrap_or_else(|e| panic!("{}: {}", text, e));
+        let from_bytes: T = from_bytes(&bytes)
+            .unwrap_or_else(|e| panic!("{}: {}", text, e));
+        assert_eq!(&from_text, value, "{}", text);
+        assert_eq!(&from_bytes, value, "{}", text);
+    }
+
+    #[test]
+    fn test_wire_actions() {
+        use crate::dispatcher::message::kitty::{ DISAMBIGUATE, TEXT };
+        let colors = vec![
+            Color::Reset, Color::Black, Color::DarkGrey, Color::Red,
+            Color::DarkRed, Color::Green, Color::DarkGreen, Color::Yellow,
+            Color::DarkYellow, Color::Blue, Color::DarkBlue, Color::Magenta,
+            Color::DarkMagenta, Color::Cyan, Color::DarkCyan, Color::White,
+            Color::Grey, Color::Rgb{ r: 0, g: 128, b: 255 },
+            Color::AnsiValue(0), Color::AnsiValue(255),
+        ];
+        let clears = vec![
+            Clear::All, Clear::CursorDn, Clear::CursorUp,
+            Clear::CurrentLn, Clear::NewLn,
+        ];
+        let mut actions = vec![
+            Goto(0, 0), Goto(-1, i16::MAX), Up(1), Down(2), Left(3),
+            Right(4), Prints("tab\t\"quoted\" \\ \u{1b}[0m 你好".into()),
+            Prints("".into()), SetContent("line\nbreak".into(), 5, 6),
+            Flush, Render, Resize(80, 24), SetFx(1 << 10 | 1 << 13),
+            SetStyles(Color::Red, Color::Rgb{ r: 1, g: 2, b: 3 }, 0),
+            ResetStyles, HideCursor, ShowCursor, EnableMouse, DisableMouse,
+            Raw, Cook, NewScreen, SwitchTo(0), SwitchTo(u32::MAX as usize),
+            CloseScreen(3), PushScreen(4), PopScreen, Resized,
+            SyncSize(132, 43), SyncMarker(7, 8), Jump, SyncTabSize(4),
+            SyncBidi(true), SyncBidi(false), Restore(snapshot()),
+            StartCast("/tmp/demo.cast".into()), StopCast,
+            PushKeyboard(DISAMBIGUATE | TEXT), PopKeyboard, SuspendProcess,
+            SetMouse(MouseLevel::Off, MouseEncoding::Legacy),
+            SetMouse(MouseLevel::Button, MouseEncoding::Sgr),
+            SetMouse(MouseLevel::Drag, MouseEncoding::SgrPixels),
+            SetMouse(MouseLevel::Motion, MouseEncoding::Sgr),
+        ];
+        for c in colors { actions.push(SetFg(c)); actions.push(SetBg(c)) }
+        for c in clears { actions.push(Clear(c)) }
+        for action in actions.iter() { roundtrip(action) }
+
+        assert_eq!(to_text(&Goto(10, 4)), "action goto 10 4");
+        assert_eq!(to_bytes(&Goto(10, -4)), vec![VERSION, 0, 0, 20, 7]);
+        assert_eq!(name(&Prints("abc".into())), "prints");
+        assert_eq!(name(&SetMouse(MouseLevel::Off, MouseEncoding::Sgr)),
+            "set_mouse");
+    }
+
+    #[test]
+    fn test_wire_queries_and_replies() {
+        let queries = vec![
+            Query::Size(1), Query::Coord(2), Query::Pos(3), Query::GetCh(4),
+            Query::GetLn(5), Query::Screen(6), Query::ListScreens(7),
+            Query::Snapshot(8, 9), Query::Dump(1, 2, Format::Plain),
+            Query::Dump(1, 2, Format::Ansi), Query::Dump(1, 2, Format::Html),
+            Query::_IsRaw(10), Query::LastError(11),
+        ];
+        for query in queries.iter() { roundtrip(query) }
+
+        let replies = vec![
+            Reply::Size(80, 24), Reply::Coord(1, 2), Reply::Pos(3, 4),
+            Reply::GetCh("你".into()), Reply::GetLn("a line".into()),
+            Reply::Screen(1), Reply::Screens(vec![]),
+            Reply::Screens(vec![0, 1, 300]), Reply::Snapshot(None),
+            Reply::Snapshot(Some(snapshot())), Reply::Dump(None),
+            Reply::Dump(Some("<pre>\n</pre>".into())),
+            Reply::_IsRaw(true), Reply::Empty, Reply::LastError(None),
+            Reply::LastError(Some(Failure {
+                action: "flush".into(), class: ErrorClass::Disconnected,
+                details: "Broken pipe (os error 32)".into(), is_fatal: true,
+            })),
+        ];
+        for reply in replies.iter() { roundtrip(reply) }
+    }
+
+    #[test]
+    fn test_wire_msgs() {
+        let keys = vec![
+            KeyEvent::Backspace, KeyEvent::Enter, KeyEvent::Left,
+            KeyEvent::Right, KeyEvent::Up, KeyEvent::Down, KeyEvent::Home,
+            KeyEvent::End, KeyEvent::PageUp, KeyEvent::PageDown,
+            KeyEvent::Tab, KeyEvent::BackTab, KeyEvent::Delete,
+            KeyEvent::Insert, KeyEvent::F(1), KeyEvent::F(12),
+            KeyEvent::Char('a'), KeyEvent::Char('"'), KeyEvent::Char(' '),
+            KeyEvent::Alt('x'), KeyEvent::Ctrl('q'), KeyEvent::Null,
+            KeyEvent::Esc, KeyEvent::CtrlUp, KeyEvent::CtrlDown,
+            KeyEvent::CtrlRight, KeyEvent::CtrlLeft, KeyEvent::ShiftUp,
+            KeyEvent::ShiftDown, KeyEvent::ShiftRight, KeyEvent::ShiftLeft,
+        ];
+        let buttons = vec![
+            MouseButton::Left, MouseButton::Right, MouseButton::Middle,
+            MouseButton::WheelUp, MouseButton::WheelDown,
+        ];
+        let mut msgs = vec![
+            Msg::Unsupported,
+            Msg::Response(Reply::Size(80, 24)),
+            Msg::Received(InputEvent::Unsupported(vec![27, 91, 255])),
+            Msg::Received(InputEvent::Unknown),
+            Msg::Received(InputEvent::CursorPos(12, 3)),
+            Msg::Received(InputEvent::Mouse(MouseEvent::Release(1, 2))),
+            Msg::Received(InputEvent::Mouse(MouseEvent::Hold(3, 4))),
+            Msg::Received(InputEvent::Mouse(MouseEvent::Unknown)),
+            Msg::Key(Key {
+                code: KeyCode::Char('a'), modifiers: Key::CTRL | Key::SHIFT,
+                kind: KeyKind::Repeat, text: "A".into(),
+            }),
+            Msg::Key(Key {
+                code: KeyCode::Other(57399), modifiers: Key::NUM_LOCK,
+                kind: KeyKind::Release, text: String::new(),
+            }),
+            Msg::Region("ok \"button\"".into(), Mouse {
+                kind: MouseKind::Press, button: Some(Button::Left),
+                col: 1, row: 0, modifiers: 0,
+            }),
+            Msg::Enter("panel".into()),
+            Msg::Leave("panel".into()),
+            Msg::Locked,
+            Msg::LockTimeout,
+            Msg::Error(Failure {
+                action: "prints".into(), class: ErrorClass::Interrupted,
+                details: "".into(), is_fatal: false,
+            }),
+            Msg::Resumed,
+            Msg::Signal(SignalKind::Interrupt),
+            Msg::Signal(SignalKind::Terminate),
+            Msg::Signal(SignalKind::Hangup),
+        ];
+        let gestures = [
+            Gesture::DoubleClick, Gesture::TripleClick, Gesture::DragStart,
+            Gesture::DragMove, Gesture::DragEnd, Gesture::LongPress,
+        ];
+        for gesture in gestures.iter() {
+            msgs.push(Msg::Gesture(*gesture, Mouse {
+                kind: MouseKind::Press, button: Some(Button::Right),
+                col: 4, row: 2, modifiers: Key::SHIFT,
+            }))
+        }
+        let codes = vec![
+            KeyCode::Esc, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace,
+            KeyCode::Insert, KeyCode::Delete, KeyCode::Left, KeyCode::Right,
+            KeyCode::Up, KeyCode::Down, KeyCode::PageUp, KeyCode::PageDown,
+            KeyCode::Home, KeyCode::End, KeyCode::F(5),
+        ];
+        let mouse_buttons = vec![
+            Some(Button::Left), Some(Button::Middle), Some(Button::Right),
+            Some(Button::WheelUp), Some(Button::WheelDown),
+            Some(Button::WheelLeft), Some(Button::WheelRight),
+            Some(Button::Extra(9)), None,
+        ];
+        for (i, button) in mouse_buttons.into_iter().enumerate() {
+            let kind = [MouseKind::Press, MouseKind::Release,
+                MouseKind::Drag, MouseKind::Move][i % 4];
+            msgs.push(Msg::Mouse(Mouse {
+                kind, button, col: 300, row: i as i16, modifiers: Key::CTRL,
+            }))
+        }
+        for code in codes {
+            msgs.push(Msg::Key(Key {
+                code, modifiers: Key::SUPER,
+                kind: KeyKind::Press, text: String::new(),
+            }))
+        }
+        for kv in keys {
+            msgs.push(Msg::Received(InputEvent::Keyboard(kv)))
+        }
+        for mb in buttons {
+            msgs.push(Msg::Received(
+                InputEvent::Mouse(MouseEvent::Press(mb, 5, 6))))
+        }
+        for msg in msgs.iter() { roundtrip(msg) }
+
+        assert_eq!(
+            to_text(&Msg::Received(InputEvent::Keyboard(KeyEvent::Ctrl('q')))),
+            "msg received key ctrl \"q\"");
+    }
+
+    #[test]
+    fn test_wire_errors() {
+        // Malformed text:
+        assert!(from_text::<Action>("action prints \"abc").is_err());
+        assert!(from_text::<Action>("action prints \"\\q\"").is_err());
+        assert!(from_text::<Action>("action goto 1").is_err());
+        assert!(from_text::<Action>("action goto 1 2 3").is_err());
+        assert!(from_text::<Action>("action goto 1 99999").is_err());
+        assert!(from_text::<Action>("action fly 1").is_err());
+        assert!(from_text::<Action>("query size 0").is_err());
+        // Malformed bytes:
+        let mut bytes = to_bytes(&Prints("abc".into()));
+        assert!(from_bytes::<Action>(&bytes[..bytes.len() - 1]).is_err());
+        bytes[0] = VERSION + 1;
+        assert!(from_bytes::<Action>(&bytes).is_err());
+        assert!(from_bytes::<Action>(&[VERSION, 0, 200]).is_err());
+        assert!(from_bytes::<Query>(&[VERSION, 0, 0, 0]).is_err());
+    }
+}
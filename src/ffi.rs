@@ -0,0 +1,1286 @@
+// This module provides the C ABI that allows the Dispatcher and EventHandles
+// to be wrapped by other languages. The matching header is generated with
+// cbindgen into `include/tuitty.h` (see `cbindgen.toml`).
+//
+// (imdaveho) NOTE: Dispatchers, EventHandles, Snapshots and strings returned
+// across the boundary are heap allocated by Rust and must be handed back to
+// their respective `tuitty_*_free` functions. Functions that send a signal
+// or make a request return 0 on success and -1 on failure. Every pointer
+// argument must either be null or point to a live value of that type; the
+// safety contract is the same for all `unsafe` functions below.
+//
+// Panics do not unwind into the caller: a function that panics returns its
+// failure value instead (NULL, -1 or an empty event).
+#![allow(clippy::missing_safety_doc)]
+use std::{
+    ptr, time::Duration, ffi::{ CStr, CString }, os::raw::{ c_char, c_int },
+    panic::AssertUnwindSafe,
+};
+use crate::dispatcher::{ Dispatcher, EventHandle, catch_unwind };
+use crate::dispatcher::message::{
+    Action::{ self, * }, Msg, Reply, Format, Snapshot, MouseLevel,
+    MouseEncoding, ErrorClass, Policy, SignalKind, Key, KeyCode, KeyKind,
+    Mouse, MouseKind, Button, Gesture, Failure,
+};
+use crate::tuitty_core::common::enums::{
+    Clear, Color, Effect, InputEvent, KeyEvent, MouseEvent, MouseButton,
+};
+
+
+// Event kinds
+pub const TUITTY_EVENT_NONE: u8 = 0;
+pub const TUITTY_EVENT_KEYBOARD: u8 = 1;
+pub const TUITTY_EVENT_MOUSE: u8 = 2;
+pub const TUITTY_EVENT_CURSOR_POS: u8 = 3;
+pub const TUITTY_EVENT_RESPONSE: u8 = 4;
+pub const TUITTY_EVENT_LOCKED: u8 = 5;
+pub const TUITTY_EVENT_LOCK_TIMEOUT: u8 = 6;
+pub const TUITTY_EVENT_ERROR: u8 = 7;
+pub const TUITTY_EVENT_RESUMED: u8 = 8;
+pub const TUITTY_EVENT_SIGNAL: u8 = 9;
+pub const TUITTY_EVENT_REGION: u8 = 10;
+pub const TUITTY_EVENT_ENTER: u8 = 11;
+pub const TUITTY_EVENT_LEAVE: u8 = 12;
+pub const TUITTY_EVENT_GESTURE: u8 = 13;
+pub const TUITTY_EVENT_UNSUPPORTED: u8 = 255;
+
+// Key kinds
+pub const TUITTY_KEY_NONE: u8 = 0;
+pub const TUITTY_KEY_CHAR: u8 = 1;
+pub const TUITTY_KEY_ALT: u8 = 2;
+pub const TUITTY_KEY_CTRL: u8 = 3;
+pub const TUITTY_KEY_F: u8 = 4;
+pub const TUITTY_KEY_BACKSPACE: u8 = 5;
+pub const TUITTY_KEY_ENTER: u8 = 6;
+pub const TUITTY_KEY_LEFT: u8 = 7;
+pub const TUITTY_KEY_RIGHT: u8 = 8;
+pub const TUITTY_KEY_UP: u8 = 9;
+pub const TUITTY_KEY_DOWN: u8 = 10;
+pub const TUITTY_KEY_HOME: u8 = 11;
+pub const TUITTY_KEY_END: u8 = 12;
+pub const TUITTY_KEY_PAGE_UP: u8 = 13;
+pub const TUITTY_KEY_PAGE_DOWN: u8 = 14;
+pub const TUITTY_KEY_TAB: u8 = 15;
+pub const TUITTY_KEY_BACK_TAB: u8 = 16;
+pub const TUITTY_KEY_DELETE: u8 = 17;
+pub const TUITTY_KEY_INSERT: u8 = 18;
+pub const TUITTY_KEY_NULL: u8 = 19;
+pub const TUITTY_KEY_ESC: u8 = 20;
+pub const TUITTY_KEY_CTRL_UP: u8 = 21;
+pub const TUITTY_KEY_CTRL_DOWN: u8 = 22;
+pub const TUITTY_KEY_CTRL_RIGHT: u8 = 23;
+pub const TUITTY_KEY_CTRL_LEFT: u8 = 24;
+pub const TUITTY_KEY_SHIFT_UP: u8 = 25;
+pub const TUITTY_KEY_SHIFT_DOWN: u8 = 26;
+pub const TUITTY_KEY_SHIFT_RIGHT: u8 = 27;
+pub const TUITTY_KEY_SHIFT_LEFT: u8 = 28;
+pub const TUITTY_KEY_OTHER: u8 = 29;
+
+// Key event kinds (see tuitty_push_keyboard)
+pub const TUITTY_KEY_PRESS: u8 = 0;
+pub const TUITTY_KEY_REPEAT: u8 = 1;
+pub const TUITTY_KEY_RELEASE: u8 = 2;
+
+// Modifiers of key and mouse events
+pub const TUITTY_MOD_SHIFT: u8 = 1;
+pub const TUITTY_MOD_ALT: u8 = 2;
+pub const TUITTY_MOD_CTRL: u8 = 4;
+pub const TUITTY_MOD_SUPER: u8 = 8;
+pub const TUITTY_MOD_HYPER: u8 = 16;
+pub const TUITTY_MOD_META: u8 = 32;
+pub const TUITTY_MOD_CAPS_LOCK: u8 = 64;
+pub const TUITTY_MOD_NUM_LOCK: u8 = 128;
+
+// Mouse kinds
+pub const TUITTY_MOUSE_NONE: u8 = 0;
+pub const TUITTY_MOUSE_PRESS: u8 = 1;
+pub const TUITTY_MOUSE_RELEASE: u8 = 2;
+pub const TUITTY_MOUSE_HOLD: u8 = 3;
+pub const TUITTY_MOUSE_MOVE: u8 = 4;
+
+// Mouse buttons
+pub const TUITTY_BUTTON_NONE: u8 = 0;
+pub const TUITTY_BUTTON_LEFT: u8 = 1;
+pub const TUITTY_BUTTON_RIGHT: u8 = 2;
+pub const TUITTY_BUTTON_MIDDLE: u8 = 3;
+pub const TUITTY_BUTTON_WHEEL_UP: u8 = 4;
+pub const TUITTY_BUTTON_WHEEL_DOWN: u8 = 5;
+pub const TUITTY_BUTTON_WHEEL_LEFT: u8 = 6;
+pub const TUITTY_BUTTON_WHEEL_RIGHT: u8 = 7;
+pub const TUITTY_BUTTON_EXTRA: u8 = 8;
+
+// Gestures (see TUITTY_EVENT_GESTURE)
+pub const TUITTY_GESTURE_NONE: u8 = 0;
+pub const TUITTY_GESTURE_DOUBLE_CLICK: u8 = 1;
+pub const TUITTY_GESTURE_TRIPLE_CLICK: u8 = 2;
+pub const TUITTY_GESTURE_DRAG_START: u8 = 3;
+pub const TUITTY_GESTURE_DRAG_MOVE: u8 = 4;
+pub const TUITTY_GESTURE_DRAG_END: u8 = 5;
+pub const TUITTY_GESTURE_LONG_PRESS: u8 = 6;
+
+// Sizes of the strings in TuittyEvent (including the NUL terminator)
+pub const TUITTY_NAME_LEN: usize = 64;
+pub const TUITTY_DETAILS_LEN: usize = 256;
+
+// Colors (see TuittyColor)
+pub const TUITTY_COLOR_RESET: u8 = 0;
+pub const TUITTY_COLOR_BLACK: u8 = 1;
+pub const TUITTY_COLOR_DARK_GREY: u8 = 2;
+pub const TUITTY_COLOR_RED: u8 = 3;
+pub const TUITTY_COLOR_DARK_RED: u8 = 4;
+pub const TUITTY_COLOR_GREEN: u8 = 5;
+pub const TUITTY_COLOR_DARK_GREEN: u8 = 6;
+pub const TUITTY_COLOR_YELLOW: u8 = 7;
+pub const TUITTY_COLOR_DARK_YELLOW: u8 = 8;
+pub const TUITTY_COLOR_BLUE: u8 = 9;
+pub const TUITTY_COLOR_DARK_BLUE: u8 = 10;
+pub const TUITTY_COLOR_MAGENTA: u8 = 11;
+pub const TUITTY_COLOR_DARK_MAGENTA: u8 = 12;
+pub const TUITTY_COLOR_CYAN: u8 = 13;
+pub const TUITTY_COLOR_DARK_CYAN: u8 = 14;
+pub const TUITTY_COLOR_WHITE: u8 = 15;
+pub const TUITTY_COLOR_GREY: u8 = 16;
+pub const TUITTY_COLOR_ANSI: u8 = 17;
+pub const TUITTY_COLOR_RGB: u8 = 18;
+
+// Clear kinds
+pub const TUITTY_CLEAR_ALL: u8 = 0;
+pub const TUITTY_CLEAR_CURSOR_DN: u8 = 1;
+pub const TUITTY_CLEAR_CURSOR_UP: u8 = 2;
+pub const TUITTY_CLEAR_CURRENT_LN: u8 = 3;
+pub const TUITTY_CLEAR_NEW_LN: u8 = 4;
+
+// Effects (see tuitty_fx)
+pub const TUITTY_FX_RESET: u8 = 0;
+pub const TUITTY_FX_BOLD: u8 = 1;
+pub const TUITTY_FX_DIM: u8 = 2;
+pub const TUITTY_FX_UNDERLINE: u8 = 3;
+pub const TUITTY_FX_REVERSE: u8 = 4;
+pub const TUITTY_FX_HIDE: u8 = 5;
+
+// Mouse tracking levels (see tuitty_set_mouse)
+pub const TUITTY_MOUSE_LEVEL_OFF: u8 = 0;
+pub const TUITTY_MOUSE_LEVEL_BUTTON: u8 = 1;
+pub const TUITTY_MOUSE_LEVEL_DRAG: u8 = 2;
+pub const TUITTY_MOUSE_LEVEL_MOTION: u8 = 3;
+
+// Mouse encodings (see tuitty_set_mouse)
+pub const TUITTY_MOUSE_ENCODING_LEGACY: u8 = 0;
+pub const TUITTY_MOUSE_ENCODING_SGR: u8 = 1;
+pub const TUITTY_MOUSE_ENCODING_SGR_PIXELS: u8 = 2;
+
+// Kitty keyboard flags (see tuitty_push_keyboard)
+pub const TUITTY_KITTY_DISAMBIGUATE: u8 = 1;
+pub const TUITTY_KITTY_EVENT_TYPES: u8 = 2;
+pub const TUITTY_KITTY_ALTERNATE_KEYS: u8 = 4;
+pub const TUITTY_KITTY_ALL_KEYS: u8 = 8;
+pub const TUITTY_KITTY_TEXT: u8 = 16;
+
+// Error classes (see tuitty_dispatcher_on_error)
+pub const TUITTY_ERROR_DISCONNECTED: u8 = 0;
+pub const TUITTY_ERROR_INTERRUPTED: u8 = 1;
+pub const TUITTY_ERROR_OTHER: u8 = 2;
+
+// Error policies (see tuitty_dispatcher_on_error)
+pub const TUITTY_POLICY_IGNORE: u8 = 0;
+pub const TUITTY_POLICY_RETRY: u8 = 1;
+pub const TUITTY_POLICY_SHUTDOWN: u8 = 2;
+
+// Signals
+pub const TUITTY_SIGNAL_INTERRUPT: u8 = 0;
+pub const TUITTY_SIGNAL_TERMINATE: u8 = 1;
+pub const TUITTY_SIGNAL_HANGUP: u8 = 2;
+
+// Dump formats
+pub const TUITTY_FORMAT_PLAIN: u8 = 0;
+pub const TUITTY_FORMAT_ANSI: u8 = 1;
+pub const TUITTY_FORMAT_HTML: u8 = 2;
+
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TuittyColor {
+    // One of TUITTY_COLOR_*
+    pub kind: u8,
+    // Used by TUITTY_COLOR_ANSI
+    pub value: u8,
+    // Used by TUITTY_COLOR_RGB
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TuittyEvent {
+    // One of TUITTY_EVENT_*
+    pub kind: u8,
+    // One of TUITTY_KEY_*
+    pub key: u8,
+    // The unicode scalar for CHAR, ALT, CTRL, the number for F, the code of
+    // OTHER, the number of TUITTY_BUTTON_EXTRA (8 to 11) or one of
+    // TUITTY_SIGNAL_* for TUITTY_EVENT_SIGNAL.
+    pub ch: u32,
+    // One of TUITTY_KEY_PRESS, TUITTY_KEY_REPEAT or TUITTY_KEY_RELEASE.
+    pub key_kind: u8,
+    // TUITTY_MOD_* of key and mouse events (combined with a bitwise OR).
+    pub modifiers: u8,
+    // One of TUITTY_MOUSE_*
+    pub mouse: u8,
+    // One of TUITTY_BUTTON_*
+    pub button: u8,
+    // One of TUITTY_GESTURE_*
+    pub gesture: u8,
+    // One of TUITTY_ERROR_* and whether it shut down the dispatcher (1 or
+    // 0) for TUITTY_EVENT_ERROR.
+    pub error_class: u8,
+    pub is_fatal: u8,
+    // Mouse (in region coordinates for TUITTY_EVENT_REGION) or cursor
+    // position.
+    pub col: i16,
+    pub row: i16,
+    // The region for REGION, ENTER and LEAVE, the failing action for ERROR,
+    // or the text of a key (with TUITTY_KITTY_TEXT). NUL terminated, and
+    // truncated to fit.
+    pub name: [c_char; TUITTY_NAME_LEN],
+    // The details of the error for ERROR. NUL terminated, and truncated to
+    // fit.
+    pub details: [c_char; TUITTY_DETAILS_LEN],
+}
+
+impl TuittyEvent {
+    fn empty() -> TuittyEvent {
+        TuittyEvent {
+            kind: TUITTY_EVENT_NONE, key: TUITTY_KEY_NONE, ch: 0,
+            key_kind: TUITTY_KEY_PRESS, modifiers: 0,
+            mouse: TUITTY_MOUSE_NONE, button: TUITTY_BUTTON_NONE,
+            gesture: TUITTY_GESTURE_NONE, error_class: 0, is_fatal: 0,
+            col: 0, row: 0,
+            name: [0; TUITTY_NAME_LEN], details: [0; TUITTY_DETAILS_LEN],
+        }
+    }
+
+    fn with_mouse(&mut self, mouse: &Mouse) {
+        self.mouse = match mouse.kind {
+            MouseKind::Press => TUITTY_MOUSE_PRESS,
+            MouseKind::Release => TUITTY_MOUSE_RELEASE,
+            MouseKind::Drag => TUITTY_MOUSE_HOLD,
+            MouseKind::Move => TUITTY_MOUSE_MOVE,
+        };
+        self.button = match mouse.button {
+            Some(Button::Left) => TUITTY_BUTTON_LEFT,
+            Some(Button::Middle) => TUITTY_BUTTON_MIDDLE,
+            Some(Button::Right) => TUITTY_BUTTON_RIGHT,
+            Some(Button::WheelUp) => TUITTY_BUTTON_WHEEL_UP,
+            Some(Button::WheelDown) => TUITTY_BUTTON_WHEEL_DOWN,
+            Some(Button::WheelLeft) => TUITTY_BUTTON_WHEEL_LEFT,
+            Some(Button::WheelRight) => TUITTY_BUTTON_WHEEL_RIGHT,
+            Some(Button::Extra(n)) => {
+                self.ch = n as u32;
+                TUITTY_BUTTON_EXTRA
+            },
+            None => TUITTY_BUTTON_NONE,
+        };
+        self.modifiers = mouse.modifiers;
+        self.col = mouse.col;
+        self.row = mouse.row;
+    }
+
+    fn with_key(&mut self, key: &Key) {
+        // Keys that a legacy terminal would send are reported the same way,
+        // the rest by their code (along with the modifiers).
+        let (code, ch) = match key.legacy() {
+            Some(kv) => key_kind(&kv),
+            None => code_kind(&key.code),
+        };
+        self.key = code;
+        self.ch = ch;
+        self.key_kind = match key.kind {
+            KeyKind::Press => TUITTY_KEY_PRESS,
+            KeyKind::Repeat => TUITTY_KEY_REPEAT,
+            KeyKind::Release => TUITTY_KEY_RELEASE,
+        };
+        self.modifiers = key.modifiers;
+        copy_str(&mut self.name, &key.text);
+    }
+
+    fn with_failure(&mut self, failure: &Failure) {
+        self.error_class = match failure.class {
+            ErrorClass::Disconnected => TUITTY_ERROR_DISCONNECTED,
+            ErrorClass::Interrupted => TUITTY_ERROR_INTERRUPTED,
+            ErrorClass::Other => TUITTY_ERROR_OTHER,
+        };
+        self.is_fatal = failure.is_fatal as u8;
+        copy_str(&mut self.name, &failure.action);
+        copy_str(&mut self.details, &failure.details);
+    }
+
+    fn from_msg(msg: &Msg) -> TuittyEvent {
+        let mut evt = TuittyEvent::empty();
+        match msg {
+            Msg::Received(iv) => match iv {
+                InputEvent::Keyboard(kv) => {
+                    evt.kind = TUITTY_EVENT_KEYBOARD;
+                    let (key, ch) = key_kind(kv);
+                    evt.key = key;
+                    evt.ch = ch;
+                },
+                InputEvent::Mouse(mv) => {
+                    evt.kind = TUITTY_EVENT_MOUSE;
+                    let (mouse, button, col, row) = mouse_kind(mv);
+                    evt.mouse = mouse;
+                    evt.button = button;
+                    evt.col = col;
+                    evt.row = row;
+                },
+                #[cfg(unix)]
+                InputEvent::CursorPos(col, row) => {
+                    evt.kind = TUITTY_EVENT_CURSOR_POS;
+                    evt.col = *col;
+                    evt.row = *row;
+                },
+                _ => evt.kind = TUITTY_EVENT_UNSUPPORTED,
+            },
+            Msg::Key(key) => {
+                evt.kind = TUITTY_EVENT_KEYBOARD;
+                evt.with_key(key);
+            },
+            Msg::Mouse(mouse) => {
+                evt.kind = TUITTY_EVENT_MOUSE;
+                evt.with_mouse(mouse);
+            },
+            Msg::Region(name, mouse) => {
+                evt.kind = TUITTY_EVENT_REGION;
+                evt.with_mouse(mouse);
+                copy_str(&mut evt.name, name);
+            },
+            Msg::Enter(name) => {
+                evt.kind = TUITTY_EVENT_ENTER;
+                copy_str(&mut evt.name, name);
+            },
+            Msg::Leave(name) => {
+                evt.kind = TUITTY_EVENT_LEAVE;
+                copy_str(&mut evt.name, name);
+            },
+            Msg::Gesture(gesture, mouse) => {
+                evt.kind = TUITTY_EVENT_GESTURE;
+                evt.with_mouse(mouse);
+                evt.gesture = match gesture {
+                    Gesture::DoubleClick => TUITTY_GESTURE_DOUBLE_CLICK,
+                    Gesture::TripleClick => TUITTY_GESTURE_TRIPLE_CLICK,
+                    Gesture::DragStart => TUITTY_GESTURE_DRAG_START,
+                    Gesture::DragMove => TUITTY_GESTURE_DRAG_MOVE,
+                    Gesture::DragEnd => TUITTY_GESTURE_DRAG_END,
+                    Gesture::LongPress => TUITTY_GESTURE_LONG_PRESS,
+                };
+            },
+            Msg::Response(_) => evt.kind = TUITTY_EVENT_RESPONSE,
+            Msg::Locked => evt.kind = TUITTY_EVENT_LOCKED,
+            Msg::LockTimeout => evt.kind = TUITTY_EVENT_LOCK_TIMEOUT,
+            Msg::Error(failure) => {
+                evt.kind = TUITTY_EVENT_ERROR;
+                evt.with_failure(failure);
+            },
+            Msg::Resumed => evt.kind = TUITTY_EVENT_RESUMED,
+            Msg::Signal(kind) => {
+                evt.kind = TUITTY_EVENT_SIGNAL;
+                evt.ch = match kind {
+                    SignalKind::Interrupt => TUITTY_SIGNAL_INTERRUPT,
+                    SignalKind::Terminate => TUITTY_SIGNAL_TERMINATE,
+                    SignalKind::Hangup => TUITTY_SIGNAL_HANGUP,
+                } as u32;
+            },
+            _ => evt.kind = TUITTY_EVENT_UNSUPPORTED,
+        }
+        evt
+    }
+}
+
+fn key_kind(kv: &KeyEvent) -> (u8, u32) {
+    match kv {
+        KeyEvent::Char(c) => (TUITTY_KEY_CHAR, *c as u32),
+        KeyEvent::Alt(c) => (TUITTY_KEY_ALT, *c as u32),
+        KeyEvent::Ctrl(c) => (TUITTY_KEY_CTRL, *c as u32),
+        KeyEvent::F(n) => (TUITTY_KEY_F, *n as u32),
+        KeyEvent::Backspace => (TUITTY_KEY_BACKSPACE, 0),
+        KeyEvent::Enter => (TUITTY_KEY_ENTER, 0),
+        KeyEvent::Left => (TUITTY_KEY_LEFT, 0),
+        KeyEvent::Right => (TUITTY_KEY_RIGHT, 0),
+        KeyEvent::Up => (TUITTY_KEY_UP, 0),
+        KeyEvent::Down => (TUITTY_KEY_DOWN, 0),
+        KeyEvent::Home => (TUITTY_KEY_HOME, 0),
+        KeyEvent::End => (TUITTY_KEY_END, 0),
+        KeyEvent::PageUp => (TUITTY_KEY_PAGE_UP, 0),
+        KeyEvent::PageDown => (TUITTY_KEY_PAGE_DOWN, 0),
+        KeyEvent::Tab => (TUITTY_KEY_TAB, 0),
+        KeyEvent::BackTab => (TUITTY_KEY_BACK_TAB, 0),
+        KeyEvent::Delete => (TUITTY_KEY_DELETE, 0),
+        KeyEvent::Insert => (TUITTY_KEY_INSERT, 0),
+        KeyEvent::Null => (TUITTY_KEY_NULL, 0),
+        KeyEvent::Esc => (TUITTY_KEY_ESC, 0),
+        KeyEvent::CtrlUp => (TUITTY_KEY_CTRL_UP, 0),
+        KeyEvent::CtrlDown => (TUITTY_KEY_CTRL_DOWN, 0),
+        KeyEvent::CtrlRight => (TUITTY_KEY_CTRL_RIGHT, 0),
+        KeyEvent::CtrlLeft => (TUITTY_KEY_CTRL_LEFT, 0),
+        KeyEvent::ShiftUp => (TUITTY_KEY_SHIFT_UP, 0),
+        KeyEvent::ShiftDown => (TUITTY_KEY_SHIFT_DOWN, 0),
+        KeyEvent::ShiftRight => (TUITTY_KEY_SHIFT_RIGHT, 0),
+        KeyEvent::ShiftLeft => (TUITTY_KEY_SHIFT_LEFT, 0),
+        #[allow(unreachable_patterns)]
+        _ => (TUITTY_KEY_NONE, 0),
+    }
+}
+
+fn code_kind(code: &KeyCode) -> (u8, u32) {
+    match code {
+        KeyCode::Char(c) => (TUITTY_KEY_CHAR, *c as u32),
+        KeyCode::Esc => (TUITTY_KEY_ESC, 0),
+        KeyCode::Enter => (TUITTY_KEY_ENTER, 0),
+        KeyCode::Tab => (TUITTY_KEY_TAB, 0),
+        KeyCode::Backspace => (TUITTY_KEY_BACKSPACE, 0),
+        KeyCode::Insert => (TUITTY_KEY_INSERT, 0),
+        KeyCode::Delete => (TUITTY_KEY_DELETE, 0),
+        KeyCode::Left => (TUITTY_KEY_LEFT, 0),
+        KeyCode::Right => (TUITTY_KEY_RIGHT, 0),
+        KeyCode::Up => (TUITTY_KEY_UP, 0),
+        KeyCode::Down => (TUITTY_KEY_DOWN, 0),
+        KeyCode::PageUp => (TUITTY_KEY_PAGE_UP, 0),
+        KeyCode::PageDown => (TUITTY_KEY_PAGE_DOWN, 0),
+        KeyCode::Home => (TUITTY_KEY_HOME, 0),
+        KeyCode::End => (TUITTY_KEY_END, 0),
+        KeyCode::F(n) => (TUITTY_KEY_F, *n as u32),
+        KeyCode::Other(n) => (TUITTY_KEY_OTHER, *n),
+    }
+}
+
+fn copy_str(dest: &mut [c_char], s: &str) {
+    // Copies as much of `s` as fits (on a char boundary) with a NUL.
+    let mut end = s.len().min(dest.len() - 1);
+    while !s.is_char_boundary(end) { end -= 1 }
+    for (d, b) in dest.iter_mut().zip(s[..end].bytes()) { *d = b as c_char }
+    dest[end] = 0;
+}
+
+fn mouse_kind(mv: &MouseEvent) -> (u8, u8, i16, i16) {
+    match mv {
+        MouseEvent::Press(mb, col, row) => {
+            let button = match mb {
+                MouseButton::Left => TUITTY_BUTTON_LEFT,
+                MouseButton::Right => TUITTY_BUTTON_RIGHT,
+                MouseButton::Middle => TUITTY_BUTTON_MIDDLE,
+                MouseButton::WheelUp => TUITTY_BUTTON_WHEEL_UP,
+                MouseButton::WheelDown => TUITTY_BUTTON_WHEEL_DOWN,
+            };
+            (TUITTY_MOUSE_PRESS, button, *col, *row)
+        },
+        MouseEvent::Release(col, row) =>
+            (TUITTY_MOUSE_RELEASE, TUITTY_BUTTON_NONE, *col, *row),
+        MouseEvent::Hold(col, row) =>
+            (TUITTY_MOUSE_HOLD, TUITTY_BUTTON_NONE, *col, *row),
+        _ => (TUITTY_MOUSE_NONE, TUITTY_BUTTON_NONE, 0, 0),
+    }
+}
+
+fn to_color(c: TuittyColor) -> Color {
+    match c.kind {
+        TUITTY_COLOR_BLACK => Color::Black,
+        TUITTY_COLOR_DARK_GREY => Color::DarkGrey,
+        TUITTY_COLOR_RED => Color::Red,
+        TUITTY_COLOR_DARK_RED => Color::DarkRed,
+        TUITTY_COLOR_GREEN => Color::Green,
+        TUITTY_COLOR_DARK_GREEN => Color::DarkGreen,
+        TUITTY_COLOR_YELLOW => Color::Yellow,
+        TUITTY_COLOR_DARK_YELLOW => Color::DarkYellow,
+        TUITTY_COLOR_BLUE => Color::Blue,
+        TUITTY_COLOR_DARK_BLUE => Color::DarkBlue,
+        TUITTY_COLOR_MAGENTA => Color::Magenta,
+        TUITTY_COLOR_DARK_MAGENTA => Color::DarkMagenta,
+        TUITTY_COLOR_CYAN => Color::Cyan,
+        TUITTY_COLOR_DARK_CYAN => Color::DarkCyan,
+        TUITTY_COLOR_WHITE => Color::White,
+        TUITTY_COLOR_GREY => Color::Grey,
+        TUITTY_COLOR_ANSI => Color::AnsiValue(c.value),
+        TUITTY_COLOR_RGB => Color::Rgb{ r: c.r, g: c.g, b: c.b },
+        _ => Color::Reset,
+    }
+}
+
+fn to_clear(clr: u8) -> Clear {
+    match clr {
+        TUITTY_CLEAR_CURSOR_DN => Clear::CursorDn,
+        TUITTY_CLEAR_CURSOR_UP => Clear::CursorUp,
+        TUITTY_CLEAR_CURRENT_LN => Clear::CurrentLn,
+        TUITTY_CLEAR_NEW_LN => Clear::NewLn,
+        _ => Clear::All,
+    }
+}
+
+fn to_format(format: u8) -> Format {
+    match format {
+        TUITTY_FORMAT_ANSI => Format::Ansi,
+        TUITTY_FORMAT_HTML => Format::Html,
+        _ => Format::Plain,
+    }
+}
+
+unsafe fn to_string(s: *const c_char) -> Option<String> {
+    if s.is_null() { return None }
+    Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+}
+
+fn into_raw(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(c) => c.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+
+// Returns the effect bits to pass to `tuitty_set_fx` or `tuitty_set_styles`.
+// Multiple effects can be combined with a bitwise OR.
+#[no_mangle]
+pub extern "C" fn tuitty_fx(kind: u8) -> u32 {
+    match kind {
+        TUITTY_FX_BOLD => Effect::Bold as u32,
+        TUITTY_FX_DIM => Effect::Dim as u32,
+        TUITTY_FX_UNDERLINE => Effect::Underline as u32,
+        TUITTY_FX_REVERSE => Effect::Reverse as u32,
+        TUITTY_FX_HIDE => Effect::Hide as u32,
+        _ => Effect::Reset as u32,
+    }
+}
+
+
+fn guard<T>(failure: T, f: impl FnOnce() -> T) -> T {
+    // Unwinding across `extern "C"` aborts the host (or is undefined). The
+    // caller recovers, so the terminal is not restored (see `restore`).
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(failure)
+}
+
+
+// DISPATCHER
+#[no_mangle]
+pub extern "C" fn tuitty_dispatcher_init() -> *mut Dispatcher {
+    guard(ptr::null_mut(), || Box::into_raw(Box::new(Dispatcher::init())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_free(dispatch: *mut Dispatcher) {
+    if dispatch.is_null() { return }
+    guard((), || drop(Box::from_raw(dispatch)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_listen(
+    dispatch: *mut Dispatcher
+) -> *mut EventHandle {
+    match dispatch.as_mut() {
+        Some(d) => guard(ptr::null_mut(), ||
+            Box::into_raw(Box::new(d.listen()))),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_spawn(
+    dispatch: *const Dispatcher
+) -> *mut EventHandle {
+    match dispatch.as_ref() {
+        Some(d) => guard(ptr::null_mut(), ||
+            Box::into_raw(Box::new(d.spawn()))),
+        None => ptr::null_mut(),
+    }
+}
+
+// Restores the terminal on a Rust panic, unless it is caught at the C ABI
+// (see `Dispatcher::restore_on_panic`).
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_restore_on_panic(
+    dispatch: *const Dispatcher
+) {
+    if let Some(d) = dispatch.as_ref() { guard((), || d.restore_on_panic()) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_restore_on_exit(
+    dispatch: *const Dispatcher
+) {
+    if let Some(d) = dispatch.as_ref() { guard((), || d.restore_on_exit()) }
+}
+
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_grace(
+    dispatch: *const Dispatcher, ms: u64
+) -> c_int {
+    match dispatch.as_ref() {
+        Some(d) => guard(-1, || match d.grace(Duration::from_millis(ms)) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }),
+        None => -1,
+    }
+}
+
+// `retries` is only used by TUITTY_POLICY_RETRY.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_dispatcher_on_error(
+    dispatch: *const Dispatcher, class: u8, policy: u8, retries: u8
+) -> c_int {
+    let class = match class {
+        TUITTY_ERROR_DISCONNECTED => ErrorClass::Disconnected,
+        TUITTY_ERROR_INTERRUPTED => ErrorClass::Interrupted,
+        TUITTY_ERROR_OTHER => ErrorClass::Other,
+        _ => return -1,
+    };
+    let policy = match policy {
+        TUITTY_POLICY_IGNORE => Policy::Ignore,
+        TUITTY_POLICY_RETRY => Policy::Retry(retries),
+        TUITTY_POLICY_SHUTDOWN => Policy::Shutdown,
+        _ => return -1,
+    };
+    match dispatch.as_ref() {
+        Some(d) => guard(-1, || match d.on_error(class, policy) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }),
+        None => -1,
+    }
+}
+
+
+// EVENT HANDLE
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_handle_free(handle: *mut EventHandle) {
+    if handle.is_null() { return }
+    guard((), || drop(Box::from_raw(handle)))
+}
+
+unsafe fn poll(
+    handle: *const EventHandle,
+    event: *mut TuittyEvent,
+    poll_fn: fn(&EventHandle) -> Option<Msg>,
+) -> c_int {
+    let handle = match handle.as_ref() {
+        Some(h) => h,
+        None => return -1,
+    };
+    let evt = guard(TuittyEvent::empty(), || match poll_fn(handle) {
+        Some(msg) => TuittyEvent::from_msg(&msg),
+        None => TuittyEvent::empty(),
+    });
+    if !event.is_null() { *event = evt }
+    if evt.kind == TUITTY_EVENT_NONE { 0 } else { 1 }
+}
+
+// Returns 1 if an event was written to `event`, 0 if there was none.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_poll_async(
+    handle: *const EventHandle, event: *mut TuittyEvent
+) -> c_int {
+    poll(handle, event, EventHandle::poll_async)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_poll_latest_async(
+    handle: *const EventHandle, event: *mut TuittyEvent
+) -> c_int {
+    poll(handle, event, EventHandle::poll_latest_async)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_poll_sync(
+    handle: *const EventHandle, event: *mut TuittyEvent
+) -> c_int {
+    poll(handle, event, EventHandle::poll_sync)
+}
+
+// Returns a file descriptor that becomes readable whenever events are queued
+// for the handle, or -1 on failure. The fd is owned by the handle and is
+// closed by `tuitty_handle_free`.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_fd(handle: *const EventHandle) -> c_int {
+    match handle.as_ref() {
+        Some(h) => guard(-1, || h.fd().unwrap_or(-1)),
+        None => -1,
+    }
+}
+
+unsafe fn command<T, E>(
+    handle: *const EventHandle,
+    cmd_fn: fn(&EventHandle) -> Result<T, E>,
+) -> c_int {
+    match handle.as_ref() {
+        Some(h) => guard(-1, || match cmd_fn(h) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_suspend(handle: *const EventHandle) -> c_int {
+    command(handle, EventHandle::suspend)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_transmit(handle: *const EventHandle) -> c_int {
+    command(handle, EventHandle::transmit)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_stop(handle: *const EventHandle) -> c_int {
+    command(handle, EventHandle::stop)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_lock(handle: *const EventHandle) -> c_int {
+    // Held until tuitty_unlock (or tuitty_handle_free) rather than a guard.
+    command(handle, |h| h.lock().map(std::mem::forget))
+}
+
+// Gives up waiting for the lock after `ms` milliseconds (see
+// TUITTY_EVENT_LOCK_TIMEOUT).
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_lock_timeout(
+    handle: *const EventHandle, ms: u64
+) -> c_int {
+    match handle.as_ref() {
+        Some(h) => guard(-1, || {
+            match h.lock_timeout(Duration::from_millis(ms)) {
+                Ok(held) => { std::mem::forget(held); 0 },
+                Err(_) => -1,
+            }
+        }),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_unlock(handle: *const EventHandle) -> c_int {
+    command(handle, EventHandle::unlock)
+}
+
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_focus(
+    handle: *const EventHandle, share_mouse: bool
+) -> c_int {
+    match handle.as_ref() {
+        Some(h) => guard(-1, || match h.focus(share_mouse) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_blur(handle: *const EventHandle) -> c_int {
+    command(handle, EventHandle::blur)
+}
+
+
+// ACTIONS
+unsafe fn signal(handle: *const EventHandle, action: Action) -> c_int {
+    match handle.as_ref() {
+        Some(h) => guard(-1, || match h.signal(action) {
+            Ok(_) => 0,
+            Err(_) => -1,
+        }),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_goto(
+    handle: *const EventHandle, col: i16, row: i16
+) -> c_int {
+    signal(handle, Goto(col, row))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_up(handle: *const EventHandle, n: i16) -> c_int {
+    signal(handle, Up(n))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_down(
+    handle: *const EventHandle, n: i16
+) -> c_int {
+    signal(handle, Down(n))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_left(
+    handle: *const EventHandle, n: i16
+) -> c_int {
+    signal(handle, Left(n))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_right(
+    handle: *const EventHandle, n: i16
+) -> c_int {
+    signal(handle, Right(n))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_clear(
+    handle: *const EventHandle, clr: u8
+) -> c_int {
+    signal(handle, Action::Clear(to_clear(clr)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_prints(
+    handle: *const EventHandle, s: *const c_char
+) -> c_int {
+    match to_string(s) {
+        Some(s) => signal(handle, Prints(s)),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_content(
+    handle: *const EventHandle, s: *const c_char, col: i16, row: i16
+) -> c_int {
+    match to_string(s) {
+        Some(s) => signal(handle, SetContent(s, col, row)),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_flush(handle: *const EventHandle) -> c_int {
+    signal(handle, Flush)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_render(handle: *const EventHandle) -> c_int {
+    signal(handle, Render)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_resize(
+    handle: *const EventHandle, w: i16, h: i16
+) -> c_int {
+    signal(handle, Resize(w, h))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_fx(
+    handle: *const EventHandle, fx: u32
+) -> c_int {
+    signal(handle, SetFx(fx))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_fg(
+    handle: *const EventHandle, c: TuittyColor
+) -> c_int {
+    signal(handle, SetFg(to_color(c)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_bg(
+    handle: *const EventHandle, c: TuittyColor
+) -> c_int {
+    signal(handle, SetBg(to_color(c)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_styles(
+    handle: *const EventHandle, fg: TuittyColor, bg: TuittyColor, fx: u32
+) -> c_int {
+    signal(handle, SetStyles(to_color(fg), to_color(bg), fx))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_reset_styles(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, ResetStyles)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_hide_cursor(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, HideCursor)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_show_cursor(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, ShowCursor)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_enable_mouse(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, EnableMouse)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_disable_mouse(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, DisableMouse)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_set_mouse(
+    handle: *const EventHandle, level: u8, encoding: u8
+) -> c_int {
+    let level = match level {
+        TUITTY_MOUSE_LEVEL_OFF => MouseLevel::Off,
+        TUITTY_MOUSE_LEVEL_BUTTON => MouseLevel::Button,
+        TUITTY_MOUSE_LEVEL_DRAG => MouseLevel::Drag,
+        TUITTY_MOUSE_LEVEL_MOTION => MouseLevel::Motion,
+        _ => return -1,
+    };
+    let encoding = match encoding {
+        TUITTY_MOUSE_ENCODING_LEGACY => MouseEncoding::Legacy,
+        TUITTY_MOUSE_ENCODING_SGR => MouseEncoding::Sgr,
+        TUITTY_MOUSE_ENCODING_SGR_PIXELS => MouseEncoding::SgrPixels,
+        _ => return -1,
+    };
+    signal(handle, SetMouse(level, encoding))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_raw(handle: *const EventHandle) -> c_int {
+    signal(handle, Raw)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_cook(handle: *const EventHandle) -> c_int {
+    signal(handle, Cook)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_new_screen(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, NewScreen)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_switch_to(
+    handle: *const EventHandle, id: usize
+) -> c_int {
+    signal(handle, SwitchTo(id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_close_screen(
+    handle: *const EventHandle, id: usize
+) -> c_int {
+    signal(handle, CloseScreen(id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_push_screen(
+    handle: *const EventHandle, id: usize
+) -> c_int {
+    signal(handle, PushScreen(id))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_pop_screen(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, PopScreen)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_resized(handle: *const EventHandle) -> c_int {
+    signal(handle, Resized)
+}
+
+// Updates the size of the internal buffer without resizing the terminal.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_sync_size(
+    handle: *const EventHandle, w: i16, h: i16
+) -> c_int {
+    signal(handle, SyncSize(w, h))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_sync_marker(
+    handle: *const EventHandle, col: i16, row: i16
+) -> c_int {
+    signal(handle, SyncMarker(col, row))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_jump(handle: *const EventHandle) -> c_int {
+    signal(handle, Jump)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_sync_tab_size(
+    handle: *const EventHandle, n: usize
+) -> c_int {
+    signal(handle, SyncTabSize(n))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_sync_bidi(
+    handle: *const EventHandle, state: bool
+) -> c_int {
+    signal(handle, SyncBidi(state))
+}
+
+// The snapshot is copied, so it still needs to be freed by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_restore(
+    handle: *const EventHandle, snapshot: *const Snapshot
+) -> c_int {
+    match snapshot.as_ref() {
+        Some(s) => signal(handle, Restore(s.clone())),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_start_cast(
+    handle: *const EventHandle, path: *const c_char
+) -> c_int {
+    match to_string(path) {
+        Some(path) => signal(handle, StartCast(path)),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_stop_cast(handle: *const EventHandle) -> c_int {
+    signal(handle, StopCast)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_push_keyboard(
+    handle: *const EventHandle, flags: u8
+) -> c_int {
+    signal(handle, PushKeyboard(flags))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_pop_keyboard(
+    handle: *const EventHandle
+) -> c_int {
+    signal(handle, PopKeyboard)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_suspend_process(
+    handle: *const EventHandle
+) -> c_int {
+    // The handles get TUITTY_EVENT_RESUMED once the process is continued.
+    signal(handle, SuspendProcess)
+}
+
+
+// REQUESTS
+unsafe fn request(handle: *const EventHandle, query: &str) -> Option<Reply> {
+    match handle.as_ref() {
+        Some(h) => guard(None, || h.request(query).ok()),
+        None => None,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_size(
+    handle: *const EventHandle, w: *mut i16, h: *mut i16
+) -> c_int {
+    match request(handle, "size") {
+        Some(Reply::Size(width, height)) => {
+            if !w.is_null() { *w = width }
+            if !h.is_null() { *h = height }
+            0
+        },
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_coord(
+    handle: *const EventHandle, col: *mut i16, row: *mut i16
+) -> c_int {
+    match request(handle, "coord") {
+        Some(Reply::Coord(c, r)) => {
+            if !col.is_null() { *col = c }
+            if !row.is_null() { *row = r }
+            0
+        },
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_pos(
+    handle: *const EventHandle, col: *mut i16, row: *mut i16
+) -> c_int {
+    match request(handle, "raw_pos") {
+        Some(Reply::Pos(c, r)) => {
+            if !col.is_null() { *col = c }
+            if !row.is_null() { *row = r }
+            0
+        },
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_screen(
+    handle: *const EventHandle, id: *mut usize
+) -> c_int {
+    match request(handle, "screen") {
+        Some(Reply::Screen(i)) => {
+            if !id.is_null() { *id = i }
+            0
+        },
+        _ => -1,
+    }
+}
+
+// Writes up to `len` screen ids into `ids` and returns the total number
+// of screens (which may be more than `len`), or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_screens(
+    handle: *const EventHandle, ids: *mut usize, len: usize
+) -> c_int {
+    match request(handle, "screens") {
+        Some(Reply::Screens(list)) => {
+            if !ids.is_null() {
+                for (i, id) in list.iter().take(len).enumerate() {
+                    *ids.add(i) = *id;
+                }
+            }
+            list.len() as c_int
+        },
+        _ => -1,
+    }
+}
+
+// Returns a string that must be freed with `tuitty_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_getch(
+    handle: *const EventHandle
+) -> *mut c_char {
+    match request(handle, "getch") {
+        Some(Reply::GetCh(s)) => into_raw(s),
+        _ => ptr::null_mut(),
+    }
+}
+
+// Returns a string that must be freed with `tuitty_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_getln(
+    handle: *const EventHandle
+) -> *mut c_char {
+    match request(handle, "getln") {
+        Some(Reply::GetLn(s)) => into_raw(s),
+        _ => ptr::null_mut(),
+    }
+}
+
+// Returns a string that must be freed with `tuitty_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_dump(
+    handle: *const EventHandle, screen: usize, format: u8
+) -> *mut c_char {
+    let reply = match handle.as_ref() {
+        Some(h) => guard(None, || h.dump(screen, to_format(format)).ok()),
+        None => None,
+    };
+    match reply {
+        Some(Reply::Dump(Some(s))) => into_raw(s),
+        _ => ptr::null_mut(),
+    }
+}
+
+// Returns a snapshot that must be freed with `tuitty_snapshot_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_request_snapshot(
+    handle: *const EventHandle, screen: usize
+) -> *mut Snapshot {
+    let reply = match handle.as_ref() {
+        Some(h) => guard(None, || h.snapshot(screen).ok()),
+        None => None,
+    };
+    match reply {
+        Some(Reply::Snapshot(Some(s))) => Box::into_raw(Box::new(s)),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_snapshot_free(snapshot: *mut Snapshot) {
+    if snapshot.is_null() { return }
+    drop(Box::from_raw(snapshot));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tuitty_string_free(s: *mut c_char) {
+    if s.is_null() { return }
+    drop(CString::from_raw(s));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    fn text(s: &[c_char]) -> String {
+        unsafe { CStr::from_ptr(s.as_ptr()) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_event_from_msg() {
+        let mouse = Mouse {
+            kind: MouseKind::Press, button: Some(Button::Extra(8)),
+            col: 2, row: 3, modifiers: Key::CTRL,
+        };
+        let evt = TuittyEvent::from_msg(
+            &Msg::Region("panel".to_string(), mouse.clone()));
+        assert_eq!(evt.kind, TUITTY_EVENT_REGION);
+        assert_eq!((evt.button, evt.ch), (TUITTY_BUTTON_EXTRA, 8));
+        assert_eq!((evt.col, evt.row, evt.modifiers), (2, 3, TUITTY_MOD_CTRL));
+        assert_eq!(text(&evt.name), "panel");
+
+        let evt = TuittyEvent::from_msg(
+            &Msg::Gesture(Gesture::LongPress, mouse));
+        assert_eq!(evt.kind, TUITTY_EVENT_GESTURE);
+        assert_eq!(evt.gesture, TUITTY_GESTURE_LONG_PRESS);
+
+        // Keys without a legacy KeyEvent are reported by their code:
+        let key = Key {
+            code: KeyCode::Char('a'), modifiers: Key::CTRL | Key::SHIFT,
+            kind: KeyKind::Release, text: String::new(),
+        };
+        let evt = TuittyEvent::from_msg(&Msg::Key(key));
+        assert_eq!(evt.kind, TUITTY_EVENT_KEYBOARD);
+        assert_eq!((evt.key, evt.ch), (TUITTY_KEY_CHAR, 'a' as u32));
+        assert_eq!(evt.key_kind, TUITTY_KEY_RELEASE);
+        assert_eq!(evt.modifiers, TUITTY_MOD_CTRL | TUITTY_MOD_SHIFT);
+
+        // Errors carry the failure, truncated to fit:
+        let evt = TuittyEvent::from_msg(&Msg::Error(Failure {
+            action: "flush".to_string(), class: ErrorClass::Disconnected,
+            details: "é".repeat(TUITTY_DETAILS_LEN), is_fatal: true,
+        }));
+        assert_eq!(evt.kind, TUITTY_EVENT_ERROR);
+        assert_eq!(evt.error_class, TUITTY_ERROR_DISCONNECTED);
+        assert_eq!(evt.is_fatal, 1);
+        assert_eq!(text(&evt.name), "flush");
+        assert_eq!(text(&evt.details), "é".repeat(TUITTY_DETAILS_LEN / 2 - 1));
+    }
+}
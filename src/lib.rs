@@ -2,3 +2,4 @@ extern crate tuitty_core;
 
 mod store;
 pub mod dispatcher;
+pub mod ffi;
@@ -0,0 +1,165 @@
+// This module provides the escape sequences and color tables that are used
+// to export the contents of a ScreenBuffer (eg. for dumps and recordings).
+use crate::tuitty_core::common::enums::{ Color::{*, self}, Effect };
+
+
+fn color_code(c: Color, is_bg: bool) -> Option<String> {
+    // Background codes are offset by 10 from foreground codes.
+    let offset = if is_bg { 10 } else { 0 };
+    let code = match c {
+        Reset => return None,
+        Black => 30,
+        DarkRed => 31,
+        DarkGreen => 32,
+        DarkYellow => 33,
+        DarkBlue => 34,
+        DarkMagenta => 35,
+        DarkCyan => 36,
+        Grey => 37,
+        DarkGrey => 90,
+        Red => 91,
+        Green => 92,
+        Yellow => 93,
+        Blue => 94,
+        Magenta => 95,
+        Cyan => 96,
+        White => 97,
+        AnsiValue(n) => return Some(format!("{};5;{}", 38 + offset, n)),
+        Rgb{r, g, b} => return Some(
+            format!("{};2;{};{};{}", 38 + offset, r, g, b)),
+    };
+    Some((code + offset).to_string())
+}
+
+fn effect_codes(fx: u32) -> Vec<&'static str> {
+    let effects = [
+        (Effect::Bold as u32, "1"),
+        (Effect::Dim as u32, "2"),
+        (Effect::Underline as u32, "4"),
+        (Effect::Reverse as u32, "7"),
+        (Effect::Hide as u32, "8"),
+    ];
+    effects.iter().filter(|(effect, _)| fx & effect != 0)
+        .map(|(_, code)| *code).collect()
+}
+
+pub fn sgr(style: (Color, Color, u32)) -> String {
+    // Always start from a reset so that sequences do not depend on
+    // whatever style was previously active.
+    let mut params = vec!["0".to_string()];
+    let (fg, bg, fx) = style;
+    if let Some(code) = color_code(fg, false) { params.push(code) }
+    if let Some(code) = color_code(bg, true) { params.push(code) }
+    for code in effect_codes(fx) { params.push(code.to_string()) }
+    format!("\x1B[{}m", params.join(";"))
+}
+
+pub fn color(c: Color, is_bg: bool) -> String {
+    // Sets only the foreground (or background) color.
+    let code = match color_code(c, is_bg) {
+        Some(code) => code,
+        None => if is_bg { "49".to_string() } else { "39".to_string() },
+    };
+    format!("\x1B[{}m", code)
+}
+
+pub fn effects(fx: u32) -> String {
+    // Sets only the effects (a reset clears every effect).
+    let codes = effect_codes(fx);
+    if codes.is_empty() { return "\x1B[22;24;27;28m".to_string() }
+    format!("\x1B[{}m", codes.join(";"))
+}
+
+pub fn hex(c: Color) -> Option<String> {
+    // Returns the color as it would look on a default xterm palette.
+    let palette = |n: u8| -> (u8, u8, u8) {
+        match n {
+            0 => (0, 0, 0),
+            1 => (128, 0, 0),
+            2 => (0, 128, 0),
+            3 => (128, 128, 0),
+            4 => (0, 0, 128),
+            5 => (128, 0, 128),
+            6 => (0, 128, 128),
+            7 => (192, 192, 192),
+            8 => (128, 128, 128),
+            9 => (255, 0, 0),
+            10 => (0, 255, 0),
+            11 => (255, 255, 0),
+            12 => (0, 0, 255),
+            13 => (255, 0, 255),
+            14 => (0, 255, 255),
+            15 => (255, 255, 255),
+            16..=231 => {
+                let steps = [0, 95, 135, 175, 215, 255];
+                let n = n - 16;
+                (steps[(n / 36) as usize],
+                 steps[((n % 36) / 6) as usize],
+                 steps[(n % 6) as usize])
+            },
+            _ => {
+                let v = 8 + (n - 232) * 10;
+                (v, v, v)
+            }
+        }
+    };
+    let (r, g, b) = match c {
+        Reset => return None,
+        Black => palette(0),
+        DarkRed => palette(1),
+        DarkGreen => palette(2),
+        DarkYellow => palette(3),
+        DarkBlue => palette(4),
+        DarkMagenta => palette(5),
+        DarkCyan => palette(6),
+        Grey => palette(7),
+        DarkGrey => palette(8),
+        Red => palette(9),
+        Green => palette(10),
+        Yellow => palette(11),
+        Blue => palette(12),
+        Magenta => palette(13),
+        Cyan => palette(14),
+        White => palette(15),
+        AnsiValue(n) => palette(n),
+        Rgb{r, g, b} => (r, g, b),
+    };
+    Some(format!("#{:02x}{:02x}{:02x}", r, g, b))
+}
+
+pub fn css(style: (Color, Color, u32)) -> String {
+    let (mut fg, mut bg, fx) = style;
+    if fx & (Effect::Reverse as u32) != 0 {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    let mut rules = vec![];
+    if let Some(c) = hex(fg) { rules.push(format!("color:{}", c)) }
+    if let Some(c) = hex(bg) { rules.push(format!("background:{}", c)) }
+    if fx & (Effect::Bold as u32) != 0 {
+        rules.push("font-weight:bold".to_string())
+    }
+    if fx & (Effect::Dim as u32) != 0 {
+        rules.push("opacity:0.5".to_string())
+    }
+    if fx & (Effect::Underline as u32) != 0 {
+        rules.push("text-decoration:underline".to_string())
+    }
+    if fx & (Effect::Hide as u32) != 0 {
+        rules.push("visibility:hidden".to_string())
+    }
+    rules.join(";")
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
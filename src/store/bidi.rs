@@ -0,0 +1,326 @@
+// This module provides a reduced implementation of the Unicode Bidirectional
+// Algorithm (UAX #9) so that right-to-left scripts (eg. Arabic and Hebrew)
+// can be laid out in visual order before they are stored in ScreenBuffer.
+//
+// (imdaveho) NOTE: Explicit embeddings, overrides and isolates (X1-X8) are
+// not supported. Those formatting characters are treated as boundary neutrals
+// and skipped (X9). Each run of text between control characters (\r, \n, \t)
+// is resolved as its own paragraph.
+use crate::tuitty_core::common::unicode::grapheme::*;
+use Class::*;
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Class {
+    // Strong
+    L, R, AL,
+    // Weak
+    EN, ES, ET, AN, CS, NSM, BN,
+    // Neutral
+    B, S, WS, ON,
+}
+
+pub fn class(ch: char) -> Class {
+    match ch as u32 {
+        // ASCII and Latin-1
+        0x0030..=0x0039 => EN,
+        0x002B | 0x002D => ES,
+        0x0023..=0x0025 | 0x00A2..=0x00A5 | 0x00B0 | 0x00B1 => ET,
+        0x002C | 0x002E | 0x002F | 0x003A | 0x00A0 => CS,
+        0x000A | 0x000D | 0x001C..=0x001E | 0x0085 => B,
+        0x0009 | 0x000B | 0x001F => S,
+        0x000C | 0x0020 => WS,
+        0x0000..=0x0008 | 0x000E..=0x001B | 0x007F..=0x0084
+            | 0x0086..=0x009F | 0x00AD => BN,
+        0x0021 | 0x0022 | 0x0026..=0x002A | 0x003B..=0x0040
+            | 0x005B..=0x0060 | 0x007B..=0x007E | 0x00A1 | 0x00A6..=0x00A9
+            | 0x00AB | 0x00AC | 0x00AE | 0x00AF | 0x00B4 | 0x00B6..=0x00B8
+            | 0x00BB..=0x00BF | 0x00D7 | 0x00F7 => ON,
+        // Combining Diacritical Marks
+        0x0300..=0x036F => NSM,
+        // Hebrew
+        0x0591..=0x05BD | 0x05BF | 0x05C1 | 0x05C2
+            | 0x05C4 | 0x05C5 | 0x05C7 => NSM,
+        0x0590..=0x05FF => R,
+        // Arabic
+        0x0600..=0x0605 | 0x0660..=0x0669 | 0x066B | 0x066C | 0x06DD => AN,
+        0x060C => CS,
+        0x0610..=0x061A | 0x064B..=0x065F | 0x0670 | 0x06D6..=0x06DC
+            | 0x06DF..=0x06E4 | 0x06E7 | 0x06E8 | 0x06EA..=0x06ED => NSM,
+        0x06F0..=0x06F9 => EN,
+        0x0600..=0x06FF => AL,
+        // Syriac, Arabic Supplement, Thaana
+        0x0700..=0x07BF => AL,
+        // NKo, Samaritan, Mandaic
+        0x07C0..=0x085F => R,
+        // Arabic Extended
+        0x0860..=0x08FF => AL,
+        // General Punctuation
+        0x2000..=0x200A => WS,
+        0x200B..=0x200D | 0x2060..=0x206F => BN,
+        0x200E => L,
+        0x200F => R,
+        0x2028 => WS,
+        0x2029 => B,
+        // Explicit formatting characters are not supported (see NOTE).
+        0x202A..=0x202E => BN,
+        0x202F => CS,
+        0x2030..=0x2034 => ET,
+        0x2010..=0x2027 | 0x2035..=0x205E => ON,
+        // Arrows, Math Operators, Box Drawing, etc.
+        0x2190..=0x2BFF => ON,
+        // Hebrew and Arabic Presentation Forms
+        0xFB1D..=0xFB4F => R,
+        0xFB50..=0xFDFF => AL,
+        0xFE70..=0xFEFE => AL,
+        0xFEFF => BN,
+        // Right-to-left historic scripts
+        0x10800..=0x10FFF => R,
+        0x1E800..=0x1EDFF => R,
+        0x1EE00..=0x1EEFF => AL,
+        _ => L,
+    }
+}
+
+pub fn mirror(ch: char) -> char {
+    match ch {
+        '(' => ')', ')' => '(',
+        '[' => ']', ']' => '[',
+        '{' => '}', '}' => '{',
+        '<' => '>', '>' => '<',
+        '«' => '»', '»' => '«',
+        '‹' => '›', '›' => '‹',
+        '≤' => '≥', '≥' => '≤',
+        _ => ch,
+    }
+}
+
+pub fn is_separator(s: &str) -> bool {
+    matches!(s, "\x00" | "\r" | "\n" | "\r\n" | "\t")
+}
+
+fn classify(s: &str) -> Class {
+    match s.chars().next() {
+        Some(ch) => class(ch),
+        None => BN,
+    }
+}
+
+pub fn is_rtl(segments: &[&str]) -> bool {
+    segments.iter().any(|s| matches!(classify(s), R | AL | AN))
+}
+
+fn is_neutral(c: Class) -> bool {
+    matches!(c, B | S | WS | ON)
+}
+
+// Resolves the embedding level of each item (rules P2-P3, W1-W7, N1-N2,
+// I1-I2, and L1).
+pub fn resolve(classes: &[Class]) -> Vec<u8> {
+    let length = classes.len();
+    // P2, P3: The first strong character determines the paragraph level.
+    let para: u8 = match classes.iter().find(|c| matches!(c, L | R | AL)) {
+        Some(R) | Some(AL) => 1,
+        _ => 0,
+    };
+    // The embedding direction is also used for sos and eos.
+    let e = if para == 0 { L } else { R };
+    let mut types = classes.to_vec();
+
+    // X9, W1: Boundary neutrals and non-spacing marks take the type of
+    // the previous character (or sos).
+    let mut prev = e;
+    for t in types.iter_mut() {
+        match *t {
+            NSM | BN => *t = prev,
+            _ => prev = *t,
+        }
+    }
+    // W2: European numbers after Arabic letters become Arabic numbers.
+    let mut strong = e;
+    for t in types.iter_mut() {
+        match *t {
+            L | R | AL => strong = *t,
+            EN if strong == AL => *t = AN,
+            _ => (),
+        }
+    }
+    // W3: Arabic letters become R.
+    for t in types.iter_mut() {
+        if *t == AL { *t = R }
+    }
+    // W4: A single separator between two numbers of the same type.
+    for i in 1..length.saturating_sub(1) {
+        let (before, after) = (types[i - 1], types[i + 1]);
+        match types[i] {
+            ES if before == EN && after == EN => types[i] = EN,
+            CS if before == after && (before == EN || before == AN) => {
+                types[i] = before
+            },
+            _ => (),
+        }
+    }
+    // W5: A sequence of terminators adjacent to European numbers.
+    let mut i = 0;
+    while i < length {
+        if types[i] != ET { i += 1; continue }
+        let start = i;
+        while i < length && types[i] == ET { i += 1 }
+        let before = start > 0 && types[start - 1] == EN;
+        let after = i < length && types[i] == EN;
+        if before || after {
+            for t in types[start..i].iter_mut() { *t = EN }
+        }
+    }
+    // W6: Remaining separators and terminators become neutral.
+    for t in types.iter_mut() {
+        match *t {
+            ES | ET | CS => *t = ON,
+            _ => (),
+        }
+    }
+    // W7: European numbers after L (or sos) become L.
+    let mut strong = e;
+    for t in types.iter_mut() {
+        match *t {
+            L | R => strong = *t,
+            EN if strong == L => *t = L,
+            _ => (),
+        }
+    }
+    // N1, N2: Neutrals take the direction of the surrounding text if both
+    // sides agree. Otherwise they take the embedding direction.
+    let direction = |c: Class| match c {
+        L => L,
+        _ => R,
+    };
+    let mut i = 0;
+    while i < length {
+        if !is_neutral(types[i]) { i += 1; continue }
+        let start = i;
+        while i < length && is_neutral(types[i]) { i += 1 }
+        let before = if start > 0 { direction(types[start - 1]) } else { e };
+        let after = if i < length { direction(types[i]) } else { e };
+        let resolved = if before == after { before } else { e };
+        for t in types[start..i].iter_mut() { *t = resolved }
+    }
+    // I1, I2: Resolve the implicit levels.
+    let mut levels: Vec<u8> = types.iter().map(|t| match (para % 2, t) {
+        (0, R) => para + 1,
+        (0, AN) | (0, EN) => para + 2,
+        (1, L) | (1, AN) | (1, EN) => para + 1,
+        _ => para,
+    }).collect();
+    // L1: Separators, and any whitespace preceding them or the end of
+    // the line, are reset to the paragraph level.
+    let mut trailing = true;
+    for i in (0..length).rev() {
+        match classes[i] {
+            B | S => { levels[i] = para; trailing = true },
+            WS | BN => if trailing { levels[i] = para },
+            _ => trailing = false,
+        }
+    }
+    levels
+}
+
+// Returns the logical index of each item in visual order along with whether
+// the glyph should be mirrored (rules L2 and L4).
+pub fn visual_order(segments: &[&str]) -> Vec<(usize, bool)> {
+    let classes: Vec<Class> = segments.iter().map(|s| classify(s)).collect();
+    let levels = resolve(&classes);
+    let mut order: Vec<usize> = (0..segments.len()).collect();
+    let highest = levels.iter().cloned().max().unwrap_or(0);
+    let lowest_odd = match levels.iter().cloned().filter(|l| l % 2 == 1).min() {
+        Some(l) => l,
+        None => highest + 1,
+    };
+    // L2: From the highest level to the lowest odd level, reverse any
+    // contiguous sequence at that level or higher.
+    let mut level = highest;
+    while level >= lowest_odd && level > 0 {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level { i += 1; continue }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level { i += 1 }
+            order[start..i].reverse();
+        }
+        level -= 1;
+    }
+    order.into_iter().map(|i| (i, levels[i] % 2 == 1)).collect()
+}
+
+pub fn mirrored(s: &str) -> String {
+    s.chars().map(mirror).collect()
+}
+
+pub fn reorder(content: &str) -> String {
+    let segments: Vec<&str> = UnicodeGraphemes
+        ::graphemes(content, true).collect();
+    let mut output = String::with_capacity(content.len());
+    let mut run: Vec<&str> = Vec::new();
+    for s in segments {
+        if is_separator(s) {
+            reorder_run(&run, &mut output);
+            run.clear();
+            output.push_str(s);
+        } else { run.push(s) }
+    }
+    reorder_run(&run, &mut output);
+    output
+}
+
+fn reorder_run(run: &[&str], output: &mut String) {
+    if !is_rtl(run) {
+        for s in run { output.push_str(s) }
+        return
+    }
+    for (i, is_mirror) in visual_order(run) {
+        if is_mirror { output.push_str(&mirrored(run[i])) }
+        else { output.push_str(run[i]) }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bidi_classes() {
+        assert_eq!(class('a'), L);
+        assert_eq!(class('א'), R);
+        assert_eq!(class('ع'), AL);
+        assert_eq!(class('٣'), AN);
+        assert_eq!(class('7'), EN);
+        assert_eq!(class(' '), WS);
+        assert_eq!(class('('), ON);
+        assert_eq!(class('\u{05B4}'), NSM);
+    }
+
+    #[test]
+    fn test_bidi_levels() {
+        // Mixed LTR paragraph: "ab אב"
+        let levels = resolve(&[L, L, WS, R, R]);
+        assert_eq!(levels, vec![0, 0, 0, 1, 1]);
+        // RTL paragraph with numbers: "אב 12"
+        let levels = resolve(&[R, R, WS, EN, EN]);
+        assert_eq!(levels, vec![1, 1, 1, 2, 2]);
+        // Trailing whitespace is reset to the paragraph level: "a א "
+        let levels = resolve(&[L, WS, R, WS]);
+        assert_eq!(levels, vec![0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_bidi_reorder() {
+        assert_eq!(reorder("abc אבג"), "abc גבא");
+        assert_eq!(reorder("אב 12"), "12 בא");
+        assert_eq!(reorder("א(ב)"), "(ב)א");
+        // Separators split the content into separate runs.
+        assert_eq!(reorder("אב\nab"), "בא\nab");
+        // Pure LTR content is left untouched.
+        assert_eq!(reorder("hello (world)"), "hello (world)");
+    }
+}
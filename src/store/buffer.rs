@@ -2,6 +2,9 @@
 // make up the terminal screen.
 use crate::tuitty_core::common::unicode::{grapheme::*, wcwidth::*};
 use super::{ Term, Color::{*, self}, Style, Clear };
+use super::bidi::{ is_rtl, is_separator, mirrored, visual_order };
+use super::ansi::{ sgr, css, escape_html };
+use super::{ Snapshot, CellData, Format };
 
 #[cfg(unix)]
 use crate::tuitty_core::common::enums::Effect;
@@ -9,6 +12,8 @@ use crate::tuitty_core::common::enums::Effect;
 use crate::tuitty_core::common::enums::{Effect, foreground, background, effects};
 #[cfg(windows)]
 type WORD = u16;
+// A row of visible glyphs along with their (fg, bg, fx) styles.
+type Line = Vec<(String, (Color, Color, u32))>;
 
 
 #[derive(Clone)]
@@ -17,6 +22,9 @@ pub struct Cell {
     is_wide: bool,
     is_part: bool,
     style: (Color, Color, u32),
+    // The cell index in logical order and whether the glyph was mirrored,
+    // if the cell was laid out as part of a right-to-left run.
+    logical: Option<(usize, bool)>,
 }
 
 
@@ -29,6 +37,7 @@ pub struct ScreenBuffer {
     tab_size: usize,
     active_style: (Color, Color, u32),
     placeholder: char,
+    is_bidi: bool,
 }
 
 impl ScreenBuffer {
@@ -43,6 +52,7 @@ impl ScreenBuffer {
             tab_size: 8,
             active_style: (Reset, Reset, Effect::Reset as u32),
             placeholder: '🚧',
+            is_bidi: false,
         }
     }
 
@@ -186,6 +196,14 @@ impl ScreenBuffer {
         self.placeholder = ch;
     }
 
+    pub fn is_bidi(&self) -> bool {
+        self.is_bidi
+    }
+
+    pub fn sync_bidi(&mut self, state: bool) {
+        self.is_bidi = state;
+    }
+
     pub fn sync_size(&mut self, w: i16, h: i16) {
         self.window = (w, h);
         self.capacity = (w * h) as usize;
@@ -197,6 +215,9 @@ impl ScreenBuffer {
     // }
 
     pub fn getch(&self) -> String {
+        // Returns the glyph under the cursor as it is displayed (eg. a
+        // mirrored bracket in a right-to-left run). Only `getln` is in
+        // logical order.
         let index = self.cursor;
         match &self.cells[index] {
             Some(cell) => if cell.is_part {
@@ -209,6 +230,33 @@ impl ScreenBuffer {
         }
     }
 
+    pub fn getln(&self) -> String {
+        // Returns the row under the cursor in logical order so that
+        // right-to-left runs read the same as they were printed.
+        let width = self.width() as usize;
+        let row = std::cmp::min(self.row(), self.height() - 1) as usize;
+        let start = row * width;
+        let mut glyphs: Vec<(usize, String)> = Vec::with_capacity(width);
+        for (i, cell) in self.cells[start..start + width].iter().enumerate() {
+            match cell {
+                Some(cell) => {
+                    if cell.is_part { continue }
+                    let glyph: String = cell.glyph.iter().collect();
+                    match cell.logical {
+                        Some((index, true)) =>
+                            glyphs.push((index, mirrored(&glyph))),
+                        Some((index, false)) => glyphs.push((index, glyph)),
+                        None => glyphs.push((start + i, glyph)),
+                    }
+                },
+                None => glyphs.push((start + i, " ".to_string())),
+            }
+        }
+        glyphs.sort_by_key(|g| g.0);
+        let line: String = glyphs.into_iter().map(|g| g.1).collect();
+        line.trim_end().to_string()
+    }
+
     pub fn delch(&mut self) {
         // Eg. Backspace moves cursor left 1 cell. This should have called
         // something that updated the cursor to the starting cell. Therefore
@@ -260,7 +308,8 @@ impl ScreenBuffer {
         self.active_style = (f, b, fx);
     }
 
-    fn set_cell(&mut self, ch: Vec<char>, is_wide: bool) {
+    fn set_cell(&mut self, ch: Vec<char>, is_wide: bool) -> usize {
+        // Returns the index of the cell that holds the glyph.
         let mut index = self.cursor;
         if index >= self.capacity { index = self.capacity - 1 }
         if is_wide {
@@ -270,6 +319,7 @@ impl ScreenBuffer {
                 is_wide: true,
                 is_part: false,
                 style: self.active_style,
+                logical: None,
             }));
             self.cells.remove(index + 1);
             self.cells.insert(index + 1, Some(Cell {
@@ -277,6 +327,7 @@ impl ScreenBuffer {
                 is_wide: true,
                 is_part: true,
                 style: self.active_style,
+                logical: None,
             }));
             self.cursor = index + 2;
         } else {
@@ -292,6 +343,7 @@ impl ScreenBuffer {
                 is_wide: false,
                 is_part: false,
                 style: self.active_style,
+                logical: None,
             }));
             self.cursor = index + 1;
             if from_wide {
@@ -302,9 +354,10 @@ impl ScreenBuffer {
                 self.cursor = index + 2;
             }
         }
+        index
     }
 
-    fn set_ascii(&mut self, s: &str) {
+    fn set_ascii(&mut self, s: &str) -> Option<usize> {
         match s {
             "\x00" => (),
             "\r" => self.sync_coord(0, self.row()),
@@ -338,7 +391,68 @@ impl ScreenBuffer {
             _ => {
                 let ch = if s == "\x1B" { vec!['^'] }
                 else { s.chars().collect() };
-                self.set_cell(ch, false)
+                return Some(self.set_cell(ch, false))
+            }
+        }
+        None
+    }
+
+    fn set_segment(&mut self, s: &str) -> Option<usize> {
+        // Returns the index of the cell that holds the glyph (if any).
+        if s.is_ascii() { self.set_ascii(s) }
+        else {
+            Some(match s.width() {
+                1 => {
+                    if s.contains("\u{fe0f}") {
+                        self.set_cell(s.chars().collect(), true)
+                    } else {
+                        self.set_cell(s.chars().collect(), false)
+                    }
+                },
+                2 => self.set_cell(s.chars().collect(), true),
+                // (imdaveho) NOTE: We are not going to handle complex
+                // combiner chars until there is a better way to detect
+                // how many cells is going to be taken up or until there
+                // is a consistent font / handling across terminal emulators
+                // for these complex characters.
+                // Instead, we will render all complex combiner characters
+                // with this placeholder glyph: 🚧
+                _ => self.set_cell(vec![self.placeholder,], true),
+            })
+        }
+    }
+
+    fn span(s: &str) -> usize {
+        // The number of cells that set_segment will occupy.
+        if s.is_ascii() { return 1 }
+        match s.width() {
+            1 => if s.contains("\u{fe0f}") { 2 } else { 1 },
+            _ => 2,
+        }
+    }
+
+    fn set_run(&mut self, run: &[&str]) {
+        if !is_rtl(run) {
+            for s in run { self.set_segment(s); }
+            return
+        }
+        // Keep track of where each grapheme would have started if it
+        // were laid out in logical order.
+        let mut offsets = Vec::with_capacity(run.len());
+        let mut offset = self.cursor;
+        for s in run {
+            offsets.push(offset);
+            offset += Self::span(s);
+        }
+        for (i, is_mirror) in visual_order(run) {
+            let index = match is_mirror {
+                true => self.set_segment(&mirrored(run[i])),
+                false => self.set_segment(run[i]),
+            };
+            // Mark the cell that the glyph was actually written to.
+            let cell = index.and_then(|n| self.cells.get_mut(n));
+            if let Some(Some(cell)) = cell {
+                cell.logical = Some((offsets[i], is_mirror));
             }
         }
     }
@@ -347,29 +461,21 @@ impl ScreenBuffer {
         let segments: Vec<&str> = UnicodeGraphemes
             ::graphemes(content, true).collect();
 
+        if !self.is_bidi {
+            for s in segments { self.set_segment(s); }
+            return
+        }
+        // Bidi mode: each run between control characters is reordered
+        // into visual order before it is stored.
+        let mut run: Vec<&str> = Vec::with_capacity(segments.len());
         for s in segments {
-            if s.is_ascii() { self.set_ascii(s) }
-            else {
-                match s.width() {
-                    1 => {
-                        if s.contains("\u{fe0f}") {
-                            self.set_cell(s.chars().collect(), true)
-                        } else {
-                            self.set_cell(s.chars().collect(), false)
-                        }
-                    },
-                    2 => self.set_cell(s.chars().collect(), true),
-                    // (imdaveho) NOTE: We are not going to handle complex
-                    // combiner chars until there is a better way to detect
-                    // how many cells is going to be taken up or until there
-                    // is a consistent font / handling across terminal emulators
-                    // for these complex characters.
-                    // Instead, we will render all complex combiner characters
-                    // with this placeholder glyph: 🚧
-                    _ => self.set_cell(vec![self.placeholder,], true),
-                }
-            }
+            if is_separator(s) {
+                self.set_run(&run);
+                run.clear();
+                self.set_ascii(s);
+            } else { run.push(s) }
         }
+        self.set_run(&run);
     }
 
     pub fn sync_clear(&mut self, clr: Clear) {
@@ -409,6 +515,82 @@ impl ScreenBuffer {
     }
 
    
+    pub fn snapshot(&self) -> Snapshot {
+        let cells = self.cells.iter().map(|cell| cell.as_ref().map(|c| {
+            CellData {
+                glyph: c.glyph.iter().collect(),
+                is_wide: c.is_wide,
+                is_part: c.is_part,
+                style: c.style,
+                logical: c.logical,
+            }
+        })).collect();
+        let width = self.width();
+        let marker = self.marker as i16;
+        Snapshot {
+            size: self.window,
+            cursor: self.coord(),
+            marker: ((marker % width), (marker / width)),
+            tab_size: self.tab_size,
+            style: self.active_style,
+            cells,
+            // Screen modes are filled in by the Store.
+            is_raw: false,
+            is_mouse: false,
+            is_cursor: true,
+            is_bidi: self.is_bidi,
+            mouse_mode: None,
+            keyboard: Vec::new(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // (imdaveho) NOTE: The snapshot may have been taken at a different
+        // terminal size (eg. persisted across restarts). Rows and columns
+        // are copied over up to the current window size.
+        let (w, h) = (self.width() as usize, self.height() as usize);
+        let (sw, sh) = (snapshot.size.0 as usize, snapshot.size.1 as usize);
+        let mut cells = vec![None; self.capacity];
+        for row in 0..std::cmp::min(h, sh) {
+            for col in 0..std::cmp::min(w, sw) {
+                let cell = match snapshot.cells.get(row * sw + col) {
+                    Some(Some(c)) => c,
+                    _ => continue,
+                };
+                // Do not keep half of a wide char that was cut off.
+                if cell.is_wide && !cell.is_part && col + 1 >= w { continue }
+                if cell.is_part && col == 0 { continue }
+                // Logical indices are laid out at the snapshot width.
+                let logical = cell.logical.and_then(|(i, is_mirror)| {
+                    let (r, c) = (i / sw, i % sw);
+                    if r < h && c < w { Some((r * w + c, is_mirror)) }
+                    else { None }
+                });
+                cells[row * w + col] = Some(Cell {
+                    glyph: cell.glyph.chars().collect(),
+                    is_wide: cell.is_wide,
+                    is_part: cell.is_part,
+                    style: cell.style,
+                    logical,
+                });
+            }
+        }
+        self.cells = cells;
+        self.tab_size = snapshot.tab_size;
+        self.active_style = snapshot.style;
+        self.is_bidi = snapshot.is_bidi;
+        let (col, row) = snapshot.marker;
+        let (col, row) = (
+            std::cmp::min(col, self.width() - 1),
+            std::cmp::min(row, self.height() - 1) );
+        self.sync_marker(col, row);
+        let (col, row) = snapshot.cursor;
+        let (col, row) = (
+            std::cmp::min(col, self.width() - 1),
+            std::cmp::min(row, self.height() - 1) );
+        self.sync_coord(col, row);
+    }
+
     #[cfg(unix)]
     pub fn render(&self, term: &Term) -> std::io::Result<()> {
         let (col, row) = self.coord();
@@ -494,7 +676,8 @@ impl ScreenBuffer {
                 glyph: vec![' '],
                 is_part: false,
                 is_wide: false,
-                style
+                style,
+                logical: None,
             };
             let data = match cell { Some(c) => c, None => &spc };
             for ch in &data.glyph { chunk.push(*ch) }
@@ -572,33 +755,102 @@ impl ScreenBuffer {
     }
 
 
-    #[cfg(test)]
-    fn check_contents(&self) -> String {
-        let mut chars: Vec<char> = Vec::with_capacity(self.capacity);
+    fn lines(&self) -> Vec<Line> {
+        // Walks the cells row by row and collects the visible glyphs along
+        // with their styles. Partial cells are skipped and empty cells are
+        // reported as a single whitespace with the default style.
+        let default = (Reset, Reset, Effect::Reset as u32);
+        let width = self.width() as usize;
+        let mut lines = Vec::with_capacity(self.height() as usize);
+        let mut line = Vec::with_capacity(width);
         let mut length = 0;
         for c in self.cells.iter() {
             match c {
                 Some(cell) => {
                     if cell.is_part { continue }
-                    let width = if cell.is_wide { 2 } else { 1 };
-                    if length + width > self.capacity { break }
-                    for c in &cell.glyph {
-                        chars.push(*c)
-                    }
-                    length += width;
+                    let span = if cell.is_wide { 2 } else { 1 };
+                    if length + span > self.capacity { break }
+                    line.push((cell.glyph.iter().collect(), cell.style));
+                    length += span;
                 },
                 None => {
                     if length + 1 > self.capacity { break }
-                    chars.push(' ');
+                    line.push((" ".to_string(), default));
                     length += 1;
                 },
             }
+            // A wide char that straddles two rows stays on the first row.
+            if length >= width * (lines.len() + 1) {
+                lines.push(line);
+                line = Vec::with_capacity(width);
+            }
         }
         while length < self.capacity {
-            chars.push(' ');
+            line.push((" ".to_string(), default));
             length += 1;
+            if length >= width * (lines.len() + 1) {
+                lines.push(line);
+                line = Vec::with_capacity(width);
+            }
         }
-        chars.iter().collect()
+        if !line.is_empty() { lines.push(line) }
+        lines
+    }
+
+    pub fn dump(&self, format: Format) -> String {
+        let default = (Reset, Reset, Effect::Reset as u32);
+        let mut output = String::with_capacity(self.capacity);
+        match format {
+            Format::Plain => {
+                let rows: Vec<String> = self.lines().into_iter()
+                    .map(|line| {
+                        let row: String = line.into_iter()
+                            .map(|g| g.0).collect();
+                        row.trim_end().to_string()
+                    }).collect();
+                output.push_str(&rows.join("\n"));
+            },
+            Format::Ansi => {
+                for (i, line) in self.lines().into_iter().enumerate() {
+                    if i > 0 { output.push('\n') }
+                    let mut style = default;
+                    for (glyph, s) in line {
+                        if s != style {
+                            output.push_str(&sgr(s));
+                            style = s;
+                        }
+                        output.push_str(&glyph);
+                    }
+                    if style != default { output.push_str(&sgr(default)) }
+                }
+            },
+            Format::Html => {
+                output.push_str("<pre class=\"tuitty\">");
+                for (i, line) in self.lines().into_iter().enumerate() {
+                    if i > 0 { output.push('\n') }
+                    let mut style = default;
+                    for (glyph, s) in line {
+                        if s != style {
+                            if style != default { output.push_str("</span>") }
+                            if s != default {
+                                output.push_str(&format!(
+                                    "<span style=\"{}\">", css(s)));
+                            }
+                            style = s;
+                        }
+                        output.push_str(&escape_html(&glyph));
+                    }
+                    if style != default { output.push_str("</span>") }
+                }
+                output.push_str("</pre>");
+            },
+        }
+        output
+    }
+
+    #[cfg(test)]
+    fn check_contents(&self) -> String {
+        self.lines().into_iter().flatten().map(|g| g.0).collect()
     }
 }
 
@@ -919,5 +1171,95 @@ mod tests {
         let output = buffer.check_contents();
         assert_eq!(output, format!("a$ z🚧 ❤️z{}", " ".repeat(0)));
     }
+
+    #[test]
+    fn test_bidi_content() {
+        let mut buffer = ScreenBuffer::new(10, 2);
+        buffer.sync_bidi(true);
+        // Right-to-left run within a left-to-right line:
+        buffer.sync_content("abc אבג");
+        let output = buffer.check_contents();
+        assert_eq!(output, format!("abc גבא{}", " ".repeat(13)));
+        // The cell under the cursor is the visual glyph, while the line is
+        // read back in logical order:
+        buffer.sync_coord(4, 0);
+        assert_eq!(buffer.getch(), "ג");
+        assert_eq!(buffer.getln(), "abc אבג");
+
+        // Right-to-left line with numbers and mirrored brackets:
+        buffer.sync_coord(0, 1);
+        buffer.sync_content("א(ב) 12");
+        let output = buffer.check_contents();
+        assert_eq!(output, "abc גבא   12 (ב)א   ");
+        assert_eq!(buffer.getln(), "א(ב) 12");
+
+        // Wide glyphs within a right-to-left run:
+        buffer.sync_clear(Clear::All);
+        buffer.sync_content("א㓘ב");
+        assert_eq!(buffer.getln(), "א㓘ב");
+
+        // Disabled bidi stores content as is:
+        buffer.sync_bidi(false);
+        buffer.sync_clear(Clear::All);
+        buffer.sync_content("abc אבג");
+        let output = buffer.check_contents();
+        assert_eq!(output, format!("abc אבג{}", " ".repeat(13)));
+    }
+
+    #[test]
+    fn test_buffer_snapshot() {
+        let mut buffer = ScreenBuffer::new(5, 2);
+        buffer.sync_content("He㓘o, क्‍ष");
+        buffer.sync_marker(1, 1);
+        buffer.sync_coord(2, 0);
+        let snapshot = buffer.snapshot();
+        assert_eq!(snapshot.size, (5, 2));
+        assert_eq!(snapshot.cursor, (2, 0));
+        assert_eq!(snapshot.marker, (1, 1));
+        assert_eq!(snapshot.cells[2].as_ref().unwrap().glyph, "㓘");
+
+        // Overwrite the buffer and then restore it:
+        buffer.sync_clear(Clear::All);
+        buffer.sync_content("overlay");
+        buffer.restore(&snapshot);
+        assert_eq!(buffer.check_contents(), "He㓘o, क्‍ष ");
+        assert_eq!(buffer.coord(), (2, 0));
+        assert_eq!(buffer.getch(), "㓘");
+
+        // Restore into a smaller window:
+        let mut buffer = ScreenBuffer::new(3, 1);
+        buffer.restore(&snapshot);
+        assert_eq!(buffer.check_contents(), "He ");
+        assert_eq!(buffer.coord(), (2, 0));
+
+        // Restore a right-to-left row into a wider window:
+        let mut buffer = ScreenBuffer::new(6, 2);
+        buffer.sync_bidi(true);
+        buffer.sync_coord(3, 1);
+        buffer.sync_content("אב");
+        let snapshot = buffer.snapshot();
+        let mut buffer = ScreenBuffer::new(8, 2);
+        buffer.restore(&snapshot);
+        buffer.sync_coord(0, 1);
+        assert_eq!(buffer.getln(), "   אב");
+    }
+
+    #[test]
+    fn test_buffer_dump() {
+        let mut buffer = ScreenBuffer::new(5, 2);
+        buffer.sync_content("a㓘");
+        buffer.sync_styles(Red, Reset, Effect::Bold as u32);
+        buffer.sync_content("<b");
+        // Plain text has trailing whitespace trimmed per row:
+        assert_eq!(buffer.dump(Format::Plain), "a㓘<b\n");
+        // Ansi text resets the style at the end of each row:
+        assert_eq!(buffer.dump(Format::Ansi), format!(
+            "a㓘{}<b{}\n     ",
+            "\x1B[0;91;1m", "\x1B[0m"));
+        // Html output escapes the glyphs:
+        assert_eq!(buffer.dump(Format::Html), format!(
+            "<pre class=\"tuitty\">a㓘{}&lt;b</span>\n     </pre>",
+            "<span style=\"color:#ff0000;font-weight:bold\">"));
+    }
 }
 
@@ -1,4 +1,6 @@
 mod buffer;
+pub mod ansi;
+pub mod bidi;
 use buffer::ScreenBuffer;
 
 use std::io::{ Result, Error, ErrorKind };
@@ -6,21 +8,84 @@ use crate::tuitty_core::terminal::Term;
 use crate::tuitty_core::common::enums::{ Clear, Color, Style };
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseLevel {
+    Off,
+    // Presses and releases.
+    Button,
+    // Motion while a button is held.
+    Drag,
+    // All motion.
+    Motion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseEncoding {
+    // Limited to 223 columns and rows.
+    Legacy,
+    Sgr,
+    // SGR with pixel (rather than cell) coordinates.
+    SgrPixels,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Plain,
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    // Screen buffer
+    pub size: (i16, i16),
+    pub cursor: (i16, i16),
+    pub marker: (i16, i16),
+    pub tab_size: usize,
+    pub style: (Color, Color, u32),
+    pub cells: Vec<Option<CellData>>,
+    // Screen mode settings
+    pub is_raw: bool,
+    pub is_mouse: bool,
+    pub is_cursor: bool,
+    pub is_bidi: bool,
+    pub mouse_mode: Option<(MouseLevel, MouseEncoding)>,
+    pub keyboard: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellData {
+    pub glyph: String,
+    pub is_wide: bool,
+    pub is_part: bool,
+    pub style: (Color, Color, u32),
+    pub logical: Option<(usize, bool)>,
+}
+
 struct Screen {
+    // Stable handle that is never reused after the screen is closed.
+    id: usize,
     // Screen mode settings
     is_raw_enabled: bool,
     is_mouse_enabled: bool,
+    // Extended mouse tracking (see `SetMouse`) instead of `EnableMouse`.
+    mouse_mode: Option<(MouseLevel, MouseEncoding)>,
     is_cursor_visible: bool,
+    // Stack of kitty keyboard enhancement flags.
+    keyboard: Vec<u8>,
     // Screen buffer
     buffer: ScreenBuffer,
 }
 
 impl Screen {
-    pub fn new(w: i16, h: i16) -> Screen {
+    pub fn new(id: usize, w: i16, h: i16) -> Screen {
         Screen {
+            id,
             is_raw_enabled: false,
             is_mouse_enabled: false,
+            mouse_mode: None,
             is_cursor_visible: true,
+            keyboard: Vec::new(),
             buffer: ScreenBuffer::new(w, h),
         }
     }
@@ -28,26 +93,63 @@ impl Screen {
 
 
 pub struct Store {
+    // Handle of the active screen and its position in data.
     id: usize,
+    index: usize,
+    // Handle that will be given to the next new screen.
+    next: usize,
     data: Vec<Screen>,
+    // Previously active screens (most recent last).
+    history: Vec<usize>,
+    // Navigation stack of screens to return to on pop.
+    stack: Vec<usize>,
+    // Whether keyboard flags were ever pushed (and need to be reset).
+    is_keyboard_used: bool,
+    // Whether extended mouse tracking was ever set (and needs to be reset).
+    is_mouse_mode_used: bool,
+    // Glyph of the complex characters that are not rendered (of every
+    // screen, see `ScreenBuffer::sync_placeholder`).
+    placeholder: char,
 }
 
 impl Store {
     pub fn new(w: i16, h: i16) -> Store {
-        Store { id: 0, data: vec![Screen::new(w, h)] }
+        Store {
+            id: 0, index: 0, next: 1,
+            data: vec![Screen::new(0, w, h)],
+            history: Vec::with_capacity(8),
+            stack: Vec::with_capacity(8),
+            is_keyboard_used: false,
+            is_mouse_mode_used: false,
+            placeholder: '🚧',
+        }
     }
 
     pub fn id(&self) -> usize {
         self.id
     }
 
+    pub fn ids(&self) -> Vec<usize> {
+        self.data.iter().map(|s| s.id).collect()
+    }
+
+    fn position(&self, id: usize) -> Option<usize> {
+        self.data.iter().position(|s| s.id == id)
+    }
+
     pub fn exists(&self, id: usize) -> bool {
-        self.data.get(id).is_some()
+        self.position(id).is_some()
     }
 
     pub fn set(&mut self, id: usize) -> Result<()> {
-        if let Some(_) = self.data.get(id) {
+        if let Some(index) = self.position(id) {
+            if self.id != id {
+                let previous = self.id;
+                self.history.retain(|&i| i != previous && i != id);
+                self.history.push(previous);
+            }
             self.id = id;
+            self.index = index;
             return Ok(());
         }
 
@@ -55,124 +157,381 @@ impl Store {
             format!("Error: Screen ({}) does not exist", id)))
     }
 
-    pub fn new_screen(&mut self, w: i16, h: i16) {
-        self.data.push(Screen::new(w, h));
-        self.id = self.data.len() - 1;
+    pub fn previous(&self) -> usize {
+        // The most recently active screen, or the main screen.
+        match self.history.last() {
+            Some(&id) => id,
+            None => 0,
+        }
+    }
+
+    pub fn push(&mut self) {
+        // Remember the active screen before navigating away from it.
+        self.stack.push(self.id);
+    }
+
+    pub fn pop(&mut self) -> Option<usize> {
+        // Skip entries that would not navigate anywhere (the active screen)
+        // or no longer exist.
+        while let Some(id) = self.stack.pop() {
+            if id != self.id && self.exists(id) { return Some(id) }
+        }
+        None
+    }
+
+    pub fn new_screen(&mut self, w: i16, h: i16) -> usize {
+        let id = self.next;
+        self.next += 1;
+        let mut screen = Screen::new(id, w, h);
+        screen.buffer.sync_placeholder(self.placeholder);
+        self.data.push(screen);
+        // The id was just pushed so this cannot fail.
+        let _ = self.set(id);
+        id
+    }
+
+    pub fn close(&mut self, id: usize) -> Result<()> {
+        // (imdaveho) NOTE: The main screen (0) cannot be closed, and the
+        // active screen needs to be switched away from before closing it.
+        if id == 0 || id == self.id {
+            return Err(Error::new(ErrorKind::Other,
+                format!("Error: Screen ({}) cannot be closed", id)))
+        }
+        let index = match self.position(id) {
+            Some(i) => i,
+            None => return Err(Error::new(ErrorKind::Other,
+                format!("Error: Screen ({}) does not exist", id)))
+        };
+        self.data.remove(index);
+        self.history.retain(|&i| i != id);
+        self.stack.retain(|&i| i != id);
+        // eg. [0, a, 0] -> [0, 0] would otherwise pop back to 0 twice.
+        self.stack.dedup();
+        // Removing a screen shifts the position of the active screen.
+        if index < self.index { self.index -= 1 }
+        Ok(())
+    }
+
+    pub fn snapshot(&self, id: usize) -> Option<Snapshot> {
+        let screen = &self.data[self.position(id)?];
+        let mut snapshot = screen.buffer.snapshot();
+        snapshot.is_raw = screen.is_raw_enabled;
+        snapshot.is_mouse = screen.is_mouse_enabled;
+        snapshot.is_cursor = screen.is_cursor_visible;
+        snapshot.mouse_mode = screen.mouse_mode;
+        snapshot.keyboard = screen.keyboard.clone();
+        Some(snapshot)
+    }
+
+    pub fn dump(&self, id: usize, format: Format) -> Option<String> {
+        let screen = &self.data[self.position(id)?];
+        Some(screen.buffer.dump(format))
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        // self.set() ensures that there is a valid id
+        let screen = &mut self.data[self.index];
+        screen.is_raw_enabled = snapshot.is_raw;
+        screen.is_mouse_enabled = snapshot.is_mouse;
+        screen.is_cursor_visible = snapshot.is_cursor;
+        screen.mouse_mode = snapshot.mouse_mode;
+        screen.keyboard = snapshot.keyboard.clone();
+        screen.buffer.restore(snapshot);
+        // The terminal modes are synced from these afterwards.
+        if snapshot.mouse_mode.is_some() { self.is_mouse_mode_used = true }
+        if !snapshot.keyboard.is_empty() { self.is_keyboard_used = true }
     }
 
     pub fn coord(&self) -> (i16, i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.coord()
+        self.data[self.index].buffer.coord()
     }
 
     pub fn size(&self) -> (i16, i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.size()
+        self.data[self.index].buffer.size()
     }
 
     pub fn getch(&self) -> String {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.getch()
+        self.data[self.index].buffer.getch()
+    }
+
+    pub fn getln(&self) -> String {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].buffer.getln()
     }
 
     pub fn is_raw(&self) -> bool {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_raw_enabled
+        self.data[self.index].is_raw_enabled
     }
 
     pub fn sync_raw(&mut self, state: bool) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_raw_enabled = state;
+        self.data[self.index].is_raw_enabled = state;
     }
 
     pub fn is_cursor(&self) -> bool {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_cursor_visible
+        self.data[self.index].is_cursor_visible
     }
 
     pub fn sync_cursor(&mut self, state: bool) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_cursor_visible = state;
+        self.data[self.index].is_cursor_visible = state;
     }
 
     pub fn is_mouse(&self) -> bool {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_mouse_enabled
+        self.data[self.index].is_mouse_enabled
     }
 
     pub fn sync_mouse(&mut self, state: bool) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].is_mouse_enabled = state;
+        self.data[self.index].is_mouse_enabled = state;
+    }
+
+    pub fn mouse_mode(&self) -> Option<(MouseLevel, MouseEncoding)> {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].mouse_mode
+    }
+
+    pub fn is_mouse_mode_used(&self) -> bool {
+        self.is_mouse_mode_used
+    }
+
+    pub fn sync_mouse_mode(
+        &mut self, mode: Option<(MouseLevel, MouseEncoding)>
+    ) {
+        // self.set() ensures that there is a valid id
+        if mode.is_some() { self.is_mouse_mode_used = true }
+        self.data[self.index].mouse_mode = mode;
+    }
+
+    pub fn keyboard(&self) -> u8 {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].keyboard.last().copied().unwrap_or(0)
+    }
+
+    pub fn is_keyboard_used(&self) -> bool {
+        self.is_keyboard_used
+    }
+
+    pub fn push_keyboard(&mut self, flags: u8) {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].keyboard.push(flags);
+        self.is_keyboard_used = true;
+    }
+
+    pub fn pop_keyboard(&mut self) {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].keyboard.pop();
     }
 
     pub fn sync_goto(&mut self, col: i16, row: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_coord(col, row);
+        self.data[self.index].buffer.sync_coord(col, row);
     }
 
     pub fn sync_left(&mut self, n: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_left(n);
+        self.data[self.index].buffer.sync_left(n);
     }
 
     pub fn sync_right(&mut self, n: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_right(n);
+        self.data[self.index].buffer.sync_right(n);
     }
 
     pub fn sync_up(&mut self, n: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_up(n);
+        self.data[self.index].buffer.sync_up(n);
     }
 
     pub fn sync_down(&mut self, n: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_down(n);
+        self.data[self.index].buffer.sync_down(n);
     }
 
     pub fn jump(&mut self) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.jump();
+        self.data[self.index].buffer.jump();
     }
 
     pub fn sync_marker(&mut self, col: i16, row: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_marker(col, row);
+        self.data[self.index].buffer.sync_marker(col, row);
     }
 
     pub fn sync_size(&mut self, w: i16, h: i16) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_size(w, h);
+        self.data[self.index].buffer.sync_size(w, h);
     }
 
     pub fn sync_tab_size(&mut self, n: usize) {
         // TODO: include a process Command into tabs
         // to ensure that system tabs is aligned.
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_tab_size(n);
+        self.data[self.index].buffer.sync_tab_size(n);
+    }
+
+    pub fn sync_placeholder(&mut self, ch: char) {
+        // (imdaveho) NOTE: The placeholder takes up 2 cells, so it should
+        // be a wide character.
+        self.placeholder = ch;
+        for screen in self.data.iter_mut() {
+            screen.buffer.sync_placeholder(ch)
+        }
+    }
+
+    pub fn is_bidi(&self) -> bool {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].buffer.is_bidi()
+    }
+
+    pub fn sync_bidi(&mut self, state: bool) {
+        // self.set() ensures that there is a valid id
+        self.data[self.index].buffer.sync_bidi(state);
     }
 
     pub fn sync_content(&mut self, content: &str) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_content(content);
+        self.data[self.index].buffer.sync_content(content);
     }
 
     pub fn sync_style(&mut self, style: Style) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_style(style);
+        self.data[self.index].buffer.sync_style(style);
     }
 
     pub fn sync_styles(&mut self, f: Color, b: Color, fx: u32) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_styles(f, b, fx);
+        self.data[self.index].buffer.sync_styles(f, b, fx);
     }
 
     pub fn sync_clear(&mut self, clr: Clear) {
         // self.set() ensures that there is a valid id
-        self.data[self.id].buffer.sync_clear(clr);
+        self.data[self.index].buffer.sync_clear(clr);
     }
 
     pub fn render(&self, term: &Term) -> Result<()> {
-        self.data[self.id].buffer.render(term)
+        self.data[self.index].buffer.render(term)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_screen_lifecycle() {
+        let mut store = Store::new(5, 2);
+        let a = store.new_screen(5, 2);
+        let b = store.new_screen(5, 2);
+        assert_eq!(store.ids(), vec![0, a, b]);
+        assert_eq!(store.id(), b);
+        assert_eq!(store.previous(), a);
+
+        // The main and the active screens cannot be closed:
+        assert!(store.close(0).is_err());
+        assert!(store.close(b).is_err());
+
+        // Handles remain stable after closing a screen:
+        store.sync_content("b");
+        store.close(a).unwrap();
+        assert_eq!(store.ids(), vec![0, b]);
+        assert_eq!(store.getch(), " ");
+        store.sync_goto(0, 0);
+        assert_eq!(store.getch(), "b");
+        assert_eq!(store.previous(), 0);
+        assert!(!store.exists(a));
+
+        // Closed handles are not reused:
+        let c = store.new_screen(5, 2);
+        assert!(c != a && c != b);
+        assert_eq!(store.previous(), b);
+    }
+
+    #[test]
+    fn test_store_screen_stack() {
+        let mut store = Store::new(5, 2);
+        let a = store.new_screen(5, 2);
+        let b = store.new_screen(5, 2);
+        store.set(0).unwrap();
+        // Push 0 -> a -> b:
+        store.push();
+        store.set(a).unwrap();
+        store.push();
+        store.set(b).unwrap();
+        // Closing a screen removes it from the stack:
+        store.close(a).unwrap();
+        assert_eq!(store.pop(), Some(0));
+        assert_eq!(store.pop(), None);
+        // Push 0 -> a -> 0 -> b, then close a:
+        let a = store.new_screen(5, 2);
+        store.set(0).unwrap();
+        store.push();
+        store.set(a).unwrap();
+        store.push();
+        store.set(0).unwrap();
+        store.push();
+        store.set(b).unwrap();
+        store.close(a).unwrap();
+        // The duplicate 0 is gone:
+        assert_eq!(store.pop(), Some(0));
+        store.set(0).unwrap();
+        assert_eq!(store.pop(), None);
+        // The active screen is skipped:
+        store.push();
+        store.set(b).unwrap();
+        store.push();
+        assert_eq!(store.pop(), Some(0));
+    }
+
+    #[test]
+    fn test_store_screen_modes() {
+        let mut store = Store::new(5, 2);
+        let a = store.new_screen(5, 2);
+        assert!(!store.is_mouse_mode_used());
+        store.sync_mouse_mode(Some((MouseLevel::Drag, MouseEncoding::Sgr)));
+        store.push_keyboard(1);
+        store.push_keyboard(3);
+        // Each screen keeps its own mouse mode and keyboard flags:
+        store.set(0).unwrap();
+        assert_eq!(store.mouse_mode(), None);
+        assert_eq!(store.keyboard(), 0);
+        store.set(a).unwrap();
+        assert_eq!(store.mouse_mode(),
+            Some((MouseLevel::Drag, MouseEncoding::Sgr)));
+        assert_eq!(store.keyboard(), 3);
+        store.pop_keyboard();
+        assert_eq!(store.keyboard(), 1);
+        assert!(store.is_mouse_mode_used() && store.is_keyboard_used());
+
+        // Snapshots bring back the modes along with the cells:
+        let snapshot = store.snapshot(a).unwrap();
+        store.set(0).unwrap();
+        store.restore(&snapshot);
+        assert_eq!(store.mouse_mode(),
+            Some((MouseLevel::Drag, MouseEncoding::Sgr)));
+        assert_eq!(store.keyboard(), 1);
+        store.pop_keyboard();
+        assert_eq!(store.keyboard(), 0);
+    }
+
+    #[test]
+    fn test_store_placeholder() {
+        let mut store = Store::new(5, 2);
+        store.sync_placeholder('▒');
+        store.sync_content("👨‍👩‍👧");
+        store.sync_goto(0, 0);
+        assert_eq!(store.getch(), "▒");
+        // New screens use it too:
+        store.new_screen(5, 2);
+        store.sync_content("👨‍👩‍👧");
+        store.sync_goto(0, 0);
+        assert_eq!(store.getch(), "▒");
     }
 }